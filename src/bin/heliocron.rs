@@ -1,31 +1,132 @@
-use std::process;
+use std::{process, sync::Arc};
 
-use heliocron::{calc, cli, domain, errors, subcommands};
+use heliocron::{calc, cli, clock::SystemClock, domain, errors, subcommands};
 
-async fn run_heliocron() -> Result<(), errors::HeliocronError> {
-    let config = cli::parse_config()?;
-    let solar_calculations = calc::SolarCalculations::new(config.date, config.coordinates);
+/// Run the chosen subcommand, returning the exit status if successful.
+async fn run_heliocron(cli_args: cli::Cli) -> Result<i32, errors::HeliocronError> {
+    let config = cli::parse_config(cli_args, Arc::new(SystemClock))?;
+    let clock = config.clock;
+    let solar_calculations = calc::SolarCalculations::new(config.date, config.coordinates)
+        .with_time_zone(config.time_zone)
+        .with_observer_elevation(config.observer_elevation);
 
     match config.action {
-        domain::Action::Report { json } => subcommands::display_report(solar_calculations, json)?,
+        domain::Action::Report {
+            output,
+            custom_events,
+        } => subcommands::display_report(solar_calculations, &output, &custom_events)?,
+        domain::Action::RangeReport {
+            range,
+            output,
+            custom_events,
+        } => subcommands::display_range_report(solar_calculations, range, &output, &custom_events)?,
+        domain::Action::Get { query, seconds } => {
+            subcommands::get(solar_calculations, query, seconds)?
+        }
         domain::Action::Wait {
             event,
-            offset,
-            run_missed_task,
-        } => subcommands::wait(event, offset, solar_calculations, run_missed_task).await?,
-        domain::Action::Poll { watch, json } => subcommands::poll(solar_calculations, watch, json)?,
+            adjustment,
+            missed_event,
+            next,
+            command,
+            timeout,
+        } => {
+            subcommands::wait(
+                event,
+                adjustment,
+                solar_calculations,
+                missed_event,
+                next,
+                clock.as_ref(),
+            )
+            .await?;
+            if !command.is_empty() {
+                subcommands::exec(&command, timeout).await?
+            }
+        }
+        domain::Action::WaitForElevation {
+            threshold,
+            immediate,
+            command,
+            timeout,
+        } => {
+            subcommands::wait_for_elevation(
+                threshold,
+                immediate,
+                solar_calculations,
+                clock.as_ref(),
+            )
+            .await?;
+            if !command.is_empty() {
+                subcommands::exec(&command, timeout).await?
+            }
+        }
+        domain::Action::Poll { watch, output } => {
+            subcommands::poll(solar_calculations, watch, &output, clock.as_ref()).await?
+        }
+        domain::Action::Next {
+            limit,
+            output,
+            custom_events,
+        } => subcommands::next(solar_calculations, limit, &output, &custom_events)?,
+        domain::Action::Daemon { heliotab, grace } => {
+            subcommands::daemon(&heliotab, grace, solar_calculations, clock).await?
+        }
+        domain::Action::Crontab {
+            scheduled,
+            days,
+            install,
+        } => subcommands::crontab(scheduled, days, install, solar_calculations, clock.as_ref())?,
+        domain::Action::Systemd {
+            scheduled,
+            days,
+            output_dir,
+            system,
+            regenerate,
+        } => subcommands::systemd(
+            scheduled,
+            days,
+            &output_dir,
+            system,
+            &regenerate,
+            solar_calculations,
+            clock.as_ref(),
+        )?,
+        domain::Action::Ics {
+            range,
+            events,
+            polar,
+        } => subcommands::ics(range, &events, polar, solar_calculations, clock.as_ref())?,
+        domain::Action::Locations { locations, default } => {
+            subcommands::locations(&locations, default.as_deref())?
+        }
+        domain::Action::Check { condition } => {
+            // The result of the check is reported solely through the exit status.
+            if !subcommands::check(condition, solar_calculations) {
+                return Ok(1);
+            }
+        }
     }
-    Ok(())
+    Ok(0)
 }
 
 #[tokio::main]
 async fn main() {
-    // returns 0 if execution completes successfully, otherwise it prints the error and returns 1
-    process::exit(match run_heliocron().await {
-        Ok(_) => 0,
+    // returns 0 if execution completes successfully, otherwise it prints the error and returns a non-zero exit
+    // code specific to the kind of error
+    let cli_args = cli::parse_args();
+    let is_check = cli_args.is_check();
+    process::exit(match run_heliocron(cli_args).await {
+        Ok(status) => status,
         Err(err) => {
             eprintln!("{err}");
-            1
+            // like test(1), `check` exits with status 1 only if the condition doesn't hold, so any error must be
+            // told apart from that
+            if is_check {
+                2
+            } else {
+                err.exit_code()
+            }
         }
     });
 }
@@ -1,7 +1,10 @@
-use chrono::{DateTime, Duration, FixedOffset, NaiveTime, TimeZone};
+use chrono::{DateTime, Duration, FixedOffset, NaiveDate, NaiveTime, TimeZone, Timelike};
 
-use crate::domain;
 use crate::traits::{DateTimeExt, NaiveTimeExt};
+use crate::{domain, moon};
+
+/// The number of days ahead for which `SolarCalculations::next_elevation_crossing` searches before giving up.
+pub const ELEVATION_CROSSING_HORIZON: u32 = 366;
 
 /// Convert a chrono::FixedOffset into a deimal float representation.
 ///
@@ -10,10 +13,38 @@ fn offset_to_decimal_float(offset: &FixedOffset) -> f64 {
     offset.local_minus_utc() as f64 / 3600.0
 }
 
+/// The occurrence of an event on one day of a schedule, relative to the time at which the schedule is generated.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Occurrence {
+    /// The event is still to come, at the given time.
+    Due(DateTime<FixedOffset>),
+    /// The event has already passed, at the given time.
+    Passed(DateTime<FixedOffset>),
+    /// The event does not occur on this day.
+    Missing,
+}
+
+impl Occurrence {
+    /// A comment describing the occurrence on the given date, for schedules which have a line for every day but can
+    /// only run the command on those on which it is due.
+    pub fn comment(&self, date: NaiveDate) -> String {
+        match self {
+            Self::Due(datetime) => format!("# {date}: the event is due at {datetime}"),
+            Self::Passed(datetime) => {
+                format!("# {date}: the event has already passed, at {datetime}")
+            }
+            Self::Missing => format!("# {date}: the event does not occur on this day"),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct SolarCalculations {
     pub date: DateTime<FixedOffset>,
     pub coordinates: domain::Coordinates,
+    pub time_zone: domain::TimeZone,
+    /// The elevation of the observer in metres above sea level.
+    pub observer_elevation: f64,
 
     solar_declination: f64,
     solar_noon_fraction: f64,
@@ -145,6 +176,8 @@ impl SolarCalculations {
         Self {
             date,
             coordinates,
+            time_zone: domain::TimeZone::Fixed(*date.offset()),
+            observer_elevation: 0.0,
             solar_declination,
             solar_noon_fraction,
             corrected_solar_elevation_angle,
@@ -152,8 +185,76 @@ impl SolarCalculations {
         }
     }
 
+    /// Set the time zone in which event times are displayed. By default, this is the fixed offset of `date`.
+    pub fn with_time_zone(mut self, time_zone: domain::TimeZone) -> Self {
+        self.time_zone = time_zone;
+        self
+    }
+
+    /// Set the elevation of the observer in metres above sea level, from where the horizon appears lower, so that
+    /// sunrise is earlier and sunset is later. By default, the observer is at sea level.
+    pub fn with_observer_elevation(mut self, observer_elevation: f64) -> Self {
+        self.observer_elevation = observer_elevation;
+        self
+    }
+
     pub fn refresh(&self, date: DateTime<FixedOffset>) -> Self {
         Self::new(date, self.coordinates.clone())
+            .with_time_zone(self.time_zone)
+            .with_observer_elevation(self.observer_elevation)
+    }
+
+    /// Run the calculations for another date at the same location. As with the command line, the calculations are
+    /// based on midday in the chosen time zone.
+    pub fn for_date(&self, date: NaiveDate) -> Self {
+        self.refresh(self.time_zone.from_local_datetime(&date.and_hms(12, 0, 0)))
+    }
+
+    /// Returns the time of the event (+/- offset), once clamped, on each of the given number of days, starting from the
+    /// date of the calculations. Any jitter is ignored, as it is only chosen when waiting.
+    pub fn occurrences(
+        &self,
+        event: &domain::Event,
+        adjustment: &domain::EventAdjustment,
+        days: u32,
+    ) -> Vec<(NaiveDate, Option<DateTime<FixedOffset>>)> {
+        let start_date = self.date.date().naive_local();
+        (0..days)
+            .map(|days| start_date + Duration::days(days.into()))
+            .map(|date| {
+                let event_time = self.for_date(date).event_time(event.clone());
+                let datetime = event_time.0.map(|datetime| {
+                    adjustment
+                        .clamp
+                        .apply(datetime + adjustment.offset, self.time_zone)
+                });
+                (date, datetime)
+            })
+            .collect()
+    }
+
+    /// As `occurrences`, but classifying each day's occurrence against `now`. Each time is first passed through
+    /// `schedule`, which gives the time at which the scheduler will actually run the command, e.g. rounded to the
+    /// nearest minute for cron, so that a command which would only run after `now` is counted as due.
+    pub fn scheduled_occurrences(
+        &self,
+        event: &domain::Event,
+        adjustment: &domain::EventAdjustment,
+        days: u32,
+        now: DateTime<FixedOffset>,
+        schedule: impl Fn(DateTime<FixedOffset>) -> DateTime<FixedOffset>,
+    ) -> Vec<(NaiveDate, Occurrence)> {
+        self.occurrences(event, adjustment, days)
+            .into_iter()
+            .map(|(date, datetime)| {
+                let occurrence = match datetime.map(&schedule) {
+                    Some(datetime) if datetime <= now => Occurrence::Passed(datetime),
+                    Some(datetime) => Occurrence::Due(datetime),
+                    None => Occurrence::Missing,
+                };
+                (date, occurrence)
+            })
+            .collect()
     }
 
     pub fn solar_elevation(&self) -> f64 {
@@ -169,15 +270,17 @@ impl SolarCalculations {
         domain::EventTime::new(Some(solar_noon))
     }
 
-    fn day_fraction_to_datetime(&self, mut day_fraction: f64) -> DateTime<FixedOffset> {
-        let mut date = self.date.naive_local();
-        if day_fraction < 0.0 {
-            date -= Duration::days(1);
-            day_fraction = day_fraction.abs();
-        } else if day_fraction >= 1.0 {
-            date += Duration::days(1);
-            day_fraction -= 1.0;
-        }
+    /// Returns the time of the solar midnight (nadir) following the solar noon on `date`.
+    pub fn solar_midnight(&self) -> domain::EventTime {
+        let solar_midnight = self.day_fraction_to_datetime(self.solar_noon_fraction + 0.5);
+        domain::EventTime::new(Some(solar_midnight))
+    }
+
+    fn day_fraction_to_datetime(&self, day_fraction: f64) -> DateTime<FixedOffset> {
+        // A fraction outside of 0..1 falls on an earlier or later day, e.g. -0.25 is 18:00 on the previous day.
+        let days = day_fraction.floor();
+        let date = self.date.naive_local() + Duration::days(days as i64);
+        let day_fraction = day_fraction - days;
 
         let hour_fraction = day_fraction * 24.0;
         let minute_fraction = hour_fraction.fract() * 60.0;
@@ -190,15 +293,20 @@ impl SolarCalculations {
         );
 
         // Safe to unwrap because we got this date from naive_local() just earlier
-        self.date
+        let datetime = self
+            .date
             .offset()
             .from_local_date(&date.date())
             .and_time(time)
-            .unwrap()
+            .unwrap();
+
+        // The calculations are all relative to the offset in force at `date`, which isn't necessarily the offset in
+        // force at the time of the event, e.g. if the clocks change in the early hours of the morning.
+        self.time_zone.from_utc_datetime(&datetime.naive_utc())
     }
 
-    fn hour_angle(&self, degrees_below_horizon: domain::Altitude) -> Option<f64> {
-        let event_angle = *degrees_below_horizon + 90.0;
+    fn hour_angle(&self, degrees_below_horizon: f64) -> Option<f64> {
+        let event_angle = degrees_below_horizon + 90.0;
         let hour_angle = (((event_angle.to_radians().cos()
             / (self.coordinates.latitude.to_radians().cos()
                 * self.solar_declination.to_radians().cos()))
@@ -213,10 +321,32 @@ impl SolarCalculations {
         }
     }
 
+    /// Returns how many degrees the visible horizon lies below the geometric horizon, due to the elevation of the
+    /// observer.
+    fn horizon_dip(&self) -> f64 {
+        2.076 * self.observer_elevation.sqrt() / 60.0
+    }
+
+    /// Returns how many degrees below the geometric horizon the centre of the Sun is when the event occurs.
+    fn degrees_below_horizon(&self, event: &domain::FixedElevationEvent) -> f64 {
+        match event.visible_horizon {
+            true => *event.degrees_below_horizon + self.horizon_dip(),
+            false => *event.degrees_below_horizon,
+        }
+    }
+
+    /// Returns the elevation of the centre of the Sun at sunrise and sunset, allowing for the elevation of the
+    /// observer.
+    pub fn sunrise_elevation(&self) -> f64 {
+        -self.degrees_below_horizon(&domain::FixedElevationEvent::at_visible_horizon(
+            domain::Direction::Ascending,
+        ))
+    }
+
     pub fn event_time(&self, event: domain::Event) -> domain::EventTime {
         match event {
             domain::Event::Fixed(event) => {
-                let hour_angle = self.hour_angle(event.degrees_below_horizon);
+                let hour_angle = self.hour_angle(self.degrees_below_horizon(&event));
 
                 match hour_angle {
                     Some(hour_angle) => {
@@ -238,8 +368,157 @@ impl SolarCalculations {
             }
             domain::Event::Variable(event) => match event {
                 domain::VariableElevationEvent::SolarNoon => self.solar_noon(),
+                domain::VariableElevationEvent::SolarMidnight => self.solar_midnight(),
             },
+            // Lunar events are delegated to the lunar calculations for the same date and location.
+            domain::Event::Lunar(event) => self.lunar_calculations().event_time(event),
+        }
+    }
+
+    /// Returns the solar elevation at the given instant, at the same location.
+    pub fn solar_elevation_at(&self, datetime: DateTime<FixedOffset>) -> f64 {
+        self.refresh(datetime).solar_elevation()
+    }
+
+    /// Find the first instant from `from` onwards at which the Sun crosses the elevation threshold, to the nearest
+    /// second. If `immediate` is true and the threshold is already met at `from`, then `from` itself is returned.
+    ///
+    /// The solar elevation is sampled every few minutes and then refined by bisection, giving up after
+    /// `ELEVATION_CROSSING_HORIZON` days if the threshold is never crossed.
+    pub fn next_elevation_crossing(
+        &self,
+        threshold: &domain::ElevationThreshold,
+        from: DateTime<FixedOffset>,
+        immediate: bool,
+    ) -> Option<DateTime<FixedOffset>> {
+        self.next_elevation_crossing_within(
+            threshold,
+            from,
+            immediate,
+            Duration::days(ELEVATION_CROSSING_HORIZON.into()),
+        )
+    }
+
+    /// As `next_elevation_crossing`, but giving up after `period` rather than searching for up to a year, for when the
+    /// crossing is only of interest if it is soon.
+    pub fn next_elevation_crossing_within(
+        &self,
+        threshold: &domain::ElevationThreshold,
+        from: DateTime<FixedOffset>,
+        immediate: bool,
+        period: Duration,
+    ) -> Option<DateTime<FixedOffset>> {
+        let step = Duration::minutes(5);
+        let horizon = from + period;
+
+        let elevation = self.solar_elevation_at(from);
+        if immediate && threshold.is_met(elevation) {
+            return Some(from);
+        }
+
+        let mut armed = threshold.is_armed(elevation);
+        let mut before = from;
+        let mut turning_point = self.next_turning_point(from);
+        while before < horizon {
+            // The elevation only changes direction at solar noon and solar midnight, so a step is cut short at either
+            // of them. Otherwise, a peak or trough beyond the threshold could fall between two samples and be missed.
+            let after = match before + step {
+                after if after < turning_point => after,
+                _ => {
+                    let after = turning_point;
+                    turning_point = self.next_turning_point(after);
+                    after
+                }
+            };
+            let elevation = self.solar_elevation_at(after);
+
+            if armed && threshold.is_met(elevation) {
+                // The threshold is not met at `before`, but is at `after`, so the crossing is somewhere in between.
+                // Bisect over whole seconds to find the first second at which the threshold is met.
+                let mut before = before.with_nanosecond(0).unwrap();
+                let mut after = match after.nanosecond() {
+                    0 => after,
+                    _ => after.with_nanosecond(0).unwrap() + Duration::seconds(1),
+                };
+                while after - before > Duration::seconds(1) {
+                    let midpoint = before + Duration::seconds((after - before).num_seconds() / 2);
+                    if threshold.is_met(self.solar_elevation_at(midpoint)) {
+                        after = midpoint;
+                    } else {
+                        before = midpoint;
+                    }
+                }
+                let crossing = after;
+                return Some(self.time_zone.from_utc_datetime(&crossing.naive_utc()));
+            }
+
+            armed = armed || threshold.is_armed(elevation);
+            before = after;
         }
+        None
+    }
+
+    /// Returns the first solar noon or solar midnight after `at`.
+    fn next_turning_point(&self, at: DateTime<FixedOffset>) -> DateTime<FixedOffset> {
+        let date = self
+            .time_zone
+            .from_utc_datetime(&at.naive_utc())
+            .date()
+            .naive_local();
+        // Safe to unwrap because there is a solar noon on every day, including the day after `at`
+        (-1..=1)
+            .map(|days| self.for_date(date + Duration::days(days)))
+            .flat_map(|calcs| [calcs.solar_noon().0, calcs.solar_midnight().0])
+            .flatten()
+            .filter(|turning_point| *turning_point > at)
+            .min()
+            .unwrap()
+    }
+
+    /// Returns whether `at` falls within the window which opens at the `from` event (+ `from_offset`) and closes at
+    /// the next occurrence of the `to` event (+ `to_offset`) thereafter. The window may span midnight, e.g. from
+    /// sunset until sunrise, and is considered closed if either event does not occur.
+    pub fn is_between(
+        &self,
+        (from, from_offset): (&domain::Event, Duration),
+        (to, to_offset): (&domain::Event, Duration),
+        at: DateTime<FixedOffset>,
+    ) -> bool {
+        let occurrences = |event: &domain::Event, offset: Duration, date: NaiveDate| {
+            // Offsets of up to a day either way mean that the event could belong to an adjacent date.
+            (-1..=2)
+                .filter_map(|days| {
+                    self.for_date(date + Duration::days(days))
+                        .event_time(event.clone())
+                        .0
+                        .map(|event_time| event_time + offset)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let date = self
+            .time_zone
+            .from_utc_datetime(&at.naive_utc())
+            .date()
+            .naive_local();
+        let opened_at = occurrences(from, from_offset, date - Duration::days(1))
+            .into_iter()
+            .filter(|opened_at| *opened_at <= at)
+            .max();
+
+        match opened_at {
+            Some(opened_at) => occurrences(to, to_offset, opened_at.date().naive_local())
+                .into_iter()
+                .filter(|closes_at| *closes_at > opened_at)
+                .min()
+                .is_some_and(|closes_at| at < closes_at),
+            None => false,
+        }
+    }
+
+    pub fn lunar_calculations(&self) -> moon::LunarCalculations {
+        moon::LunarCalculations::new(self.date, self.coordinates.clone())
+            .with_time_zone(self.time_zone)
     }
 
     pub fn day_length(&self) -> Duration {
@@ -268,6 +547,13 @@ impl SolarCalculations {
         let date = self.solar_noon().0.unwrap();
         SolarCalculations::new(date, self.coordinates.clone()).corrected_solar_elevation_angle
     }
+
+    /// Returns the solar elevation angle at solar midnight, corrected for atmospheric refraction.
+    pub fn min_solar_elevation(&self) -> f64 {
+        // Safe to unwrap as there is always a solar midnight.
+        let date = self.solar_midnight().0.unwrap();
+        SolarCalculations::new(date, self.coordinates.clone()).corrected_solar_elevation_angle
+    }
 }
 
 #[cfg(test)]
@@ -338,6 +624,209 @@ mod tests {
         assert_eq!(calcs.solar_noon_fraction, 0.4977758080863915);
     }
 
+    #[test]
+    fn test_solar_midnight() {
+        // solar midnight is twelve hours after solar noon, when the Sun is at its lowest point
+        let date = DateTime::parse_from_rfc3339("2022-06-11T12:00:00+02:00").unwrap();
+        let coordinates = Coordinates {
+            latitude: Latitude::new(71.0).unwrap(),
+            longitude: Longitude::new(25.0).unwrap(),
+        };
+        let calcs = SolarCalculations::new(date, coordinates);
+
+        assert_eq!(
+            "2022-06-12 00:19:39 +02:00",
+            calcs.solar_midnight().to_string()
+        );
+        // this far north, the Sun never sets in June
+        assert!(calcs.min_solar_elevation() > 0.0);
+        assert!(calcs.min_solar_elevation() < calcs.max_solar_elevation());
+    }
+
+    #[test]
+    fn test_next_elevation_crossing() {
+        let date = DateTime::parse_from_rfc3339("2022-06-11T12:00:00+00:00").unwrap();
+        let coordinates = Coordinates {
+            latitude: Latitude::new(51.4769).unwrap(),
+            longitude: Longitude::new(-0.0005).unwrap(),
+        };
+        let calcs = SolarCalculations::new(date, coordinates);
+
+        let threshold = |elevation, direction, hysteresis| domain::ElevationThreshold {
+            elevation,
+            direction,
+            hysteresis,
+        };
+
+        // the crossing is found to the nearest second
+        let below = threshold(-3.5, domain::Direction::Descending, 0.0);
+        let crossing = calcs.next_elevation_crossing(&below, date, false).unwrap();
+        assert_eq!(crossing.to_string(), "2022-06-11 20:40:40 +00:00");
+        assert!(below.is_met(calcs.solar_elevation_at(crossing)));
+        assert!(!below.is_met(calcs.solar_elevation_at(crossing - Duration::seconds(1))));
+
+        // the search continues across midnight
+        let above = threshold(-10.0, domain::Direction::Ascending, 0.0);
+        let crossing = calcs.next_elevation_crossing(&above, date, false).unwrap();
+        assert_eq!(crossing.to_string(), "2022-06-12 02:10:49 +00:00");
+
+        // the threshold is already met at midday, so either return immediately or wait until tomorrow
+        let above = threshold(10.0, domain::Direction::Ascending, 0.0);
+        let crossing = calcs.next_elevation_crossing(&above, date, true).unwrap();
+        assert_eq!(crossing, date);
+        let crossing = calcs.next_elevation_crossing(&above, date, false).unwrap();
+        assert_eq!(crossing.to_string(), "2022-06-12 05:05:07 +00:00");
+
+        // the Sun must first have been above 30 degrees for the crossing to count, which it already is at midday...
+        let below = threshold(20.0, domain::Direction::Descending, 10.0);
+        let crossing = calcs.next_elevation_crossing(&below, date, false).unwrap();
+        assert_eq!(crossing.to_string(), "2022-06-11 17:46:04 +00:00");
+        // ...but it never gets as high as 65 degrees at this latitude, so the threshold is never armed
+        let below = threshold(20.0, domain::Direction::Descending, 45.0);
+        assert!(calcs.next_elevation_crossing(&below, date, false).is_none());
+    }
+
+    #[test]
+    fn test_is_between() {
+        let date = DateTime::parse_from_rfc3339("2022-06-11T12:00:00+00:00").unwrap();
+        let coordinates = Coordinates {
+            latitude: Latitude::new(51.4769).unwrap(),
+            longitude: Longitude::new(-0.0005).unwrap(),
+        };
+        let calcs = SolarCalculations::new(date, coordinates);
+
+        let sunrise = domain::Event::from_event_name(domain::EventName::Sunrise);
+        let sunset = domain::Event::from_event_name(domain::EventName::Sunset);
+        let at = |datetime| DateTime::parse_from_rfc3339(datetime).unwrap();
+
+        // sunset is at 20:16:13 and sunrise the following morning is at 03:42:51
+        let day = |datetime| {
+            calcs.is_between(
+                (&sunrise, Duration::zero()),
+                (&sunset, Duration::zero()),
+                at(datetime),
+            )
+        };
+        assert!(day("2022-06-11T12:00:00+00:00"));
+        assert!(day("2022-06-11T20:16:12+00:00"));
+        assert!(!day("2022-06-11T20:16:13+00:00"));
+        assert!(!day("2022-06-12T01:00:00+00:00"));
+
+        // the window spans midnight
+        let night = |datetime| {
+            calcs.is_between(
+                (&sunset, Duration::zero()),
+                (&sunrise, Duration::zero()),
+                at(datetime),
+            )
+        };
+        assert!(!night("2022-06-11T12:00:00+00:00"));
+        assert!(night("2022-06-11T20:16:13+00:00"));
+        assert!(night("2022-06-12T01:00:00+00:00"));
+        assert!(night("2022-06-12T03:42:50+00:00"));
+        assert!(!night("2022-06-12T03:42:51+00:00"));
+
+        // offsets can move the window either side of the events
+        let evening = |datetime| {
+            calcs.is_between(
+                (&sunset, Duration::hours(-1)),
+                (&sunset, Duration::hours(1)),
+                at(datetime),
+            )
+        };
+        assert!(!evening("2022-06-11T19:16:12+00:00"));
+        assert!(evening("2022-06-11T19:16:13+00:00"));
+        assert!(evening("2022-06-11T21:16:12+00:00"));
+        assert!(!evening("2022-06-11T21:16:13+00:00"));
+
+        // astronomical dusk doesn't occur at this time of year, so the window never opens
+        let astronomical_dusk = domain::Event::from_event_name(domain::EventName::AstronomicalDusk);
+        assert!(!calcs.is_between(
+            (&astronomical_dusk, Duration::zero()),
+            (&sunrise, Duration::zero()),
+            at("2022-06-12T01:00:00+00:00"),
+        ));
+    }
+
+    #[test]
+    fn test_next_elevation_crossing_never() {
+        // the Sun never gets this high this close to the pole
+        let date = DateTime::parse_from_rfc3339("2022-06-11T12:00:00+00:00").unwrap();
+        let coordinates = Coordinates {
+            latitude: Latitude::new(89.0).unwrap(),
+            longitude: Longitude::new(0.0).unwrap(),
+        };
+        let calcs = SolarCalculations::new(date, coordinates);
+
+        let above = domain::ElevationThreshold {
+            elevation: 60.0,
+            direction: domain::Direction::Ascending,
+            hysteresis: 0.0,
+        };
+        assert!(calcs.next_elevation_crossing(&above, date, true).is_none());
+    }
+
+    #[test]
+    fn test_next_elevation_crossing_within() {
+        let date = DateTime::parse_from_rfc3339("2022-06-11T12:00:00+01:00").unwrap();
+        let coordinates = Coordinates {
+            latitude: Latitude::new(51.4769).unwrap(),
+            longitude: Longitude::new(-0.0005).unwrap(),
+        };
+        let calcs = SolarCalculations::new(date, coordinates);
+
+        // the Sun doesn't set until the evening, so there is no crossing within the next few hours
+        let below = domain::ElevationThreshold {
+            elevation: 0.0,
+            direction: domain::Direction::Descending,
+            hysteresis: 0.0,
+        };
+        assert!(calcs
+            .next_elevation_crossing_within(&below, date, false, Duration::hours(6))
+            .is_none());
+        assert_eq!(
+            calcs.next_elevation_crossing_within(&below, date, false, Duration::hours(12)),
+            calcs.next_elevation_crossing(&below, date, false)
+        );
+    }
+
+    #[test]
+    fn test_next_elevation_crossing_near_turning_point() {
+        let date = DateTime::parse_from_rfc3339("2022-06-11T12:00:00+01:00").unwrap();
+        let coordinates = Coordinates {
+            latitude: Latitude::new(51.4769).unwrap(),
+            longitude: Longitude::new(-0.0005).unwrap(),
+        };
+        let calcs = SolarCalculations::new(date, coordinates);
+        let starts = [
+            DateTime::parse_from_rfc3339("2022-06-11T06:00:00+01:00").unwrap(),
+            DateTime::parse_from_rfc3339("2022-06-11T06:02:30+01:00").unwrap(),
+        ];
+
+        // the Sun peaks at 61.6336 degrees, only just above the threshold, so it is met for a few minutes either side
+        // of solar noon, wherever the samples happen to fall
+        let above = domain::ElevationThreshold {
+            elevation: 61.631,
+            direction: domain::Direction::Ascending,
+            hysteresis: 0.0,
+        };
+        for from in starts {
+            let crossing = calcs.next_elevation_crossing(&above, from, false).unwrap();
+            assert_eq!(crossing.to_string(), "2022-06-11 12:57:43 +01:00");
+        }
+
+        // likewise, the threshold is only armed for a few minutes either side of solar noon
+        let below = domain::ElevationThreshold {
+            elevation: 50.0,
+            direction: domain::Direction::Descending,
+            hysteresis: 11.631,
+        };
+        for from in starts {
+            let crossing = calcs.next_elevation_crossing(&below, from, false).unwrap();
+            assert_eq!(crossing.to_string(), "2022-06-11 15:26:49 +01:00");
+        }
+    }
+
     #[test]
     fn test_day_fraction_to_time_underoverflow() {
         // when a location is selected which is in a different time zone, it is possible for the sunrise/sunset to
@@ -353,6 +842,8 @@ mod tests {
         let params = [
             ("2020-03-26 12:00:00 +00:00", 1.5),
             ("2020-03-24 12:00:00 +00:00", -0.5),
+            ("2020-03-24 18:00:00 +00:00", -0.25),
+            ("2020-03-27 06:00:00 +00:00", 2.25),
         ];
 
         for (expected_time, arg) in params.iter() {
@@ -433,4 +924,65 @@ mod tests {
 
         assert_eq!(day_length, expected);
     }
+
+    #[test]
+    fn test_event_time_uses_offset_in_force() {
+        // the clocks go forward at 01:00 UTC on this date, but this event occurs just before that; it should be
+        // displayed with the offset in force at the time of the event, not the offset in force at noon
+        let time_zone = domain::TimeZone::parse("Europe/London").unwrap();
+        let date = time_zone
+            .from_local_datetime(&chrono::NaiveDate::from_ymd(2022, 3, 27).and_hms(12, 0, 0));
+        let coordinates = Coordinates {
+            latitude: Latitude::new(51.4769).unwrap(),
+            longitude: Longitude::new(-0.0005).unwrap(),
+        };
+
+        let solar_calculations =
+            SolarCalculations::new(date, coordinates).with_time_zone(time_zone);
+
+        let event = domain::Event::from_event_name(domain::EventName::CustomAM(35.0.into()));
+        assert_eq!(
+            "2022-03-27 00:50:22 +00:00",
+            solar_calculations.event_time(event).to_string()
+        );
+
+        let event = domain::Event::from_event_name(domain::EventName::Sunrise);
+        assert_eq!(
+            "2022-03-27 06:46:29 +01:00",
+            solar_calculations.event_time(event).to_string()
+        );
+    }
+
+    #[test]
+    fn test_observer_elevation() {
+        // from higher up, the Sun is visible for longer, but the twilights are defined by the geometric horizon
+        let date = FixedOffset::east(0).ymd(2022, 6, 11).and_hms(12, 0, 0);
+        let coordinates = Coordinates {
+            latitude: Latitude::new(51.4769).unwrap(),
+            longitude: Longitude::new(-0.0005).unwrap(),
+        };
+
+        let sea_level = SolarCalculations::new(date, coordinates);
+        let elevated = sea_level.clone().with_observer_elevation(1000.0);
+
+        for (event, direction) in [
+            (domain::EventName::Sunrise, -1),
+            (domain::EventName::Sunset, 1),
+        ] {
+            let event = domain::Event::from_event_name(event);
+            let difference = elevated.event_time(event.clone()).0.unwrap()
+                - sea_level.event_time(event).0.unwrap();
+            assert_eq!(difference.num_minutes().signum(), direction);
+            assert!(difference.num_minutes().abs() > 5);
+        }
+
+        assert_eq!(sea_level.sunrise_elevation(), -0.833);
+        assert!(elevated.sunrise_elevation() < -1.9);
+
+        let event = domain::Event::from_event_name(domain::EventName::CivilDawn);
+        assert_eq!(
+            elevated.event_time(event.clone()).0,
+            sea_level.event_time(event).0
+        );
+    }
 }
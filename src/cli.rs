@@ -1,29 +1,39 @@
-use std::{fs, path::PathBuf, result};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    result,
+    sync::Arc,
+};
 
-use chrono::{DateTime, Datelike, Duration, FixedOffset, Local, NaiveDate, NaiveTime, TimeZone};
-use clap::{Parser, Subcommand};
+use chrono::{
+    DateTime, Duration, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, TimeZone as _, Utc,
+};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use serde::Deserialize;
 
-use super::{domain, errors::HeliocronError};
+use super::{
+    clock::{Clock, SimulatedClock},
+    domain,
+    errors::{ConfigErrorKind, HeliocronError},
+    report, systemd, template, upcoming,
+};
 
 type Result<T, E = HeliocronError> = result::Result<T, E>;
 
+/// The command line arguments, as parsed by [`parse_args`].
 #[derive(Parser)]
 #[clap(version, about)]
-struct Cli {
+pub struct Cli {
     /// Set the date for which the calculations should be run. If specified, it should be in 'yyyy-mm-dd' format, otherwise it defaults
-    /// to the the current local date
-    #[clap(
-        short = 'd',
-        long = "date",
-        value_parser=parse_date,
-        default_value_t=Local::today().naive_local()
-    )]
-    date: NaiveDate,
+    /// to the the current date in the chosen time zone
+    #[clap(short = 'd', long = "date", value_parser=parse_date)]
+    date: Option<NaiveDate>,
 
-    /// Set the time zone. If specified, it should be in the format '[+/-]HH:MM', otherwise it defaults to the current local time zone
-    #[clap(short = 't', long = "time-zone", allow_hyphen_values = true, value_parser=parse_tz, default_value_t=*Local::today().offset())]
-    time_zone: FixedOffset,
+    /// Set the time zone. If specified, it should either be an IANA time zone name, such as 'Europe/London', or a fixed offset in the
+    /// format '[+/-]HH:MM'. Defaults to the value in ~/.config/heliocron.toml, or otherwise the local time zone
+    #[clap(short = 't', long = "time-zone", allow_hyphen_values = true, value_parser = domain::TimeZone::parse)]
+    time_zone: Option<domain::TimeZone>,
 
     /// Set the latitude in decimal degrees. Positive values to the north; negative values to the south. Defaults to '51.4769' if not
     /// otherwise specified here or in ~/.config/heliocron.toml.
@@ -35,27 +45,78 @@ struct Cli {
     #[clap(short = 'o', long = "longitude", requires = "latitude", allow_hyphen_values = true, value_parser = domain::Longitude::parse)]
     longitude: Option<domain::Longitude>,
 
+    /// Use the coordinates, time zone and elevation of one of the named locations in ~/.config/heliocron.toml. Defaults to the location named
+    /// by the 'default' key in that file, if any
+    #[clap(long = "location", conflicts_with_all = &["latitude", "longitude"])]
+    location: Option<String>,
+
+    /// Pretend that the current time is the given date and time, in the format 'yyyy-mm-ddTHH:MM[:SS]', optionally followed by a UTC offset
+    /// which is then used as the local time zone instead of UTC. Waits return immediately, having moved the simulated time forward. Intended for testing
+    #[clap(long = "now", hide = true, value_parser = parse_datetime)]
+    now: Option<DateTimeArg>,
+
+    /// Stop the simulated time from passing the given date and time, in the same format as --now, so that anything which would wait beyond it
+    /// waits forever instead. This brings the daemon to rest, rather than it running through the days as fast as it can. Intended for testing
+    #[clap(long = "until", hide = true, requires = "now", value_parser = parse_datetime)]
+    until: Option<DateTimeArg>,
+
     #[clap(subcommand)]
     subcommand: Command,
 }
 
 #[derive(Subcommand)]
 pub enum Command {
-    /// Produce a full set of sunrise, sunset and other related times for the given date and location
+    /// Produce a full set of sunrise, sunset and other related times for the given date and location, or with --from, --to or --days, a
+    /// row of those times for each day in a range of dates
+    #[clap(group(clap::ArgGroup::new("range").multiple(true)))]
     Report {
-        /// Set the output format to machine-readable JSON. If this flag is not present, the report will be displayed in the terminal as a block of human-readable text
-        #[clap(long = "json")]
-        json: bool,
+        #[clap(flatten)]
+        format: FormatArgs,
+
+        /// Set the first date of the range in 'yyyy-mm-dd' format. Defaults to the chosen date
+        #[clap(long = "from", value_parser = parse_date, group = "range")]
+        from: Option<NaiveDate>,
+
+        /// Set the last date of the range in 'yyyy-mm-dd' format
+        #[clap(long = "to", value_parser = parse_date, group = "range", conflicts_with = "days")]
+        to: Option<NaiveDate>,
+
+        /// Set the number of days in the range. Defaults to 1 if neither this nor --to is given
+        #[clap(long = "days", value_parser = clap::value_parser!(u32).range(1..=domain::DateRange::MAX_DAYS), group = "range")]
+        days: Option<u32>,
+    },
+
+    /// Print a single value for the chosen date: the time of an event (+/- offset) or the day length. If the event doesn't occur on that
+    /// day, nothing is printed and heliocron exits with a distinct status
+    Get {
+        /// Choose the value: either 'day_length', or an event, as with 'wait --event'
+        #[clap(value_parser = parse_get_arg)]
+        event_name: GetArg,
+
+        #[clap(flatten)]
+        offset: OffsetArgs,
+
+        /// Print the time as a Unix timestamp, or the day length as a number of seconds, rather than as rfc3339 or HH:MM:SS respectively
+        #[clap(long = "unix", visible_alias = "seconds")]
+        seconds: bool,
     },
 
     /// Set a delay timer which will expire when the chosen event (+/- optional offset) occurs
     Wait {
-        /// Choose an event from which to base the delay
-        #[clap(short = 'e', long = "event", value_enum)]
-        event_name: domain::RawEventName,
+        /// Choose an event from which to base the delay. This is either one of the built-in events, such as 'sunset' or 'civil_dawn', or the name of
+        /// an event defined in the [events] section of ~/.config/heliocron.toml
+        #[clap(
+            short = 'e',
+            long = "event",
+            value_parser = parse_event_arg,
+            required_unless_present = "elevation",
+            conflicts_with = "elevation"
+        )]
+        event_name: Option<EventArg>,
 
         /// Choose a delay from your chosen event (see --event) in one of the following formats: {'HH:MM:SS' | 'HH:MM'}. The value may be prepended with '-' to make it negative.
-        /// A negative offset will set the delay to be before the event, whilst a positive offset will set the delay to be after the event
+        /// A negative offset will set the delay to be before the event, whilst a positive offset will set the delay to be after the event. The offset of an
+        /// event defined in ~/.config/heliocron.toml is added to this one
         #[clap(
             short = 'o',
             long = "offset",
@@ -76,14 +137,85 @@ pub enum Command {
         )]
         custom_altitude: Option<domain::Altitude>,
 
+        #[clap(flatten)]
+        adjustment: Box<AdjustmentArgs>,
+
         /// Add a short description to help identify the process e.g. when using htop. This parameter has no other effect on the running of the program
         #[clap(long = "tag")]
         tag: Option<String>,
 
-        /// Define whether the task should still be run even if the event has been missed. A tolerance of 30 seconds after the event is allowed before a task
-        /// would be skipped. Setting this flag will cause the task to run regardless of how overdue it is
-        #[clap(long = "run-missed-event")]
+        /// Define whether the task should still be run even if the event has been missed. Setting this flag will cause the task to run regardless of how
+        /// overdue it is, and is equivalent to '--missed-event always'
+        #[clap(long = "run-missed-event", conflicts_with = "missed-event")]
         run_missed_task: bool,
+
+        /// Choose what to do if the wait ends after the event (+/- offset), e.g. because the device was suspended: 'skip' the task if it is late at all,
+        /// run it only if it is no later than the grace period, or 'always' run it
+        #[clap(
+            long = "missed-event",
+            value_enum,
+            default_value = "run-if-within-grace"
+        )]
+        missed_event: domain::MissedEventPolicy,
+
+        /// Set how late the wait may end, in the format {'HH:MM:SS' | 'HH:MM'}, before the event is considered to have been missed. Defaults to the value
+        /// in ~/.config/heliocron.toml, or otherwise 30 seconds
+        #[clap(long = "grace", value_parser = parse_grace)]
+        grace: Option<Duration>,
+
+        /// Append a JSON record of how late the wait ended, and whether the task was run as a result, to this file
+        #[clap(long = "audit-log", value_name = "PATH")]
+        audit_log: Option<PathBuf>,
+
+        /// If the event (+/- offset) has already passed, or does not occur, on the chosen date, then wait for its next occurrence on a later date
+        /// instead of failing. The chosen date is printed
+        #[clap(long = "next")]
+        next: bool,
+
+        /// Set the maximum number of days to search ahead for the next occurrence of the event when using --next
+        #[clap(long = "horizon", default_value = "366", requires = "next")]
+        horizon: u32,
+
+        /// Instead of waiting for an event, wait until the centre of the Sun falls below this elevation, in degrees above the horizon. Negative values
+        /// are below the horizon. Can't be combined with --offset, --next or --horizon
+        #[clap(
+            long = "elevation-below",
+            group = "elevation",
+            allow_hyphen_values = true,
+            value_parser = parse_elevation,
+            conflicts_with_all = &["offset", "next", "horizon"]
+        )]
+        elevation_below: Option<f64>,
+
+        /// Instead of waiting for an event, wait until the centre of the Sun rises above this elevation, in degrees above the horizon. Negative values
+        /// are below the horizon. Can't be combined with --offset, --next or --horizon
+        #[clap(
+            long = "elevation-above",
+            group = "elevation",
+            allow_hyphen_values = true,
+            value_parser = parse_elevation,
+            conflicts_with_all = &["offset", "next", "horizon"]
+        )]
+        elevation_above: Option<f64>,
+
+        /// Only count a crossing of --elevation-below or --elevation-above once the Sun has first been at least this many degrees on the other side
+        /// of the threshold
+        #[clap(long = "hysteresis", default_value = "0", value_parser = parse_hysteresis, requires = "elevation")]
+        hysteresis: f64,
+
+        /// Return immediately if the Sun is already beyond the --elevation-below or --elevation-above threshold, rather than waiting for it to next
+        /// cross the threshold
+        #[clap(long = "immediate", requires = "elevation")]
+        immediate: bool,
+
+        /// Set the maximum time for which the command may run, in the format {'HH:MM:SS' | 'HH:MM'}. If the command is still running after this time, it is terminated
+        #[clap(long = "timeout", value_parser = parse_timeout, requires = "command")]
+        timeout: Option<Duration>,
+
+        /// Optionally, the command to run once the event occurs, given after '--'. Signals received by heliocron are forwarded to the command, and
+        /// heliocron exits with a distinct status if the command fails
+        #[clap(last = true, value_name = "COMMAND")]
+        command: Vec<String>,
     },
 
     /// Display real time data pertaining to the Sun at the current local time
@@ -92,13 +224,222 @@ pub enum Command {
         #[clap(long = "watch")]
         watch: bool,
 
-        /// Set the output format to machine-readable JSON. If this flag is not present, the report will be displayed in the terminal as a block of human-readable text
-        #[clap(long = "json")]
-        json: bool,
+        #[clap(flatten)]
+        format: FormatArgs,
+    },
+
+    /// List the upcoming events in chronological order from now, with the time remaining until each one. The events defined in
+    /// ~/.config/heliocron.toml are included
+    Next {
+        /// Set the number of events to list. Defaults to 10 unless --within is given
+        #[clap(short = 'n', long = "count", value_parser = clap::value_parser!(u32).range(1..=1000), conflicts_with = "within")]
+        count: Option<u32>,
+
+        /// List every event within this period from now, such as '48h', '7d' or '1h30m'
+        #[clap(long = "within", value_parser = parse_period)]
+        within: Option<Duration>,
+
+        #[clap(flatten)]
+        format: FormatArgs,
+    },
+
+    /// Run the commands in a heliotab file as their events occur, every day, until cancelled. Send SIGHUP to reload the file
+    Daemon {
+        /// Set the path to the heliotab file. Defaults to ~/.config/heliotab
+        #[clap(short = 'f', long = "heliotab")]
+        heliotab: Option<PathBuf>,
+    },
+
+    /// Print a crontab entry for each of the coming days, which runs the command at the time of the event (+/- offset) on that day, rounded to the
+    /// nearest minute and given in the local time zone, as used by cron. This is useful where heliocron can't be kept running all day
+    Crontab {
+        #[clap(flatten)]
+        event: EventArgs,
+
+        /// Set the number of days, starting from the chosen date, for which to generate entries
+        #[clap(long = "days", default_value = "30", value_parser = clap::value_parser!(u32).range(1..=366))]
+        days: u32,
+
+        /// Set the name of the block of entries, which is delimited by '# BEGIN heliocron <NAME>' and '# END heliocron <NAME>'
+        #[clap(long = "name", default_value = "default")]
+        name: String,
+
+        /// Rather than printing the entries, install them into the crontab of the current user, replacing the block with the same name if there
+        /// is one
+        #[clap(long = "install")]
+        install: bool,
+
+        /// Install the entries into this file, which is in the format of a user's crontab, rather than the crontab of the current user
+        #[clap(long = "crontab-file", value_name = "PATH", requires = "install")]
+        crontab_file: Option<PathBuf>,
+
+        /// The command to run, given after '--'
+        #[clap(last = true, required = true, value_name = "COMMAND")]
+        command: Vec<String>,
+    },
+
+    /// Write a systemd service unit which runs the command, and a timer unit which starts it at the time of the event (+/- offset) on each of
+    /// the coming days, together with a second pair of units which regenerate them daily. This is an alternative to the crontab subcommand
+    Systemd {
+        /// Set the name of the schedule. The units are named 'heliocron-<NAME>.service', 'heliocron-<NAME>.timer' etc.
+        #[clap(long = "name", value_parser = parse_unit_name)]
+        name: String,
+
+        #[clap(flatten)]
+        event: EventArgs,
+
+        /// Set the number of days, starting from the chosen date, for which the timer has an entry
+        #[clap(long = "days", default_value = "30", value_parser = clap::value_parser!(u32).range(1..=366))]
+        days: u32,
+
+        /// Set the directory into which the units are written. Defaults to ~/.config/systemd/user, or /etc/systemd/system with --system
+        #[clap(long = "output-dir", value_name = "PATH")]
+        output_dir: Option<PathBuf>,
+
+        /// Generate units for the system instance of systemd, rather than for that of the current user
+        #[clap(long = "system")]
+        system: bool,
+
+        /// The command to run, given after '--'
+        #[clap(last = true, required = true, value_name = "COMMAND")]
+        command: Vec<String>,
+    },
+
+    /// Produce an iCalendar file with the times of the chosen events on each day from one date to another, which can be imported into or
+    /// subscribed to from a calendar application
+    Ics {
+        /// Set the first date of the calendar, in 'yyyy-mm-dd' format
+        #[clap(long = "from", value_parser = parse_date)]
+        from: NaiveDate,
+
+        /// Set the last date of the calendar, in 'yyyy-mm-dd' format
+        #[clap(long = "to", value_parser = parse_date)]
+        to: NaiveDate,
+
+        /// Choose the events to include, as a comma-separated list of any of the built-in events
+        #[clap(
+            long = "events",
+            value_delimiter = ',',
+            default_value = "sunrise,sunset",
+            value_parser = parse_report_event
+        )]
+        events: Vec<domain::RawEventName>,
+
+        /// Add an all-day entry on each day on which the Sun doesn't rise (polar night) or set (polar day)
+        #[clap(long = "polar")]
+        polar: bool,
+    },
+
+    /// List the named locations defined in ~/.config/heliocron.toml
+    Locations,
+
+    /// Check whether it is currently day, night, twilight etc. and exit with status 0 if so, 1 if not or 2 on error, without any output
+    #[clap(alias = "is")]
+    Check {
+        /// Check at this date and time instead of the current time, in the format 'yyyy-mm-ddTHH:MM[:SS]', optionally followed by a UTC offset
+        /// such as '+01:00'. Without an offset, the date and time are interpreted in the chosen time zone
+        #[clap(long = "at", global = true, value_parser = parse_datetime)]
+        at: Option<DateTimeArg>,
+
+        #[clap(subcommand)]
+        condition: Condition,
+    },
+}
+
+impl Command {
+    /// The event chosen on the command line, if the subcommand takes one.
+    fn event_arg(&self) -> Option<&EventArg> {
+        match self {
+            Command::Wait { event_name, .. } => event_name.as_ref(),
+            Command::Get {
+                event_name: GetArg::Event(event_name),
+                ..
+            } => Some(event_name),
+            Command::Crontab { event, .. } | Command::Systemd { event, .. } => {
+                Some(&event.event_name)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The conditions which can be tested by the `check` subcommand.
+#[derive(Subcommand)]
+#[clap(rename_all = "snake_case")]
+pub enum Condition {
+    /// The Sun is above the horizon
+    Day,
+    /// The centre of the Sun is more than 18 degrees below the horizon
+    Night,
+    /// Any of civil, nautical or astronomical twilight
+    Twilight,
+    /// The centre of the Sun is between the horizon and 6 degrees below it
+    CivilTwilight,
+    /// The centre of the Sun is between 6 and 12 degrees below the horizon
+    NauticalTwilight,
+    /// The centre of the Sun is between 12 and 18 degrees below the horizon
+    AstronomicalTwilight,
+    /// The centre of the Sun is between 4 degrees below and 6 degrees above the horizon
+    GoldenHour,
+    /// The centre of the Sun is between 6 and 4 degrees below the horizon
+    BlueHour,
+    /// The time is after one event (+/- offset) and before the next occurrence of another event (+/- offset)
+    Between {
+        /// Choose the event which opens the window
+        #[clap(long = "from", value_enum)]
+        from: domain::RawEventName,
+
+        /// Choose an offset from the --from event in one of the following formats: {'HH:MM:SS' | 'HH:MM'}. The value may be prepended with '-' to make it negative
+        #[clap(long = "from-offset", default_value = "00:00:00", value_parser = parse_offset, allow_hyphen_values = true)]
+        from_offset: Duration,
+
+        /// Set the altitude of the Sun for the --from event when it is a custom event
+        #[clap(
+            long = "from-altitude",
+            allow_hyphen_values = true,
+            value_parser = domain::Altitude::parse,
+            required_if_eq_any = &[("from", "custom_am"), ("from", "custom_pm")]
+        )]
+        from_altitude: Option<domain::Altitude>,
+
+        /// Choose the event which closes the window. If it occurs before the --from event on the same day, then its occurrence on the
+        /// following day is used, so that the window spans midnight
+        #[clap(long = "to", value_enum)]
+        to: domain::RawEventName,
+
+        /// Choose an offset from the --to event in one of the following formats: {'HH:MM:SS' | 'HH:MM'}. The value may be prepended with '-' to make it negative
+        #[clap(long = "to-offset", default_value = "00:00:00", value_parser = parse_offset, allow_hyphen_values = true)]
+        to_offset: Duration,
+
+        /// Set the altitude of the Sun for the --to event when it is a custom event
+        #[clap(
+            long = "to-altitude",
+            allow_hyphen_values = true,
+            value_parser = domain::Altitude::parse,
+            required_if_eq_any = &[("to", "custom_am"), ("to", "custom_pm")]
+        )]
+        to_altitude: Option<domain::Altitude>,
     },
 }
 
-fn parse_offset(offset: &str) -> Result<Duration, String> {
+/// A date and time given on the command line, which may or may not include a UTC offset.
+#[derive(Clone)]
+pub enum DateTimeArg {
+    Fixed(DateTime<FixedOffset>),
+    Local(NaiveDateTime),
+}
+
+impl DateTimeArg {
+    /// Resolve the date and time to an instant in the given time zone.
+    fn in_time_zone(&self, time_zone: domain::TimeZone) -> DateTime<FixedOffset> {
+        match self {
+            Self::Fixed(datetime) => time_zone.from_utc_datetime(&datetime.naive_utc()),
+            Self::Local(datetime) => time_zone.from_local_datetime(datetime),
+        }
+    }
+}
+
+pub(crate) fn parse_offset(offset: &str) -> Result<Duration, String> {
     // offset should either be %H:%M:%S or %H:%M +/- a "-" if negative
     let (positive, offset): (bool, &str) = match offset.chars().next() {
         Some('-') => (false, &offset[1..]),
@@ -121,145 +462,1040 @@ fn parse_offset(offset: &str) -> Result<Duration, String> {
     }
 }
 
+fn parse_timeout(timeout: &str) -> Result<Duration, String> {
+    match parse_offset(timeout) {
+        Ok(timeout) if timeout > Duration::zero() => Ok(timeout),
+        _ => Err("Expected a timeout in the format 'HH:MM' or 'HH:MM:SS'".to_string()),
+    }
+}
+
+fn parse_grace(grace: &str) -> Result<Duration, String> {
+    match parse_offset(grace) {
+        Ok(grace) if grace >= Duration::zero() => Ok(grace),
+        _ => Err("Expected a grace period in the format 'HH:MM' or 'HH:MM:SS'".to_string()),
+    }
+}
+
+fn parse_jitter(jitter: &str) -> Result<Duration, String> {
+    match parse_offset(jitter) {
+        Ok(jitter) if jitter > Duration::zero() => Ok(jitter),
+        _ => Err("Expected a jitter in the format 'HH:MM' or 'HH:MM:SS'".to_string()),
+    }
+}
+
+/// Parse a period made up of whole numbers of days, hours, minutes and seconds, such as '48h', '7d' or '1h30m'.
+fn parse_period(period: &str) -> Result<Duration, String> {
+    let invalid = || {
+        format!(
+            "Expected a period of up to 366 days such as '48h', '7d' or '1h30m'. Found '{period}'"
+        )
+    };
+
+    let mut total = Duration::zero();
+    let mut number = String::new();
+    for c in period.chars() {
+        match c {
+            '0'..='9' => number.push(c),
+            'd' | 'h' | 'm' | 's' if !number.is_empty() => {
+                let value: i64 = number.parse().map_err(|_| invalid())?;
+                let unit = match c {
+                    'd' => Duration::days(1),
+                    'h' => Duration::hours(1),
+                    'm' => Duration::minutes(1),
+                    _ => Duration::seconds(1),
+                };
+                // Values too large to multiply are far beyond the limit anyway.
+                total = total + unit * i32::try_from(value).map_err(|_| invalid())?;
+                number.clear();
+            }
+            _ => return Err(invalid()),
+        }
+    }
+
+    match number.is_empty() && total > Duration::zero() && total <= Duration::days(366) {
+        true => Ok(total),
+        false => Err(invalid()),
+    }
+}
+
+fn parse_elevation(elevation: &str) -> Result<f64, String> {
+    match elevation.parse() {
+        Ok(elevation) if (-90.0..=90.0).contains(&elevation) => Ok(elevation),
+        _ => Err(format!(
+            "Expected a number between -90.0 and 90.0. Found '{elevation}'"
+        )),
+    }
+}
+
+fn parse_hysteresis(hysteresis: &str) -> Result<f64, String> {
+    match hysteresis.parse() {
+        Ok(hysteresis) if (0.0..=180.0).contains(&hysteresis) => Ok(hysteresis),
+        _ => Err(format!(
+            "Expected a number between 0.0 and 180.0. Found '{hysteresis}'"
+        )),
+    }
+}
+
+fn parse_datetime(datetime: &str) -> Result<DateTimeArg, String> {
+    // Allow a space in place of the 'T' separator, as RFC 3339 does.
+    let normalised = datetime.replacen(' ', "T", 1);
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(&normalised)
+        .or_else(|_| DateTime::parse_from_str(&normalised, "%Y-%m-%dT%H:%M%:z"))
+    {
+        return Ok(DateTimeArg::Fixed(datetime));
+    }
+
+    ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M"]
+        .iter()
+        .find_map(|pattern| NaiveDateTime::parse_from_str(&normalised, pattern).ok())
+        .map(DateTimeArg::Local)
+        .ok_or_else(|| {
+            format!("Invalid date and time - must be in the format 'yyyy-mm-ddTHH:MM[:SS][+HH:MM]'. Found '{datetime}'")
+        })
+}
+
 fn parse_date(date: &str) -> Result<NaiveDate, String> {
     NaiveDate::parse_from_str(date, "%Y-%m-%d")
         .map_err(|_| format!("Invalid date - must be in the format 'yyyy-mm-dd'. Found '{date}'"))
 }
 
-fn parse_tz(tz: &str) -> Result<chrono::FixedOffset, String> {
-    // Use chrono's own parsing function to validate the provided time zone.
-    let date = chrono::DateTime::parse_from_str(&format!("2022-01-01T00:00:00{tz}"), "%FT%T%:z")
-        .map_err(|_| {
-            format!(
-                "Invalid time zone - expected the format '[+|-]HH:MM' between '-23:59' and '+23:59'. Found '{tz}'"
-            )
-        })?;
-    Ok(*date.offset())
+/// The arguments which choose the output format of a report.
+#[derive(Args)]
+pub struct FormatArgs {
+    /// Set the output format to machine-readable JSON, as with '--format json'
+    #[clap(long = "json", conflicts_with = "format")]
+    json: bool,
+
+    /// Set the output format. Defaults to human-readable text, which for a range of dates is a table
+    #[clap(long = "format", value_enum)]
+    format: Option<domain::OutputFormat>,
+
+    /// Substitute the values of the report into a template, e.g. '{sunrise:%H:%M} {day_length:%Hh%Mm}'. The variables are named as in the
+    /// headings of '--format csv', and may be followed by a strftime-style format for times, '%H', '%M', '%S' or '%s' for durations, or a
+    /// precision such as '.2' for numbers. For a range of dates, the template is applied to each day
+    #[clap(long = "template", conflicts_with_all = &["format", "json"])]
+    template: Option<String>,
+
+    /// Set the text which replaces a variable of the --template without a value, such as the time of an event which doesn't occur
+    #[clap(long = "never", requires = "template")]
+    never: Option<String>,
+}
+
+impl FormatArgs {
+    /// Choose how to display the report. A template is checked against the variables of the report, whose kinds are
+    /// given by `kind_of`.
+    fn output(self, kind_of: impl Fn(&str) -> Option<template::Kind>) -> Result<domain::Output> {
+        let template = match self.template {
+            Some(template) => template::Template::parse(&template, kind_of)
+                .map_err(|e| HeliocronError::Config(ConfigErrorKind::InvalidTemplate(e)))?,
+            None => {
+                return Ok(domain::Output::Format(match (self.json, self.format) {
+                    (true, _) => domain::OutputFormat::Json,
+                    (false, format) => format.unwrap_or(domain::OutputFormat::Text),
+                }))
+            }
+        };
+
+        Ok(domain::Output::Template(match self.never {
+            Some(never) => template.with_never(never),
+            None => template,
+        }))
+    }
+}
+
+/// The arguments which choose the event at which a command is run by another scheduler, for the `crontab` and
+/// `systemd` subcommands.
+#[derive(Args)]
+pub struct EventArgs {
+    /// Choose the event at which to run the command, as with 'wait --event'
+    #[clap(short = 'e', long = "event", value_parser = parse_event_arg)]
+    event_name: EventArg,
+
+    #[clap(flatten)]
+    offset: OffsetArgs,
+}
+
+impl EventArgs {
+    /// The arguments as they would be given on the command line, leaving out those with default values.
+    fn to_args(&self) -> Vec<String> {
+        let mut args = vec![
+            "--event".to_string(),
+            match self.event_name {
+                EventArg::BuiltIn(ref event_name) => event_name
+                    .to_possible_value()
+                    .map_or_else(String::new, |value| value.get_name().to_string()),
+                EventArg::Custom(ref name) => name.clone(),
+            },
+        ];
+        if self.offset.offset != Duration::zero() {
+            args.extend([
+                "--offset".to_string(),
+                template::duration_hms(self.offset.offset),
+            ]);
+        }
+        if let Some(ref altitude) = self.offset.custom_altitude {
+            args.extend(["--altitude".to_string(), altitude.to_string()]);
+        }
+        args
+    }
+
+    /// Look up the event, which may be one defined in the configuration file, and its adjustment.
+    fn resolve(
+        self,
+        custom_events: &BTreeMap<String, domain::CustomEvent>,
+    ) -> Result<(domain::Event, domain::EventAdjustment)> {
+        self.offset.resolve(self.event_name, custom_events)
+    }
+}
+
+/// The arguments which give the offset from an event, and the altitude of the Sun if it is a custom one.
+#[derive(Args)]
+pub struct OffsetArgs {
+    /// Choose an offset from the event in one of the following formats: {'HH:MM:SS' | 'HH:MM'}. The value may be prepended with '-' to make it negative
+    #[clap(
+        short = 'o',
+        long = "offset",
+        default_value = "00:00:00",
+        parse(try_from_str=parse_offset),
+        allow_hyphen_values = true,
+    )]
+    offset: Duration,
+
+    /// Set the altitude of the Sun for a custom event, as with 'wait --altitude'
+    #[clap(
+        short = 'a',
+        long = "altitude",
+        allow_hyphen_values = true,
+        value_parser = domain::Altitude::parse,
+        required_if_eq_any = &[("event-name", "custom_am"), ("event-name", "custom_pm")]
+    )]
+    custom_altitude: Option<domain::Altitude>,
+}
+
+impl OffsetArgs {
+    /// Look up the event, which may be one defined in the configuration file, and its adjustment.
+    fn resolve(
+        self,
+        event_name: EventArg,
+        custom_events: &BTreeMap<String, domain::CustomEvent>,
+    ) -> Result<(domain::Event, domain::EventAdjustment)> {
+        let (event, offset, clamp) =
+            resolve_event(event_name, self.custom_altitude, self.offset, custom_events)?;
+        Ok((
+            event,
+            domain::EventAdjustment {
+                offset,
+                jitter: None,
+                clamp,
+            },
+        ))
+    }
+}
+
+/// The arguments which adjust the time of the event, beyond the offset.
+#[derive(Args)]
+pub struct AdjustmentArgs {
+    /// Shift the event (+/- offset) by a random amount of up to this much either side of it, in the format {'HH:MM:SS' | 'HH:MM'}, e.g. so that
+    /// lights don't turn on at exactly the same time every day. The shift is applied before --not-before and --not-after
+    #[clap(long = "jitter", value_parser = parse_jitter, conflicts_with = "elevation")]
+    jitter: Option<Duration>,
+
+    /// Make the --jitter shift reproducible: the same seed, e.g. the date and the tag, always gives the same shift
+    #[clap(long = "seed", requires = "jitter")]
+    seed: Option<String>,
+
+    /// Never wait until earlier than this local time of day, in the format {'HH:MM:SS' | 'HH:MM'}. If the event (+/- offset) occurs earlier than this,
+    /// the wait ends at this time instead. Takes precedence over the 'not_before' time of an event defined in ~/.config/heliocron.toml
+    #[clap(long = "not-before", value_parser = parse_time_of_day, conflicts_with = "elevation")]
+    not_before: Option<NaiveTime>,
+
+    /// Never wait until later than this local time of day, in the format {'HH:MM:SS' | 'HH:MM'}. If the event (+/- offset) occurs later than this,
+    /// the wait ends at this time instead. Takes precedence over the 'not_after' time of an event defined in ~/.config/heliocron.toml
+    #[clap(long = "not-after", value_parser = parse_time_of_day, conflicts_with = "elevation")]
+    not_after: Option<NaiveTime>,
+}
+
+/// The event chosen with `wait --event`.
+#[derive(Clone)]
+pub enum EventArg {
+    BuiltIn(domain::RawEventName),
+    /// The name of an event defined in the configuration file, which is only known once the file has been parsed.
+    Custom(String),
+}
+
+/// The value printed by the 'get' subcommand.
+#[derive(Clone)]
+pub enum GetArg {
+    DayLength,
+    Event(EventArg),
+}
+
+fn parse_get_arg(value: &str) -> Result<GetArg, String> {
+    match value {
+        "day_length" => Ok(GetArg::DayLength),
+        event => parse_event_arg(event).map(GetArg::Event),
+    }
+}
+
+/// Parse the name of one of the events in `report::Report`, i.e. any built-in event other than the custom ones.
+fn parse_report_event(event: &str) -> Result<domain::RawEventName, String> {
+    match domain::RawEventName::from_str(event, false) {
+        Ok(event) if !event.is_custom() => Ok(event),
+        _ => {
+            let names: Vec<_> = domain::RawEventName::value_variants()
+                .iter()
+                .filter(|event| !event.is_custom())
+                .filter_map(|event| event.to_possible_value())
+                .map(|event| event.get_name().to_string())
+                .collect();
+            Err(format!(
+                "Expected one of {}. Found '{event}'",
+                names.join(", ")
+            ))
+        }
+    }
+}
+
+fn parse_event_arg(event: &str) -> Result<EventArg, String> {
+    Ok(match domain::RawEventName::from_str(event, false) {
+        Ok(event) => EventArg::BuiltIn(event),
+        Err(_) => EventArg::Custom(event.to_string()),
+    })
 }
 
 #[derive(Debug, Deserialize)]
 struct RawFileConfig {
     latitude: Option<f64>,
     longitude: Option<f64>,
+    time_zone: Option<String>,
+    grace: Option<String>,
+    default: Option<String>,
+    #[serde(default)]
+    locations: BTreeMap<String, RawLocation>,
+    #[serde(default)]
+    events: BTreeMap<String, RawCustomEvent>,
+}
+
+/// A `[locations.<name>]` table in the configuration file. Unknown keys are rejected, so that typos don't go
+/// unnoticed.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawLocation {
+    latitude: f64,
+    longitude: f64,
+    time_zone: Option<String>,
+    elevation: Option<f64>,
+}
+
+/// An `[events.<name>]` table in the configuration file. As with `wait --event custom_{am | pm}`, the altitude is in
+/// degrees below the horizon.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawCustomEvent {
+    altitude: f64,
+    direction: domain::Direction,
+    offset: Option<String>,
+    not_before: Option<String>,
+    not_after: Option<String>,
+}
+
+/// Validated contents of the configuration file. Every field is optional.
+#[derive(Default)]
+struct FileConfig {
+    coordinates: Option<domain::Coordinates>,
+    time_zone: Option<domain::TimeZone>,
+    grace: Option<Duration>,
+    /// The name of the default location, which is known to be one of `locations`.
+    default_location: Option<String>,
+    locations: BTreeMap<String, domain::Location>,
+    custom_events: BTreeMap<String, domain::CustomEvent>,
 }
 
 /// Container for all necessary runtime configuration.
 pub struct Config {
     pub coordinates: domain::Coordinates,
+    pub time_zone: domain::TimeZone,
+    /// The elevation of the observer in metres above sea level.
+    pub observer_elevation: f64,
     pub date: DateTime<FixedOffset>,
     pub action: domain::Action,
+    pub clock: Arc<dyn Clock>,
+}
+
+/// Parse the command line arguments, printing the usage and exiting with status 2 if they are invalid.
+pub fn parse_args() -> Cli {
+    Cli::parse()
+}
+
+impl Cli {
+    /// Whether the `check` subcommand was chosen, whose exit status of 1 is reserved for a condition which doesn't
+    /// hold.
+    pub fn is_check(&self) -> bool {
+        matches!(self.subcommand, Command::Check { .. })
+    }
+
+    /// The options which choose the location and time zone, as they would be given on the command line.
+    fn location_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(ref location) = self.location {
+            args.extend(["--location".to_string(), location.clone()]);
+        }
+        if let (Some(ref latitude), Some(ref longitude)) = (&self.latitude, &self.longitude) {
+            args.extend([
+                "--latitude".to_string(),
+                latitude.to_string(),
+                "--longitude".to_string(),
+                longitude.to_string(),
+            ]);
+        }
+        if let Some(time_zone) = self.time_zone {
+            args.extend(["--time-zone".to_string(), time_zone.to_string()]);
+        }
+        args
+    }
 }
 
 /// Parse all configuration streams into one valid runtime configuration. Where supported, arguments passed over the
 /// command line take precedence over values found in configuration files, which, in turn, takes precedence over
 /// any hard coded default values.
-pub fn parse_config() -> Result<Config, HeliocronError> {
-    let cli_args = Cli::parse();
+///
+/// The given clock is used to determine the current time, unless it is overridden on the command line.
+pub fn parse_config(cli_args: Cli, clock: Arc<dyn Clock>) -> Result<Config, HeliocronError> {
+    let clock = match &cli_args.now {
+        Some(now) => {
+            let now = match now {
+                DateTimeArg::Fixed(now) => *now,
+                DateTimeArg::Local(now) => Utc.from_utc_datetime(now).into(),
+            };
+            let simulated = SimulatedClock::new(now);
+            // The end is resolved in the simulated local time zone, as set by --now.
+            let simulated = match &cli_args.until {
+                Some(until) => {
+                    let end = until.in_time_zone(simulated.local_time_zone());
+                    simulated.with_end(end)
+                }
+                None => simulated,
+            };
+            Arc::new(simulated)
+        }
+        None => clock,
+    };
+
+    // Kept for the subcommands which pass them on to a later run of heliocron.
+    let location_args = cli_args.location_args();
+
+    let config_path = dirs::config_dir().map(|path| path.join("heliocron.toml"));
+    let file_config = match config_path.as_ref().filter(|path| path.exists()) {
+        Some(path) => match parse_local_config(path) {
+            Ok(file_config) => file_config,
+            // Falling back to the default values isn't an option when a named location or event is needed.
+            Err(e)
+                if cli_args.location.is_some()
+                    || matches!(cli_args.subcommand, Command::Locations)
+                    || matches!(cli_args.subcommand.event_arg(), Some(EventArg::Custom(_))) =>
+            {
+                return Err(HeliocronError::Config(ConfigErrorKind::InvalidConfigFile(
+                    e,
+                )))
+            }
+            Err(e) => {
+                eprintln!("Warning - couldn't parse configuration file due to the following reason: {e}\n. Proceeding with default values.");
+                FileConfig::default()
+            }
+        },
+        None => FileConfig::default(),
+    };
+
+    let location = match (&cli_args.location, &cli_args.latitude) {
+        (Some(name), _) => Some(file_config.locations.get(name).cloned().ok_or_else(|| {
+            HeliocronError::Config(ConfigErrorKind::InvalidLocation(unknown_location(
+                name,
+                &file_config.locations,
+            )))
+        })?),
+        // Coordinates given on the command line take precedence over the default location.
+        (None, Some(_)) => None,
+        (None, None) => file_config
+            .default_location
+            .as_ref()
+            .map(|name| file_config.locations[name].clone()),
+    };
 
     let coordinates = {
         // First try the command line arguments...
         if let (Some(lat), Some(lon)) = (cli_args.latitude, cli_args.longitude) {
             domain::Coordinates::new(lat, lon)
+        } else if let Some(location) = &location {
+            // ...then a named location...
+            location.coordinates.clone()
         } else {
             // ...failing that, check if the coordinates are set in a config file...
-            dirs::config_dir()
-                .map(|path| path.join("heliocron.toml"))
-                .filter(|path| path.exists())
-                .map(|path| parse_local_config(&path))
-                .and_then(|res| {
-                    match res {
-                        Ok(coords) => Some(coords),
-                        Err(e) => {
-                            eprintln!("Warning - couldn't parse configuration file due to the following reason: {e}\n. Proceeding with default coordinates.");
-                            None
-                        }
-                        }
-                })
-                .unwrap_or_else(|| {
-                    // ...otherwise default to some hardcoded values. Safe to unwrap because we know these values are valid.
-                    domain::Coordinates::new(
-                        domain::Latitude::new(51.4769).unwrap(),
-                        domain::Longitude::new(-0.0005).unwrap(),
-                    )
-                })
+            file_config.coordinates.unwrap_or_else(|| {
+                // ...otherwise default to some hardcoded values. Safe to unwrap because we know these values are valid.
+                domain::Coordinates::new(
+                    domain::Latitude::new(51.4769).unwrap(),
+                    domain::Longitude::new(-0.0005).unwrap(),
+                )
+            })
         }
     };
 
+    let time_zone = cli_args
+        .time_zone
+        .or_else(|| location.as_ref().and_then(|location| location.time_zone))
+        .or(file_config.time_zone)
+        .unwrap_or_else(|| clock.local_time_zone());
+
+    let observer_elevation = location.map_or(0.0, |location| location.elevation);
+
+    // The grace period is used by both `wait` and `daemon`, but only `wait` can override it.
+    let grace = file_config.grace.unwrap_or_else(|| Duration::seconds(30));
+
+    let now = clock.now_in(time_zone);
+
     let date = match cli_args.subcommand {
-        Command::Poll { .. } => {
-            let now = Local::now();
-            now.with_timezone(now.offset())
+        Command::Poll { .. } | Command::Next { .. } | Command::Daemon { .. } => now,
+        Command::Check { .. } if cli_args.date.is_some() => {
+            return Err(HeliocronError::Config(ConfigErrorKind::InvalidArguments(
+                "--date can't be used with check, use --at instead".to_string(),
+            )))
+        }
+        Command::Check { ref at, .. } => at.as_ref().map_or(now, |at| at.in_time_zone(time_zone)),
+        _ => {
+            let date = cli_args.date.unwrap_or_else(|| now.date().naive_local());
+            time_zone.from_local_datetime(&date.and_hms(12, 0, 0))
         }
-        _ => cli_args
-            .time_zone
-            .ymd(
-                cli_args.date.year(),
-                cli_args.date.month(),
-                cli_args.date.day(),
-            )
-            .and_hms(12, 0, 0),
     };
 
     let action = match cli_args.subcommand {
-        Command::Report { json } => domain::Action::Report { json },
+        Command::Report {
+            format,
+            from: None,
+            to: None,
+            days: None,
+        } => domain::Action::Report {
+            output: format
+                .output(|name| report::Report::variable_kind(name, &file_config.custom_events))?,
+            custom_events: file_config.custom_events,
+        },
+        Command::Report {
+            from,
+            to,
+            days,
+            format,
+        } => {
+            let from = from.unwrap_or_else(|| date.date().naive_local());
+            let to = match (to, days) {
+                (Some(to), _) => to,
+                (None, days) => from + Duration::days(days.unwrap_or(1) as i64 - 1),
+            };
+            domain::Action::RangeReport {
+                range: domain::DateRange::new(from, to)
+                    .map_err(|e| HeliocronError::Config(ConfigErrorKind::InvalidDateRange(e)))?,
+                output: format.output(|name| {
+                    report::Report::variable_kind(name, &file_config.custom_events)
+                })?,
+                custom_events: file_config.custom_events,
+            }
+        }
+        Command::Get {
+            event_name: GetArg::DayLength,
+            offset,
+            seconds,
+        } => {
+            if offset.offset != Duration::zero() || offset.custom_altitude.is_some() {
+                return Err(HeliocronError::Config(ConfigErrorKind::InvalidArguments(
+                    "--offset and --altitude only apply to events, not to day_length".to_string(),
+                )));
+            }
+            domain::Action::Get {
+                query: domain::Query::DayLength,
+                seconds,
+            }
+        }
+        Command::Get {
+            event_name: GetArg::Event(event_name),
+            offset,
+            seconds,
+        } => {
+            let (event, adjustment) = offset.resolve(event_name, &file_config.custom_events)?;
+
+            domain::Action::Get {
+                query: domain::Query::EventTime { event, adjustment },
+                seconds,
+            }
+        }
+        Command::Wait {
+            event_name: None,
+            elevation_below,
+            elevation_above,
+            hysteresis,
+            immediate,
+            timeout,
+            command,
+            ..
+        } => {
+            // Safe to unwrap because clap already validates that one of the elevations is present when there is
+            // no event.
+            let (elevation, direction) = match (elevation_below, elevation_above) {
+                (Some(elevation), _) => (elevation, domain::Direction::Descending),
+                (_, elevation) => (elevation.unwrap(), domain::Direction::Ascending),
+            };
+
+            domain::Action::WaitForElevation {
+                threshold: domain::ElevationThreshold {
+                    elevation,
+                    direction,
+                    hysteresis,
+                },
+                immediate,
+                command,
+                timeout,
+            }
+        }
         Command::Wait {
-            event_name,
+            event_name: Some(event_name),
             offset,
             run_missed_task,
+            missed_event,
+            grace: wait_grace,
+            audit_log,
             custom_altitude,
+            adjustment: adjustment_args,
+            next,
+            horizon,
+            timeout,
+            command,
             ..
         } => {
-            let event = match event_name {
-                domain::RawEventName::Sunrise => domain::EventName::Sunrise,
-                domain::RawEventName::Sunset => domain::EventName::Sunset,
-                domain::RawEventName::CivilDawn => domain::EventName::CivilDawn,
-                domain::RawEventName::CivilDusk => domain::EventName::CivilDusk,
-                domain::RawEventName::NauticalDawn => domain::EventName::NauticalDawn,
-                domain::RawEventName::NauticalDusk => domain::EventName::NauticalDusk,
-                domain::RawEventName::AstronomicalDawn => domain::EventName::AstronomicalDawn,
-                domain::RawEventName::AstronomicalDusk => domain::EventName::AstronomicalDusk,
-                domain::RawEventName::SolarNoon => domain::EventName::SolarNoon,
-                // These two custom_altitudes are safe to unwrap because clap already validates
-                // that custom_altitude is present when the event is custom_{am | pm}.
-                domain::RawEventName::CustomAM => {
-                    domain::EventName::CustomAM(custom_altitude.unwrap())
-                }
-                domain::RawEventName::CustomPM => {
-                    domain::EventName::CustomPM(custom_altitude.unwrap())
-                }
-            };
+            let (event, offset, clamp) = resolve_event(
+                event_name,
+                custom_altitude,
+                offset,
+                &file_config.custom_events,
+            )?;
 
-            let event = domain::Event::from_event_name(event);
+            // The clamp given on the command line overrides the clamp of a custom event, one bound at a time.
+            let clamp = domain::Clamp::new(
+                adjustment_args.not_before.or(clamp.not_before),
+                adjustment_args.not_after.or(clamp.not_after),
+            )
+            .map_err(|e| HeliocronError::Config(ConfigErrorKind::InvalidClamp(e)))?;
 
             domain::Action::Wait {
                 event,
-                offset,
-                run_missed_task,
+                adjustment: domain::EventAdjustment {
+                    offset,
+                    jitter: adjustment_args.jitter.map(|max| domain::Jitter {
+                        max,
+                        seed: adjustment_args.seed,
+                    }),
+                    clamp,
+                },
+                missed_event: domain::MissedEventHandling {
+                    policy: match run_missed_task {
+                        true => domain::MissedEventPolicy::Always,
+                        false => missed_event,
+                    },
+                    grace: wait_grace.unwrap_or(grace),
+                    audit_log,
+                },
+                next: next.then_some(horizon),
+                command,
+                timeout,
             }
         }
-        Command::Poll { watch, json } => domain::Action::Poll { watch, json },
+        Command::Poll { watch, format } => domain::Action::Poll {
+            watch,
+            output: format.output(report::PollReport::variable_kind)?,
+        },
+        Command::Next {
+            count,
+            within,
+            format,
+        } => domain::Action::Next {
+            limit: match within {
+                Some(period) => domain::UpcomingLimit::Within(period),
+                None => domain::UpcomingLimit::Count(count.unwrap_or(10) as usize),
+            },
+            output: format.output(upcoming::UpcomingEvent::variable_kind)?,
+            custom_events: file_config.custom_events,
+        },
+        Command::Daemon { heliotab } => {
+            let heliotab = heliotab
+                .or_else(|| dirs::config_dir().map(|path| path.join("heliotab")))
+                .ok_or_else(|| {
+                    HeliocronError::Config(ConfigErrorKind::InvalidHeliotab(
+                        "couldn't find the default heliotab; use --heliotab to set its path"
+                            .to_string(),
+                    ))
+                })?;
+            domain::Action::Daemon { heliotab, grace }
+        }
+        Command::Crontab {
+            event,
+            days,
+            name,
+            install,
+            crontab_file,
+            command,
+        } => {
+            let (event, adjustment) = event.resolve(&file_config.custom_events)?;
+
+            domain::Action::Crontab {
+                scheduled: domain::ScheduledCommand {
+                    name,
+                    event,
+                    adjustment,
+                    command,
+                },
+                days,
+                install: match (install, crontab_file) {
+                    (true, Some(path)) => Some(domain::CrontabTarget::File(path)),
+                    (true, None) => Some(domain::CrontabTarget::User),
+                    (false, _) => None,
+                },
+            }
+        }
+        Command::Systemd {
+            name,
+            event,
+            days,
+            output_dir,
+            system,
+            command,
+        } => {
+            let output_dir = match (output_dir, system) {
+                (Some(output_dir), _) => output_dir,
+                (None, true) => PathBuf::from("/etc/systemd/system"),
+                (None, false) => dirs::config_dir()
+                    .map(|path| path.join("systemd").join("user"))
+                    .ok_or_else(|| {
+                        HeliocronError::Config(ConfigErrorKind::InvalidOutputDir(
+                            "couldn't find the default directory; use --output-dir to set it"
+                                .to_string(),
+                        ))
+                    })?,
+            };
+            // The units are regenerated from whichever directory systemd runs the refresh service in.
+            let output_dir = std::env::current_dir()
+                .map(|current_dir| current_dir.join(output_dir))
+                .map_err(|e| {
+                    HeliocronError::Config(ConfigErrorKind::InvalidOutputDir(e.to_string()))
+                })?;
+
+            let regenerate = regenerate_command(
+                &location_args,
+                &name,
+                &event,
+                days,
+                &output_dir,
+                system,
+                &command,
+            );
+            let (event, adjustment) = event.resolve(&file_config.custom_events)?;
+
+            domain::Action::Systemd {
+                scheduled: domain::ScheduledCommand {
+                    name,
+                    event,
+                    adjustment,
+                    command,
+                },
+                days,
+                regenerate,
+                output_dir,
+                system,
+            }
+        }
+        Command::Ics {
+            from,
+            to,
+            events,
+            polar,
+        } => domain::Action::Ics {
+            range: domain::DateRange::new(from, to)
+                .map_err(|e| HeliocronError::Config(ConfigErrorKind::InvalidDateRange(e)))?,
+            events,
+            polar,
+        },
+        Command::Locations => domain::Action::Locations {
+            locations: file_config.locations,
+            default: file_config.default_location,
+        },
+        Command::Check { condition, .. } => domain::Action::Check {
+            condition: match condition {
+                Condition::Day => domain::Condition::Day,
+                Condition::Night => domain::Condition::Night,
+                Condition::Twilight => domain::Condition::Twilight,
+                Condition::CivilTwilight => domain::Condition::CivilTwilight,
+                Condition::NauticalTwilight => domain::Condition::NauticalTwilight,
+                Condition::AstronomicalTwilight => domain::Condition::AstronomicalTwilight,
+                Condition::GoldenHour => domain::Condition::GoldenHour,
+                Condition::BlueHour => domain::Condition::BlueHour,
+                Condition::Between {
+                    from,
+                    from_offset,
+                    from_altitude,
+                    to,
+                    to_offset,
+                    to_altitude,
+                } => {
+                    // Safe to unwrap because clap already validates that the altitudes are present when the
+                    // events are custom_{am | pm}.
+                    let from = domain::EventName::new(from, from_altitude).unwrap();
+                    let to = domain::EventName::new(to, to_altitude).unwrap();
+
+                    domain::Condition::Between {
+                        from: domain::Event::from_event_name(from),
+                        from_offset,
+                        to: domain::Event::from_event_name(to),
+                        to_offset,
+                    }
+                }
+            },
+        },
     };
 
     Ok(Config {
         coordinates,
+        time_zone,
+        observer_elevation,
         date,
         action,
+        clock,
     })
 }
 
-fn parse_local_config(path: &PathBuf) -> Result<domain::Coordinates, String> {
+/// Resolve the event chosen on the command line, returning the event along with its offset and clamp. The offset of
+/// a custom event is added to the offset given on the command line.
+fn resolve_event(
+    event_name: EventArg,
+    custom_altitude: Option<domain::Altitude>,
+    offset: Duration,
+    custom_events: &BTreeMap<String, domain::CustomEvent>,
+) -> Result<(domain::Event, Duration, domain::Clamp), HeliocronError> {
+    match event_name {
+        EventArg::BuiltIn(event_name) => {
+            // Safe to unwrap because clap already validates that custom_altitude is present when the event
+            // is custom_{am | pm}.
+            let event = domain::EventName::new(event_name, custom_altitude).unwrap();
+            Ok((
+                domain::Event::from_event_name(event),
+                offset,
+                domain::Clamp::default(),
+            ))
+        }
+        EventArg::Custom(name) => {
+            let custom_event = custom_events.get(&name).ok_or_else(|| {
+                HeliocronError::Config(ConfigErrorKind::InvalidCustomEvent(unknown_custom_event(
+                    &name,
+                    custom_events,
+                )))
+            })?;
+            Ok((
+                domain::Event::Fixed(custom_event.event.clone()),
+                custom_event.offset + offset,
+                custom_event.clamp,
+            ))
+        }
+    }
+}
+
+/// Describe why an event is neither a built-in event nor defined in the configuration file.
+fn unknown_custom_event(
+    name: &str,
+    custom_events: &BTreeMap<String, domain::CustomEvent>,
+) -> String {
+    let built_in = domain::RawEventName::value_variants()
+        .iter()
+        .filter_map(|event| event.to_possible_value())
+        .map(|event| event.get_name().to_string());
+    format!(
+        "'{name}' is neither a built-in event nor defined in ~/.config/heliocron.toml. Expected one of: {}",
+        built_in
+            .chain(custom_events.keys().cloned())
+            .collect::<Vec<_>>()
+            .join(", ")
+    )
+}
+
+/// Describe why a named location couldn't be found, listing the locations which are defined instead.
+fn unknown_location(name: &str, locations: &BTreeMap<String, domain::Location>) -> String {
+    match locations.is_empty() {
+        true => format!(
+            "'{name}' is not defined, because there are no locations in ~/.config/heliocron.toml"
+        ),
+        false => format!(
+            "'{name}' is not defined in ~/.config/heliocron.toml. Expected one of: {}",
+            locations.keys().cloned().collect::<Vec<_>>().join(", ")
+        ),
+    }
+}
+
+fn parse_local_config(path: &PathBuf) -> Result<FileConfig, String> {
     let config_file = fs::read(path).map_err(|_| "Failed to read config file path".to_string())?;
     let toml_config = toml::from_slice::<RawFileConfig>(&config_file).map_err(|e| e.to_string())?;
 
-    let (lat, lon) = match (toml_config.latitude, toml_config.longitude) {
-        (Some(lat), Some(lon)) => Ok((lat, lon)),
+    let coordinates = match (toml_config.latitude, toml_config.longitude) {
+        (Some(lat), Some(lon)) => Ok(Some((lat, lon))),
         (Some(_lat), None) => Err("Missing longitude".to_string()),
         (None, Some(_lon)) => Err("Missing latitude".to_string()),
-        (None, None) => Err("Missing latitude and longitude".to_string()),
-    }?;
+        (None, None) => Ok(None),
+    }?
+    .map(|(lat, lon)| -> Result<_, String> {
+        let lat = domain::Latitude::new(lat)?;
+        let lon = domain::Longitude::new(lon)?;
+        Ok(domain::Coordinates::new(lat, lon))
+    })
+    .transpose()?;
+
+    let time_zone = toml_config
+        .time_zone
+        .map(|tz| domain::TimeZone::parse(&tz))
+        .transpose()?;
+
+    let grace = toml_config
+        .grace
+        .map(|grace| parse_grace(&grace))
+        .transpose()?;
+
+    let locations = toml_config
+        .locations
+        .into_iter()
+        .map(|(name, location)| {
+            parse_location(location)
+                .map(|location| (name.clone(), location))
+                .map_err(|e| format!("Invalid location '{name}' - {e}"))
+        })
+        .collect::<Result<BTreeMap<_, _>, String>>()?;
 
-    let lat = domain::Latitude::new(lat)?;
-    let lon = domain::Longitude::new(lon)?;
+    let default_location = toml_config
+        .default
+        .map(|name| match locations.contains_key(&name) {
+            true => Ok(name),
+            false => Err(format!(
+                "The default location '{name}' is not defined in [locations]"
+            )),
+        })
+        .transpose()?;
 
-    Ok(domain::Coordinates::new(lat, lon))
+    let custom_events = toml_config
+        .events
+        .into_iter()
+        .map(|(name, event)| {
+            parse_custom_event(&name, event)
+                .map(|event| (name.clone(), event))
+                .map_err(|e| format!("Invalid custom event '{name}' - {e}"))
+        })
+        .collect::<Result<BTreeMap<_, _>, String>>()?;
+
+    Ok(FileConfig {
+        coordinates,
+        time_zone,
+        grace,
+        default_location,
+        locations,
+        custom_events,
+    })
+}
+
+fn parse_location(location: RawLocation) -> Result<domain::Location, String> {
+    let coordinates = domain::Coordinates::new(
+        domain::Latitude::new(location.latitude)?,
+        domain::Longitude::new(location.longitude)?,
+    );
+    let time_zone = location
+        .time_zone
+        .map(|tz| domain::TimeZone::parse(&tz))
+        .transpose()?;
+
+    domain::Location::new(coordinates, time_zone, location.elevation.unwrap_or(0.0))
+}
+
+fn parse_custom_event(name: &str, event: RawCustomEvent) -> Result<domain::CustomEvent, String> {
+    if domain::RawEventName::from_str(name, false).is_ok() {
+        return Err("the name is already used by a built-in event".to_string());
+    }
+
+    let offset = event
+        .offset
+        .map(|offset| parse_offset(&offset))
+        .transpose()?
+        .unwrap_or_else(Duration::zero);
+    let clamp = domain::Clamp::new(
+        event
+            .not_before
+            .map(|time| parse_time_of_day(&time))
+            .transpose()?,
+        event
+            .not_after
+            .map(|time| parse_time_of_day(&time))
+            .transpose()?,
+    )?;
+
+    Ok(domain::CustomEvent {
+        event: domain::FixedElevationEvent::new(
+            domain::Altitude::new(event.altitude)?,
+            event.direction,
+        ),
+        offset,
+        clamp,
+    })
+}
+
+/// Parse the name of a schedule of systemd units, which becomes part of their file names.
+fn parse_unit_name(name: &str) -> Result<String, String> {
+    match systemd::is_valid_name(name) {
+        true => Ok(name.to_string()),
+        false => Err(format!(
+            "Expected a name made up of letters, digits and any of '_.:-'. Found '{name}'"
+        )),
+    }
+}
+
+/// The command with which the refresh service regenerates the systemd units. It is built from the parsed arguments,
+/// leaving out any options which fix the date or the current time, so that each regeneration starts from the day on
+/// which it runs. The output directory is given explicitly, as an absolute path.
+fn regenerate_command(
+    location_args: &[String],
+    name: &str,
+    event: &EventArgs,
+    days: u32,
+    output_dir: &Path,
+    system: bool,
+    command: &[String],
+) -> Vec<String> {
+    let mut regenerate = vec![std::env::current_exe().map_or_else(
+        |_| "heliocron".to_string(),
+        |path| path.display().to_string(),
+    )];
+    regenerate.extend_from_slice(location_args);
+    regenerate.extend([
+        "systemd".to_string(),
+        "--name".to_string(),
+        name.to_string(),
+    ]);
+    regenerate.extend(event.to_args());
+    regenerate.extend([
+        "--days".to_string(),
+        days.to_string(),
+        "--output-dir".to_string(),
+        output_dir.display().to_string(),
+    ]);
+    if system {
+        regenerate.push("--system".to_string());
+    }
+    regenerate.push("--".to_string());
+    regenerate.extend_from_slice(command);
+    regenerate
+}
+
+/// Parse a time of day in the format HH:MM or HH:MM:SS.
+fn parse_time_of_day(time: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(time, "%H:%M:%S")
+        .or_else(|_| NaiveTime::parse_from_str(time, "%H:%M"))
+        .map_err(|_| {
+            format!("Expected a time of day in the format HH:MM or HH:MM:SS. Found '{time}'")
+        })
 }
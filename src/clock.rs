@@ -0,0 +1,150 @@
+//! Sources of the current time.
+//!
+//! Everything which needs to know the time, or to sleep until a later time, does so through a `Clock`. In normal
+//! use, this is the `SystemClock`, but a `SimulatedClock` allows waits to be driven deterministically, e.g. in tests.
+use std::{future::Future, pin::Pin, sync::Mutex};
+
+use chrono::{DateTime, Duration, FixedOffset, Utc};
+
+use super::{domain, sleep};
+
+/// The future returned by `Clock::sleep_until`.
+pub type Sleep<'a> = Pin<Box<dyn Future<Output = Result<(), sleep::Error>> + Send + 'a>>;
+
+pub trait Clock: Send + Sync {
+    /// Returns the current time.
+    fn now(&self) -> DateTime<Utc>;
+
+    /// Sleep until the given time has been reached.
+    fn sleep_until(&self, time: DateTime<Utc>) -> Sleep<'_>;
+
+    /// Returns the local time zone, which is used when no other time zone has been chosen.
+    fn local_time_zone(&self) -> domain::TimeZone;
+
+    /// Returns the current time in the given time zone.
+    fn now_in(&self, time_zone: domain::TimeZone) -> DateTime<FixedOffset> {
+        time_zone.from_utc_datetime(&self.now().naive_utc())
+    }
+}
+
+/// The real time, according to the system's wall clock and local time zone.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+
+    fn sleep_until(&self, time: DateTime<Utc>) -> Sleep<'_> {
+        Box::pin(sleep::sleep_until(time))
+    }
+
+    fn local_time_zone(&self) -> domain::TimeZone {
+        domain::TimeZone::Local
+    }
+}
+
+/// A clock which only moves when told to. Sleeping returns immediately, having moved the clock forward to the
+/// requested time.
+pub struct SimulatedClock {
+    now: Mutex<DateTime<Utc>>,
+    local_offset: FixedOffset,
+    end: Option<DateTime<Utc>>,
+}
+
+impl SimulatedClock {
+    /// Create a clock which starts at the given time. The offset of the given time is used as the local time zone.
+    pub fn new(now: DateTime<FixedOffset>) -> Self {
+        Self {
+            now: Mutex::new(now.with_timezone(&Utc)),
+            local_offset: *now.offset(),
+            end: None,
+        }
+    }
+
+    /// Stop the clock from moving beyond the given time. Sleeping until any later time never returns, and leaves the
+    /// clock where it was, so that something which sleeps in a loop, such as the daemon, can be brought to rest rather
+    /// than running through the days as fast as it can.
+    pub fn with_end(mut self, end: DateTime<FixedOffset>) -> Self {
+        self.end = Some(end.with_timezone(&Utc));
+        self
+    }
+
+    /// Move the clock forward, e.g. to simulate the system having been suspended.
+    pub fn advance(&self, duration: Duration) {
+        let mut now = self.now.lock().unwrap();
+        *now = *now + duration;
+    }
+}
+
+impl Clock for SimulatedClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.lock().unwrap()
+    }
+
+    fn sleep_until(&self, time: DateTime<Utc>) -> Sleep<'_> {
+        if self.end.is_some_and(|end| time > end) {
+            return Box::pin(std::future::pending());
+        }
+
+        let mut now = self.now.lock().unwrap();
+        if time > *now {
+            *now = time;
+        }
+        Box::pin(async { Ok(()) })
+    }
+
+    fn local_time_zone(&self) -> domain::TimeZone {
+        domain::TimeZone::Fixed(self.local_offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_simulated_clock() {
+        let start = DateTime::parse_from_rfc3339("2022-06-11T12:00:00+01:00").unwrap();
+        let clock = SimulatedClock::new(start);
+        assert_eq!(clock.now(), start);
+        assert_eq!(
+            clock.now_in(clock.local_time_zone()).to_string(),
+            "2022-06-11 12:00:00 +01:00"
+        );
+
+        // sleeping moves the clock forward, but never backwards
+        clock
+            .sleep_until(start.with_timezone(&Utc) + Duration::hours(1))
+            .await
+            .unwrap();
+        assert_eq!(clock.now(), start + Duration::hours(1));
+        clock.sleep_until(start.with_timezone(&Utc)).await.unwrap();
+        assert_eq!(clock.now(), start + Duration::hours(1));
+
+        clock.advance(Duration::minutes(5));
+        assert_eq!(clock.now(), start + Duration::minutes(65));
+    }
+
+    #[tokio::test]
+    async fn test_simulated_clock_with_end() {
+        let start = DateTime::parse_from_rfc3339("2022-06-11T12:00:00+01:00").unwrap();
+        let clock = SimulatedClock::new(start).with_end(start + Duration::hours(1));
+
+        // the clock can be moved up to its end...
+        clock
+            .sleep_until(start.with_timezone(&Utc) + Duration::hours(1))
+            .await
+            .unwrap();
+        assert_eq!(clock.now(), start + Duration::hours(1));
+
+        // ...but no further, so sleeping beyond it never returns
+        let sleep = clock.sleep_until(start.with_timezone(&Utc) + Duration::hours(2));
+        assert!(
+            tokio::time::timeout(std::time::Duration::from_millis(10), sleep)
+                .await
+                .is_err()
+        );
+        assert_eq!(clock.now(), start + Duration::hours(1));
+    }
+}
@@ -0,0 +1,235 @@
+//! Generation of crontab entries, as used by the `crontab` subcommand.
+//!
+//! Rather than keeping `heliocron wait` running all day, the times of an event can be calculated in advance, giving
+//! one crontab entry per day, each of which runs the command once at the minute nearest to the event. The times are
+//! given in the time zone in which cron runs, i.e. the local time zone of the system, whatever the time zone of the
+//! calculations:
+//!
+//! ```text
+//! # BEGIN heliocron lights
+//! 46 20 11 6 * /usr/local/bin/lights on
+//! 47 20 12 6 * /usr/local/bin/lights on
+//! # END heliocron lights
+//! ```
+//!
+//! The entries are kept between marker comments, so that the block can be replaced when it is regenerated without
+//! disturbing the rest of the crontab.
+use chrono::{DateTime, Datelike, Duration, FixedOffset, Timelike};
+
+use super::{calc, domain, utils};
+
+/// The first line of the block with the given name.
+fn begin_marker(name: &str) -> String {
+    format!("# BEGIN heliocron {name}")
+}
+
+/// The last line of the block with the given name.
+fn end_marker(name: &str) -> String {
+    format!("# END heliocron {name}")
+}
+
+/// Generate the block of crontab lines for the given number of days, starting from the date of the calculations, with
+/// the times in the time zone used by cron.
+///
+/// Days on which the event isn't due get a comment from `calc::Occurrence` instead of an entry, so that there is a line
+/// for every day.
+pub fn block(
+    scheduled: &domain::ScheduledCommand,
+    solar_calculations: &calc::SolarCalculations,
+    days: u32,
+    cron_time_zone: domain::TimeZone,
+    now: DateTime<FixedOffset>,
+) -> String {
+    let command = command_line(&scheduled.command);
+
+    let mut lines = vec![begin_marker(&scheduled.name)];
+    let occurrences = solar_calculations.scheduled_occurrences(
+        &scheduled.event,
+        &scheduled.adjustment,
+        days,
+        now,
+        |datetime| round_to_minute(cron_time_zone.from_utc_datetime(&datetime.naive_utc())),
+    );
+    for (date, occurrence) in occurrences {
+        lines.push(match occurrence {
+            calc::Occurrence::Due(datetime) => format!(
+                "{} {} {} {} * {command}",
+                datetime.minute(),
+                datetime.hour(),
+                datetime.day(),
+                datetime.month()
+            ),
+            occurrence => occurrence.comment(date),
+        });
+    }
+    lines.push(end_marker(&scheduled.name));
+
+    lines.join("\n") + "\n"
+}
+
+/// Replace the block with the given name in the crontab, or append it if there isn't one yet. Replacing a block with
+/// itself leaves the crontab unchanged.
+pub fn replace_block(crontab: &str, name: &str, block: &str) -> String {
+    let (begin, end) = (begin_marker(name), end_marker(name));
+    let lines: Vec<&str> = crontab.lines().collect();
+
+    let start = lines.iter().position(|line| line.trim_end() == begin);
+    let stop = start.and_then(|start| {
+        lines[start..]
+            .iter()
+            .position(|line| line.trim_end() == end)
+            .map(|stop| start + stop)
+    });
+
+    let mut crontab = String::new();
+    match (start, stop) {
+        (Some(start), Some(stop)) => {
+            for line in &lines[..start] {
+                crontab.push_str(line);
+                crontab.push('\n');
+            }
+            crontab.push_str(block);
+            for line in &lines[stop + 1..] {
+                crontab.push_str(line);
+                crontab.push('\n');
+            }
+        }
+        _ => {
+            for line in &lines {
+                crontab.push_str(line);
+                crontab.push('\n');
+            }
+            crontab.push_str(block);
+        }
+    }
+    crontab
+}
+
+/// Round to the nearest minute, as cron has no finer resolution.
+fn round_to_minute(datetime: DateTime<FixedOffset>) -> DateTime<FixedOffset> {
+    let datetime = datetime + Duration::seconds(30);
+    datetime
+        - Duration::seconds(datetime.second().into())
+        - Duration::nanoseconds(datetime.nanosecond().into())
+}
+
+/// Join the command and its arguments into a single line for `sh`, quoting where necessary. Percent signs are escaped
+/// because cron would otherwise treat them as newlines.
+fn command_line(command: &[String]) -> String {
+    utils::join_command(command, "_@%+=:,./-", |arg| {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    })
+    .replace('%', r"\%")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{scheduled_command, solar_calculations};
+
+    #[test]
+    fn test_block() {
+        // sunset is at 21:16:12 and 21:16:52 on these dates
+        let scheduled = scheduled_command(
+            "lights",
+            domain::EventName::Sunset,
+            Duration::minutes(-30),
+            &["lights", "on"],
+        );
+        let now = DateTime::parse_from_rfc3339("2022-06-11T12:00:00+01:00").unwrap();
+        let local = domain::TimeZone::parse("+01:00").unwrap();
+
+        assert_eq!(
+            block(&scheduled, &solar_calculations(51.4769), 2, local, now),
+            "# BEGIN heliocron lights\n46 20 11 6 * lights on\n47 20 12 6 * lights on\n# END heliocron lights\n"
+        );
+
+        // cron runs in the local time zone of the system, which is an hour behind that of the calculations here
+        let utc = domain::TimeZone::parse("+00:00").unwrap();
+        assert_eq!(
+            block(&scheduled, &solar_calculations(51.4769), 2, utc, now),
+            "# BEGIN heliocron lights\n46 19 11 6 * lights on\n47 19 12 6 * lights on\n# END heliocron lights\n"
+        );
+    }
+
+    #[test]
+    fn test_block_without_entries() {
+        let now = DateTime::parse_from_rfc3339("2022-06-11T12:00:00+01:00").unwrap();
+        let local = domain::TimeZone::parse("+01:00").unwrap();
+
+        // at 70 degrees north, the Sun doesn't set in June
+        let scheduled = scheduled_command(
+            "sun",
+            domain::EventName::Sunset,
+            Duration::zero(),
+            &["true"],
+        );
+        assert!(block(&scheduled, &solar_calculations(70.0), 1, local, now)
+            .contains("# 2022-06-11: the event does not occur on this day\n"));
+
+        // an event which has already passed would otherwise run on the same date next year
+        let scheduled = scheduled_command(
+            "sun",
+            domain::EventName::Sunrise,
+            Duration::zero(),
+            &["true"],
+        );
+        assert!(
+            block(&scheduled, &solar_calculations(51.4769), 1, local, now).contains(
+                "# 2022-06-11: the event has already passed, at 2022-06-11 04:43:00 +01:00\n"
+            )
+        );
+    }
+
+    #[test]
+    fn test_replace_block() {
+        let block = "# BEGIN heliocron lights\n1 2 3 4 * lights on\n# END heliocron lights\n";
+        let other = "# BEGIN heliocron other\n5 6 7 8 * other\n# END heliocron other\n";
+
+        // the block is appended if it isn't there yet...
+        let crontab = replace_block("MAILTO=me\n@reboot backup", "lights", block);
+        assert_eq!(crontab, format!("MAILTO=me\n@reboot backup\n{block}"));
+
+        // ...and is otherwise replaced in place, leaving everything else untouched
+        let crontab = replace_block(&format!("{crontab}{other}"), "lights", block);
+        assert_eq!(
+            crontab,
+            format!("MAILTO=me\n@reboot backup\n{block}{other}")
+        );
+
+        let updated = block.replace("lights on", "lights off");
+        let crontab = replace_block(&crontab, "lights", &updated);
+        assert_eq!(
+            crontab,
+            format!("MAILTO=me\n@reboot backup\n{updated}{other}")
+        );
+        assert_eq!(replace_block(&crontab, "lights", &updated), crontab);
+    }
+
+    #[test]
+    fn test_round_to_minute() {
+        let params = [
+            ("21:16:12", "21:16:00"),
+            ("21:16:30", "21:17:00"),
+            ("23:59:45", "00:00:00"),
+        ];
+
+        for (datetime, expected) in params {
+            let datetime =
+                DateTime::parse_from_rfc3339(&format!("2022-06-11T{datetime}+01:00")).unwrap();
+            assert_eq!(round_to_minute(datetime).time().to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn test_command_line() {
+        let command = |args: &[&str]| {
+            command_line(&args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>())
+        };
+
+        assert_eq!(command(&["/usr/bin/lights", "on"]), "/usr/bin/lights on");
+        assert_eq!(command(&["echo", "it's dark"]), r"echo 'it'\''s dark'");
+        assert_eq!(command(&["date", "+%H:%M"]), r"date +\%H:\%M");
+        assert_eq!(command(&["echo", ""]), "echo ''");
+    }
+}
@@ -1,12 +1,19 @@
+use std::collections::BTreeMap;
 use std::fmt;
 use std::ops::RangeInclusive;
+use std::path::PathBuf;
 
-use chrono::{DateTime, Duration, FixedOffset, NaiveTime};
+use chrono::{
+    DateTime, Duration, FixedOffset, Local, NaiveDate, NaiveDateTime, NaiveTime, Offset,
+    TimeZone as _,
+};
 use serde::Serialize;
 
+use super::template::Template;
+
 /// An enumeration of the different parts of the day. Not all of them necessarily occur during a
 /// given 24-hour period.
-#[derive(Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum DayPart {
     Day,
@@ -30,6 +37,18 @@ impl DayPart {
             Self::Day
         }
     }
+
+    /// The solar elevations at which the day part begins and ends, as used by `from_elevation_angle`. Night has no
+    /// lower bound and day has no upper bound.
+    pub fn elevation_bounds(&self) -> (Option<f64>, Option<f64>) {
+        match self {
+            Self::Night => (None, Some(-18.0)),
+            Self::AstronomicalTwilight => (Some(-18.0), Some(-12.0)),
+            Self::NauticalTwilight => (Some(-12.0), Some(-6.0)),
+            Self::CivilTwilight => (Some(-6.0), Some(0.833)),
+            Self::Day => (Some(0.833), None),
+        }
+    }
 }
 
 impl fmt::Display for DayPart {
@@ -48,19 +67,283 @@ impl fmt::Display for DayPart {
     }
 }
 
+/// An enumeration of the periods of particular light quality favoured by photographers, which overlay the parts of
+/// the day enumerated in `DayPart`.
+///
+/// Golden hour is when the Sun is between 4 degrees below and 6 degrees above the horizon, whilst blue hour is when
+/// the Sun is between 6 and 4 degrees below the horizon.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DayPartOverlay {
+    GoldenHour,
+    BlueHour,
+}
+
+impl DayPartOverlay {
+    pub fn from_elevation_angle(angle: f64) -> Option<Self> {
+        if (-6.0..-4.0).contains(&angle) {
+            Some(Self::BlueHour)
+        } else if (-4.0..6.0).contains(&angle) {
+            Some(Self::GoldenHour)
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for DayPartOverlay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::GoldenHour => "Golden Hour",
+                Self::BlueHour => "Blue Hour",
+            }
+        )
+    }
+}
+
+/// An enumeration of the principal phases of the Moon.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LunarPhase {
+    NewMoon,
+    WaxingCrescent,
+    FirstQuarter,
+    WaxingGibbous,
+    FullMoon,
+    WaningGibbous,
+    LastQuarter,
+    WaningCrescent,
+}
+
+impl LunarPhase {
+    /// Determine the phase from the elongation of the Moon, i.e. how many degrees east of the Sun it is along the
+    /// ecliptic. The principal phases occur at multiples of 90 degrees, and each is considered to last for about a
+    /// day, which is how far the Moon moves relative to the Sun in that time.
+    pub fn from_elongation(elongation: f64) -> Self {
+        // The Moon moves approximately 12.2 degrees per day relative to the Sun.
+        const HALF_DAY: f64 = 6.1;
+
+        let elongation = elongation.rem_euclid(360.0);
+        let nearest_quarter = (elongation / 90.0).round() * 90.0;
+        if (elongation - nearest_quarter).abs() <= HALF_DAY {
+            match nearest_quarter as u32 {
+                90 => Self::FirstQuarter,
+                180 => Self::FullMoon,
+                270 => Self::LastQuarter,
+                _ => Self::NewMoon,
+            }
+        } else {
+            match (elongation / 90.0) as u32 {
+                0 => Self::WaxingCrescent,
+                1 => Self::WaxingGibbous,
+                2 => Self::WaningGibbous,
+                _ => Self::WaningCrescent,
+            }
+        }
+    }
+}
+
+impl fmt::Display for LunarPhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::NewMoon => "New Moon",
+                Self::WaxingCrescent => "Waxing Crescent",
+                Self::FirstQuarter => "First Quarter",
+                Self::WaxingGibbous => "Waxing Gibbous",
+                Self::FullMoon => "Full Moon",
+                Self::WaningGibbous => "Waning Gibbous",
+                Self::LastQuarter => "Last Quarter",
+                Self::WaningCrescent => "Waning Crescent",
+            }
+        )
+    }
+}
+
 /// An enumeration of parsed commands.
 pub enum Action {
     Report {
-        json: bool,
+        output: Output,
+        custom_events: BTreeMap<String, CustomEvent>,
+    },
+    RangeReport {
+        range: DateRange,
+        output: Output,
+        custom_events: BTreeMap<String, CustomEvent>,
+    },
+    Get {
+        query: Query,
+        seconds: bool,
     },
     Wait {
         event: Event,
-        offset: Duration,
-        run_missed_task: bool,
+        adjustment: EventAdjustment,
+        missed_event: MissedEventHandling,
+        next: Option<u32>,
+        command: Vec<String>,
+        timeout: Option<Duration>,
+    },
+    WaitForElevation {
+        threshold: ElevationThreshold,
+        immediate: bool,
+        command: Vec<String>,
+        timeout: Option<Duration>,
     },
     Poll {
         watch: bool,
-        json: bool,
+        output: Output,
+    },
+    Next {
+        limit: UpcomingLimit,
+        output: Output,
+        custom_events: BTreeMap<String, CustomEvent>,
+    },
+    Daemon {
+        heliotab: PathBuf,
+        grace: Duration,
+    },
+    Crontab {
+        scheduled: ScheduledCommand,
+        days: u32,
+        install: Option<CrontabTarget>,
+    },
+    Systemd {
+        scheduled: ScheduledCommand,
+        days: u32,
+        output_dir: PathBuf,
+        system: bool,
+        regenerate: Vec<String>,
+    },
+    Ics {
+        range: DateRange,
+        events: Vec<RawEventName>,
+        polar: bool,
+    },
+    Locations {
+        locations: BTreeMap<String, Location>,
+        default: Option<String>,
+    },
+    Check {
+        condition: Condition,
+    },
+}
+
+/// A command to be run by another scheduler, such as cron or systemd, at the time of an event (+/- offset).
+pub struct ScheduledCommand {
+    /// Identifies the generated schedule, so that it can be replaced when regenerated.
+    pub name: String,
+    pub event: Event,
+    pub adjustment: EventAdjustment,
+    pub command: Vec<String>,
+}
+
+/// The crontab into which the `crontab` subcommand installs its entries.
+pub enum CrontabTarget {
+    /// The crontab of the current user, as managed by the `crontab` program.
+    User,
+    /// A file in the format of a user's crontab.
+    File(PathBuf),
+}
+
+/// The formats in which reports can be displayed.
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// Human-readable text, or an aligned table for a range of dates.
+    #[clap(alias = "table")]
+    Text,
+    Json,
+    /// One JSON object per line, e.g. one per day for a range of dates.
+    Ndjson,
+    Csv,
+    Yaml,
+    Toml,
+    /// A Markdown table.
+    Markdown,
+}
+
+/// The value printed by the `get` subcommand.
+pub enum Query {
+    /// The time of the event (+/- offset), once clamped.
+    EventTime {
+        event: Event,
+        adjustment: EventAdjustment,
+    },
+    DayLength,
+}
+
+/// How far ahead the `next` subcommand lists the upcoming events.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UpcomingLimit {
+    /// List this many events.
+    Count(usize),
+    /// List every event within this period.
+    Within(Duration),
+}
+
+/// How reports are displayed: in one of the formats, or by substituting their values into a template.
+pub enum Output {
+    Format(OutputFormat),
+    Template(Template),
+}
+
+/// What to do when waking up after the event has already occurred, e.g. because the device was suspended.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum MissedEventPolicy {
+    /// Skip the task if the wakeup was late at all, regardless of the grace period.
+    Skip,
+    /// Run the task if the wakeup was no later than the grace period, otherwise skip it.
+    RunIfWithinGrace,
+    /// Always run the task, however late the wakeup was.
+    Always,
+}
+
+/// Everything needed to decide whether a task should still be run after a late wakeup.
+pub struct MissedEventHandling {
+    pub policy: MissedEventPolicy,
+    /// How late the wakeup may be before the event is considered to have been missed.
+    pub grace: Duration,
+    /// The file to which a record of each wakeup is appended, if any.
+    pub audit_log: Option<PathBuf>,
+}
+
+impl MissedEventHandling {
+    /// Returns whether the task should be run, given how late the wakeup was.
+    pub fn should_run(&self, late_by: Duration) -> bool {
+        match self.policy {
+            // Sub-second delays are to be expected when scheduling, so they aren't counted as being late.
+            MissedEventPolicy::Skip => late_by.num_seconds() < 1,
+            MissedEventPolicy::RunIfWithinGrace => {
+                late_by.num_seconds() <= self.grace.num_seconds()
+            }
+            MissedEventPolicy::Always => true,
+        }
+    }
+}
+
+/// An enumeration of the conditions which can be tested with the `check` subcommand.
+pub enum Condition {
+    Day,
+    Night,
+    /// Any of civil, nautical or astronomical twilight.
+    Twilight,
+    CivilTwilight,
+    NauticalTwilight,
+    AstronomicalTwilight,
+    GoldenHour,
+    BlueHour,
+    /// The window which opens at one event (+/- offset) and closes at the next occurrence of another event
+    /// (+/- offset).
+    Between {
+        from: Event,
+        from_offset: Duration,
+        to: Event,
+        to_offset: Duration,
     },
 }
 
@@ -109,6 +392,82 @@ impl fmt::Display for EventTime {
     }
 }
 
+/// The time zone in which event times are calculated and displayed.
+///
+/// Named time zones are resolved through the IANA time zone database, so that each event time carries the UTC offset
+/// which is actually in force at that instant, rather than the offset in force at noon.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimeZone {
+    /// The local time zone of the system, including any daylight saving time transitions.
+    Local,
+    /// A fixed offset from UTC, which never changes.
+    Fixed(FixedOffset),
+    /// A time zone from the IANA time zone database, e.g. "Europe/London".
+    Named(chrono_tz::Tz),
+}
+
+impl TimeZone {
+    /// Create a new instance of `TimeZone` from an &str, which should either be a fixed offset in the format
+    /// '[+/-]HH:MM' or the name of a time zone in the IANA time zone database.
+    pub fn parse(tz: &str) -> Result<Self, String> {
+        // Use chrono's own parsing function to validate fixed offsets.
+        if let Ok(date) = DateTime::parse_from_str(&format!("2022-01-01T00:00:00{tz}"), "%FT%T%:z")
+        {
+            return Ok(Self::Fixed(*date.offset()));
+        }
+
+        tz.parse::<chrono_tz::Tz>().map(Self::Named).map_err(|_| {
+            format!(
+                "Invalid time zone - expected either an IANA time zone name, such as 'Europe/London', or the format '[+|-]HH:MM' between '-23:59' and '+23:59'. Found '{tz}'"
+            )
+        })
+    }
+
+    /// Return the UTC offset which is in force at the given UTC date and time.
+    pub fn offset_at(&self, utc: &NaiveDateTime) -> FixedOffset {
+        match self {
+            Self::Local => Local.offset_from_utc_datetime(utc),
+            Self::Fixed(offset) => *offset,
+            Self::Named(tz) => tz.offset_from_utc_datetime(utc).fix(),
+        }
+    }
+
+    /// Convert a UTC date and time into a date and time in this time zone.
+    pub fn from_utc_datetime(&self, utc: &NaiveDateTime) -> DateTime<FixedOffset> {
+        self.offset_at(utc).from_utc_datetime(utc)
+    }
+
+    /// Interpret a local date and time in this time zone.
+    ///
+    /// Ambiguous local times, which occur when the clocks go back, resolve to the earlier of the two instants. Local
+    /// times which are skipped when the clocks go forward are shifted forward by the length of the gap.
+    pub fn from_local_datetime(&self, local: &NaiveDateTime) -> DateTime<FixedOffset> {
+        let offset_before = self.offset_at(&(*local - Duration::days(1)));
+        let offset_after = self.offset_at(&(*local + Duration::days(1)));
+
+        for offset in [offset_before, offset_after] {
+            let utc = *local - Duration::seconds(offset.local_minus_utc() as i64);
+            if self.offset_at(&utc) == offset {
+                return offset.from_utc_datetime(&utc);
+            }
+        }
+
+        // Neither offset maps back onto the given local time, so it must fall in a gap.
+        let utc = *local - Duration::seconds(offset_before.local_minus_utc() as i64);
+        self.from_utc_datetime(&utc)
+    }
+}
+
+impl fmt::Display for TimeZone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Local => write!(f, "Local"),
+            Self::Fixed(offset) => write!(f, "{offset}"),
+            Self::Named(tz) => write!(f, "{}", tz.name()),
+        }
+    }
+}
+
 /// Newtype wrapper for validating an altitude between -90.0 and 90.0.
 #[derive(Clone)]
 pub struct Altitude(f64);
@@ -134,6 +493,12 @@ impl Altitude {
     }
 }
 
+impl fmt::Display for Altitude {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
 impl From<f64> for Altitude {
     fn from(alt: f64) -> Self {
         Self::new(alt).unwrap()
@@ -163,12 +528,31 @@ pub enum RawEventName {
     CustomAM,
     CustomPM,
     SolarNoon,
+    SolarMidnight,
+    MorningBlueHourStart,
+    MorningBlueHourEnd,
+    MorningGoldenHourStart,
+    MorningGoldenHourEnd,
+    EveningGoldenHourStart,
+    EveningGoldenHourEnd,
+    EveningBlueHourStart,
+    EveningBlueHourEnd,
+    Moonrise,
+    Moonset,
+}
+
+impl RawEventName {
+    /// Whether this is one of the custom events, which require an altitude.
+    pub fn is_custom(&self) -> bool {
+        matches!(self, Self::CustomAM | Self::CustomPM)
+    }
 }
 
 /// An enumeration of possible event names, with required data attached.
 ///
 /// For example, CustomAM/PM here include the custom altitude, in contrast to
 /// `RawEventName` where that data is absent.
+#[derive(Clone)]
 pub enum EventName {
     Sunrise,
     Sunset,
@@ -181,21 +565,118 @@ pub enum EventName {
     CustomAM(Altitude),
     CustomPM(Altitude),
     SolarNoon,
+    SolarMidnight,
+    MorningBlueHourStart,
+    MorningBlueHourEnd,
+    MorningGoldenHourStart,
+    MorningGoldenHourEnd,
+    EveningGoldenHourStart,
+    EveningGoldenHourEnd,
+    EveningBlueHourStart,
+    EveningBlueHourEnd,
+    Moonrise,
+    Moonset,
+}
+
+impl EventName {
+    /// Attach the custom altitude to a plain event name. The altitude is required by the custom events, and is
+    /// otherwise ignored.
+    pub fn new(
+        event_name: RawEventName,
+        custom_altitude: Option<Altitude>,
+    ) -> Result<Self, String> {
+        let missing_altitude = || "Custom events require an altitude".to_string();
+
+        let event_name = match event_name {
+            RawEventName::Sunrise => Self::Sunrise,
+            RawEventName::Sunset => Self::Sunset,
+            RawEventName::CivilDawn => Self::CivilDawn,
+            RawEventName::CivilDusk => Self::CivilDusk,
+            RawEventName::NauticalDawn => Self::NauticalDawn,
+            RawEventName::NauticalDusk => Self::NauticalDusk,
+            RawEventName::AstronomicalDawn => Self::AstronomicalDawn,
+            RawEventName::AstronomicalDusk => Self::AstronomicalDusk,
+            RawEventName::SolarNoon => Self::SolarNoon,
+            RawEventName::SolarMidnight => Self::SolarMidnight,
+            RawEventName::MorningBlueHourStart => Self::MorningBlueHourStart,
+            RawEventName::MorningBlueHourEnd => Self::MorningBlueHourEnd,
+            RawEventName::MorningGoldenHourStart => Self::MorningGoldenHourStart,
+            RawEventName::MorningGoldenHourEnd => Self::MorningGoldenHourEnd,
+            RawEventName::EveningGoldenHourStart => Self::EveningGoldenHourStart,
+            RawEventName::EveningGoldenHourEnd => Self::EveningGoldenHourEnd,
+            RawEventName::EveningBlueHourStart => Self::EveningBlueHourStart,
+            RawEventName::EveningBlueHourEnd => Self::EveningBlueHourEnd,
+            RawEventName::Moonrise => Self::Moonrise,
+            RawEventName::Moonset => Self::Moonset,
+            RawEventName::CustomAM => Self::CustomAM(custom_altitude.ok_or_else(missing_altitude)?),
+            RawEventName::CustomPM => Self::CustomPM(custom_altitude.ok_or_else(missing_altitude)?),
+        };
+        Ok(event_name)
+    }
 }
 
 /// The set of possible directions of travel for a celestial object relative to the obeserver, i.e.
 /// either ascending or descending.
+#[derive(Debug, Clone, Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Direction {
     Ascending,
     Descending,
 }
 
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Ascending => "Ascending",
+                Self::Descending => "Descending",
+            }
+        )
+    }
+}
+
+/// A threshold for the solar elevation, which is reached when the Sun crosses it in the given direction. That is, it
+/// rises above the threshold when ascending, or falls below it when descending.
+///
+/// So that small movements of the Sun back and forth across the threshold aren't counted as crossings, the Sun must
+/// first have been at least `hysteresis` degrees on the other side of the threshold.
+#[derive(Clone)]
+pub struct ElevationThreshold {
+    pub elevation: f64,
+    pub direction: Direction,
+    pub hysteresis: f64,
+}
+
+impl ElevationThreshold {
+    /// Whether the Sun is beyond the threshold at the given elevation.
+    pub fn is_met(&self, elevation: f64) -> bool {
+        match self.direction {
+            Direction::Ascending => elevation > self.elevation,
+            Direction::Descending => elevation < self.elevation,
+        }
+    }
+
+    /// Whether the Sun is far enough on the other side of the threshold for a subsequent crossing to count.
+    pub fn is_armed(&self, elevation: f64) -> bool {
+        match self.direction {
+            Direction::Ascending => elevation <= self.elevation - self.hysteresis,
+            Direction::Descending => elevation >= self.elevation + self.hysteresis,
+        }
+    }
+}
+
 /// Events which occur when the Sun reaches a specific elevation relative to the horizon.
 ///
 /// For example, sunrise always occurs when the centre of the Sun is 0.833 degrees below the horizon.
+#[derive(Clone)]
 pub struct FixedElevationEvent {
     pub degrees_below_horizon: Altitude,
     pub solar_direction: Direction,
+    /// Whether the event is defined relative to the visible horizon, which appears lower to an observer above sea
+    /// level, rather than relative to the geometric horizon.
+    pub visible_horizon: bool,
 }
 
 impl FixedElevationEvent {
@@ -203,6 +684,16 @@ impl FixedElevationEvent {
         Self {
             degrees_below_horizon,
             solar_direction,
+            visible_horizon: false,
+        }
+    }
+
+    /// Create an event for when the upper edge of the Sun touches the visible horizon, i.e. sunrise or sunset.
+    pub fn at_visible_horizon(solar_direction: Direction) -> Self {
+        Self {
+            degrees_below_horizon: 0.833.into(),
+            solar_direction,
+            visible_horizon: true,
         }
     }
 }
@@ -210,18 +701,31 @@ impl FixedElevationEvent {
 /// Events which occur when the Sun is at a variable elevation.
 ///
 /// For example, solar noon occurs at the maximum solar elevation, which varies based on time and location.
+/// Conversely, solar midnight occurs at the minimum solar elevation.
+#[derive(Clone)]
 pub enum VariableElevationEvent {
     SolarNoon,
+    SolarMidnight,
 }
 
-/// Any supported solar event.
+/// Events which relate to the Moon, rather than the Sun.
+#[derive(Clone)]
+pub enum LunarEvent {
+    Moonrise,
+    Moonset,
+}
+
+/// Any supported solar or lunar event.
 ///
 /// Some events, such as sunrise and sunset, occur when the Sun is at a specific altitude relative to the horizon,
 /// but other events, such as solar noon, occur not at a fixed altitude, but a variable one. Each of these has a
-/// different way of calculating the time of the event, hence they are separated into two variants.
+/// different way of calculating the time of the event, hence they are separated into two variants. Lunar events
+/// are calculated separately again.
+#[derive(Clone)]
 pub enum Event {
     Fixed(FixedElevationEvent),
     Variable(VariableElevationEvent),
+    Lunar(LunarEvent),
 }
 
 impl Event {
@@ -229,11 +733,10 @@ impl Event {
         // We can just use `.into()` (a method which can panic) for these float conversions because we can manually
         // verify that all of them are valid altitudes.
         match event {
-            EventName::Sunrise => {
-                Self::Fixed(FixedElevationEvent::new(0.833.into(), Direction::Ascending))
-            }
-            EventName::Sunset => Self::Fixed(FixedElevationEvent::new(
-                0.833.into(),
+            EventName::Sunrise => Self::Fixed(FixedElevationEvent::at_visible_horizon(
+                Direction::Ascending,
+            )),
+            EventName::Sunset => Self::Fixed(FixedElevationEvent::at_visible_horizon(
                 Direction::Descending,
             )),
             EventName::CivilDawn => {
@@ -261,12 +764,180 @@ impl Event {
                 Self::Fixed(FixedElevationEvent::new(alt, Direction::Descending))
             }
             EventName::SolarNoon => Self::Variable(VariableElevationEvent::SolarNoon),
+            EventName::SolarMidnight => Self::Variable(VariableElevationEvent::SolarMidnight),
+            EventName::MorningBlueHourStart => {
+                Self::Fixed(FixedElevationEvent::new(6.0.into(), Direction::Ascending))
+            }
+            EventName::MorningBlueHourEnd => {
+                Self::Fixed(FixedElevationEvent::new(4.0.into(), Direction::Ascending))
+            }
+            EventName::MorningGoldenHourStart => {
+                Self::Fixed(FixedElevationEvent::new(4.0.into(), Direction::Ascending))
+            }
+            EventName::MorningGoldenHourEnd => Self::Fixed(FixedElevationEvent::new(
+                (-6.0).into(),
+                Direction::Ascending,
+            )),
+            EventName::EveningGoldenHourStart => Self::Fixed(FixedElevationEvent::new(
+                (-6.0).into(),
+                Direction::Descending,
+            )),
+            EventName::EveningGoldenHourEnd => {
+                Self::Fixed(FixedElevationEvent::new(4.0.into(), Direction::Descending))
+            }
+            EventName::EveningBlueHourStart => {
+                Self::Fixed(FixedElevationEvent::new(4.0.into(), Direction::Descending))
+            }
+            EventName::EveningBlueHourEnd => {
+                Self::Fixed(FixedElevationEvent::new(6.0.into(), Direction::Descending))
+            }
+            EventName::Moonrise => Self::Lunar(LunarEvent::Moonrise),
+            EventName::Moonset => Self::Lunar(LunarEvent::Moonset),
         }
     }
 }
 
+/// How the time at which to act is derived from the time of an event. The offset and jitter are added to the event
+/// time, which is then clamped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EventAdjustment {
+    pub offset: Duration,
+    pub jitter: Option<Jitter>,
+    pub clamp: Clamp,
+}
+
+/// A random shift of up to `max` either side of the event (+/- offset), so that it doesn't occur at exactly the same
+/// time each day.
+///
+/// The same seed always gives the same shift, on every platform and with every version of heliocron. Without a seed,
+/// the shift is different every time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Jitter {
+    pub max: Duration,
+    pub seed: Option<String>,
+}
+
+impl Jitter {
+    /// Choose the shift, to the nearest second, between -max and +max inclusive.
+    pub fn shift(&self) -> Duration {
+        let seed = match &self.seed {
+            Some(seed) => fnv1a(seed.as_bytes()),
+            None => {
+                let now = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap_or_default();
+                fnv1a(&now.as_nanos().to_le_bytes()) ^ u64::from(std::process::id())
+            }
+        };
+
+        let max = self.max.num_seconds().unsigned_abs();
+        let shift = splitmix64(seed) % (2 * max + 1);
+        Duration::seconds(shift as i64 - max as i64)
+    }
+}
+
+/// The 64-bit FNV-1a hash, which, unlike the hasher in the standard library, is guaranteed never to change.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// The output function of the SplitMix64 generator, which spreads similar seeds across the whole range of a u64.
+fn splitmix64(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+/// The earliest and latest times of day at which an event (+/- offset) may be considered to occur. An event which
+/// occurs outside of these bounds is moved to the nearest of them.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Clamp {
+    pub not_before: Option<NaiveTime>,
+    pub not_after: Option<NaiveTime>,
+}
+
+impl Clamp {
+    pub fn new(
+        not_before: Option<NaiveTime>,
+        not_after: Option<NaiveTime>,
+    ) -> Result<Self, String> {
+        match (not_before, not_after) {
+            (Some(not_before), Some(not_after)) if not_before > not_after => Err(format!(
+                "The earliest time must not be later than the latest time. Found '{not_before}' and '{not_after}'."
+            )),
+            _ => Ok(Self {
+                not_before,
+                not_after,
+            }),
+        }
+    }
+
+    /// Move the date and time to within the bounds, on the same date in the given time zone.
+    pub fn apply(
+        &self,
+        datetime: DateTime<FixedOffset>,
+        time_zone: TimeZone,
+    ) -> DateTime<FixedOffset> {
+        let date = datetime.date().naive_local();
+        match (self.not_before, self.not_after) {
+            (Some(not_before), _) if datetime.time() < not_before => {
+                time_zone.from_local_datetime(&date.and_time(not_before))
+            }
+            (_, Some(not_after)) if datetime.time() > not_after => {
+                time_zone.from_local_datetime(&date.and_time(not_after))
+            }
+            _ => datetime,
+        }
+    }
+}
+
+/// The dates from one date up to and including another, such as those covered by a calendar.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DateRange {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+}
+
+impl DateRange {
+    /// The greatest number of days in a range.
+    pub const MAX_DAYS: i64 = 3660;
+
+    pub fn new(from: NaiveDate, to: NaiveDate) -> Result<Self, String> {
+        if from > to {
+            return Err(format!(
+                "The first date must not be later than the last date. Found '{from}' and '{to}'."
+            ));
+        }
+        match (to - from).num_days() + 1 {
+            days if days > Self::MAX_DAYS => Err(format!(
+                "The range must not be longer than {} days. Found {days} days from '{from}' to '{to}'.",
+                Self::MAX_DAYS
+            )),
+            _ => Ok(Self { from, to }),
+        }
+    }
+
+    pub fn dates(&self) -> impl Iterator<Item = NaiveDate> {
+        let from = self.from;
+        (0..=(self.to - from).num_days()).map(move |days| from + Duration::days(days))
+    }
+}
+
+/// An event defined in the configuration file, which occurs when the Sun reaches a given altitude, and is then
+/// offset and clamped.
+#[derive(Clone)]
+pub struct CustomEvent {
+    pub event: FixedElevationEvent,
+    pub offset: Duration,
+    pub clamp: Clamp,
+}
+
 const LATITUDE_RANGE: RangeInclusive<f64> = RangeInclusive::new(-90.0, 90.0);
 const LONGITUDE_RANGE: RangeInclusive<f64> = RangeInclusive::new(-180.0, 180.0);
+const OBSERVER_ELEVATION_RANGE: RangeInclusive<f64> = RangeInclusive::new(0.0, 10000.0);
 
 /// Represents a latitude in decimal degrees. Valid values are from -90.0..=+90.0.
 /// Positive values are to the north, whilst negative values are to the south.
@@ -366,6 +1037,35 @@ impl Coordinates {
     }
 }
 
+/// A named location from the configuration file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Location {
+    pub coordinates: Coordinates,
+    /// The time zone of the location, if it differs from the default time zone.
+    pub time_zone: Option<TimeZone>,
+    /// The elevation of the observer in metres above sea level.
+    pub elevation: f64,
+}
+
+impl Location {
+    pub fn new(
+        coordinates: Coordinates,
+        time_zone: Option<TimeZone>,
+        elevation: f64,
+    ) -> Result<Self, String> {
+        match OBSERVER_ELEVATION_RANGE.contains(&elevation) {
+            true => Ok(Self {
+                coordinates,
+                time_zone,
+                elevation,
+            }),
+            false => Err(format!(
+                "Elevation must be between 0.0 and 10000.0 metres, inclusive. Found '{elevation}'."
+            )),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -499,4 +1199,222 @@ mod tests {
         let expected = "Never";
         assert_eq!(et.to_string(), expected);
     }
+
+    #[test]
+    fn test_parse_time_zone() {
+        assert_eq!(
+            TimeZone::parse("+01:30").unwrap(),
+            TimeZone::Fixed(FixedOffset::east(5400))
+        );
+        assert_eq!(
+            TimeZone::parse("-05:00").unwrap(),
+            TimeZone::Fixed(FixedOffset::west(18000))
+        );
+        assert_eq!(
+            TimeZone::parse("Europe/London").unwrap(),
+            TimeZone::Named(chrono_tz::Europe::London)
+        );
+
+        for tz in &["+24:00", "01:00", "Europe/Narnia", ""] {
+            assert!(TimeZone::parse(tz).is_err());
+        }
+    }
+
+    #[test]
+    fn test_time_zone_from_local_datetime() {
+        let tz = TimeZone::parse("Europe/London").unwrap();
+        let params = [
+            // a regular winter time
+            ("2022-01-01T12:00:00", "2022-01-01T12:00:00+00:00"),
+            // a regular summer time
+            ("2022-06-01T12:00:00", "2022-06-01T12:00:00+01:00"),
+            // skipped when the clocks go forward
+            ("2022-03-27T01:30:00", "2022-03-27T02:30:00+01:00"),
+            // ambiguous when the clocks go back
+            ("2022-10-30T01:30:00", "2022-10-30T01:30:00+01:00"),
+        ];
+
+        for (local, expected) in params.iter() {
+            let local = NaiveDateTime::parse_from_str(local, "%FT%T").unwrap();
+            assert_eq!(*expected, tz.from_local_datetime(&local).to_rfc3339());
+        }
+    }
+
+    #[test]
+    fn test_lunar_phase_from_elongation() {
+        let params = [
+            (0.0, LunarPhase::NewMoon),
+            (6.0, LunarPhase::NewMoon),
+            (6.2, LunarPhase::WaxingCrescent),
+            (70.0, LunarPhase::WaxingCrescent),
+            (85.0, LunarPhase::FirstQuarter),
+            (95.0, LunarPhase::FirstQuarter),
+            (135.0, LunarPhase::WaxingGibbous),
+            (180.0, LunarPhase::FullMoon),
+            (225.0, LunarPhase::WaningGibbous),
+            (270.0, LunarPhase::LastQuarter),
+            (315.0, LunarPhase::WaningCrescent),
+            (355.0, LunarPhase::NewMoon),
+            (360.0, LunarPhase::NewMoon),
+        ];
+
+        for (elongation, expected) in params.iter() {
+            assert_eq!(LunarPhase::from_elongation(*elongation), *expected);
+        }
+    }
+
+    #[test]
+    fn test_day_part_elevation_bounds() {
+        let day_parts = [
+            DayPart::Night,
+            DayPart::AstronomicalTwilight,
+            DayPart::NauticalTwilight,
+            DayPart::CivilTwilight,
+            DayPart::Day,
+        ];
+
+        // each day part begins at its lower bound and ends at its upper bound, where the next one begins
+        for (index, day_part) in day_parts.iter().enumerate() {
+            let (lower, upper) = day_part.elevation_bounds();
+            assert_eq!(lower.is_none(), index == 0);
+            assert_eq!(upper.is_none(), index == day_parts.len() - 1);
+
+            if let Some(lower) = lower {
+                assert_eq!(DayPart::from_elevation_angle(lower), *day_part);
+            }
+            if let Some(upper) = upper {
+                assert_eq!(DayPart::from_elevation_angle(upper - 0.001), *day_part);
+                assert_eq!(DayPart::from_elevation_angle(upper), day_parts[index + 1]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_day_part_overlay_from_elevation_angle() {
+        let params = [
+            (-10.0, None),
+            (-6.0, Some(DayPartOverlay::BlueHour)),
+            (-4.1, Some(DayPartOverlay::BlueHour)),
+            (-4.0, Some(DayPartOverlay::GoldenHour)),
+            (0.0, Some(DayPartOverlay::GoldenHour)),
+            (5.9, Some(DayPartOverlay::GoldenHour)),
+            (6.0, None),
+        ];
+
+        for (angle, expected) in params.iter() {
+            assert_eq!(DayPartOverlay::from_elevation_angle(*angle), *expected);
+        }
+    }
+
+    #[test]
+    fn test_missed_event_should_run() {
+        let handling = |policy| MissedEventHandling {
+            policy,
+            grace: Duration::seconds(30),
+            audit_log: None,
+        };
+
+        let params = [
+            (MissedEventPolicy::Skip, 0, true),
+            (MissedEventPolicy::Skip, 1, false),
+            (MissedEventPolicy::RunIfWithinGrace, 30, true),
+            (MissedEventPolicy::RunIfWithinGrace, 31, false),
+            (MissedEventPolicy::Always, 86400, true),
+        ];
+
+        for (policy, late_by, expected) in params {
+            assert_eq!(
+                handling(policy).should_run(Duration::milliseconds(late_by * 1000 + 500)),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_new_location() {
+        let coordinates = Coordinates::new(
+            Latitude::new(51.4769).unwrap(),
+            Longitude::new(-0.0005).unwrap(),
+        );
+
+        for elevation in [0.0, 1500.0, 10000.0] {
+            assert!(Location::new(coordinates.clone(), None, elevation).is_ok());
+        }
+        for elevation in [-0.1, 10000.1] {
+            assert!(Location::new(coordinates.clone(), None, elevation).is_err());
+        }
+    }
+
+    #[test]
+    fn test_clamp() {
+        let time_zone = TimeZone::parse("+01:00").unwrap();
+        let at =
+            |time: &str| DateTime::parse_from_rfc3339(&format!("2022-06-11T{time}+01:00")).unwrap();
+        let clamp = Clamp::new(
+            Some(NaiveTime::from_hms(17, 0, 0)),
+            Some(NaiveTime::from_hms(21, 30, 0)),
+        )
+        .unwrap();
+
+        assert_eq!(clamp.apply(at("16:59:59"), time_zone), at("17:00:00"));
+        assert_eq!(clamp.apply(at("19:00:00"), time_zone), at("19:00:00"));
+        assert_eq!(clamp.apply(at("21:37:38"), time_zone), at("21:30:00"));
+        assert_eq!(
+            Clamp::default().apply(at("21:37:38"), time_zone),
+            at("21:37:38")
+        );
+
+        assert!(Clamp::new(
+            Some(NaiveTime::from_hms(21, 30, 0)),
+            Some(NaiveTime::from_hms(17, 0, 0))
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_date_range() {
+        let range = DateRange::new(
+            NaiveDate::from_ymd(2022, 12, 30),
+            NaiveDate::from_ymd(2023, 1, 2),
+        )
+        .unwrap();
+        let dates: Vec<_> = range.dates().map(|date| date.to_string()).collect();
+        assert_eq!(
+            dates,
+            ["2022-12-30", "2022-12-31", "2023-01-01", "2023-01-02"]
+        );
+
+        let day = NaiveDate::from_ymd(2022, 6, 11);
+        assert_eq!(DateRange::new(day, day).unwrap().dates().count(), 1);
+        assert!(DateRange::new(day, NaiveDate::from_ymd(2022, 6, 10)).is_err());
+
+        let last = day + Duration::days(DateRange::MAX_DAYS - 1);
+        assert!(DateRange::new(day, last).is_ok());
+        assert!(DateRange::new(day, last + Duration::days(1)).is_err());
+    }
+
+    #[test]
+    fn test_jitter_shift() {
+        let jitter = |seed: Option<&str>| Jitter {
+            max: Duration::minutes(15),
+            seed: seed.map(String::from),
+        };
+
+        // the same seed always gives the same shift, whilst different seeds give different shifts
+        let shift = jitter(Some("2022-06-11-porch")).shift();
+        assert_eq!(shift, jitter(Some("2022-06-11-porch")).shift());
+        assert_ne!(shift, jitter(Some("2022-06-12-porch")).shift());
+
+        for seed in 0..1000 {
+            let shift = jitter(Some(&seed.to_string())).shift();
+            assert!(shift.num_seconds().abs() <= 15 * 60);
+        }
+        assert!(jitter(None).shift().num_seconds().abs() <= 15 * 60);
+
+        let zero = Jitter {
+            max: Duration::zero(),
+            seed: None,
+        };
+        assert_eq!(zero.shift(), Duration::zero());
+    }
 }
@@ -1,7 +1,7 @@
 use std::error::Error;
 
 use crate::sleep;
-use chrono::{self, DateTime, FixedOffset};
+use chrono::{self, DateTime, Duration, FixedOffset};
 
 #[derive(Debug)]
 pub enum HeliocronError {
@@ -17,6 +17,15 @@ pub enum ConfigErrorKind {
     ParseAltitude,
     ParseOffset,
     InvalidEvent,
+    InvalidHeliotab(String),
+    InvalidLocation(String),
+    InvalidCustomEvent(String),
+    InvalidConfigFile(String),
+    InvalidClamp(String),
+    InvalidOutputDir(String),
+    InvalidDateRange(String),
+    InvalidTemplate(String),
+    InvalidArguments(String),
 }
 
 impl ConfigErrorKind {
@@ -36,6 +45,15 @@ impl ConfigErrorKind {
                 "Error parsing offset. Expected a string in the format HH:MM:SS or HH:MM."
             }
             ConfigErrorKind::InvalidEvent => "Error parsing event.",
+            ConfigErrorKind::InvalidHeliotab(ref msg) => msg,
+            ConfigErrorKind::InvalidLocation(ref msg) => msg,
+            ConfigErrorKind::InvalidCustomEvent(ref msg) => msg,
+            ConfigErrorKind::InvalidConfigFile(ref msg) => msg,
+            ConfigErrorKind::InvalidClamp(ref msg) => msg,
+            ConfigErrorKind::InvalidOutputDir(ref msg) => msg,
+            ConfigErrorKind::InvalidDateRange(ref msg) => msg,
+            ConfigErrorKind::InvalidTemplate(ref msg) => msg,
+            ConfigErrorKind::InvalidArguments(ref msg) => msg,
         }
     }
 }
@@ -43,9 +61,14 @@ impl ConfigErrorKind {
 #[derive(Debug)]
 pub enum RuntimeErrorKind {
     NonOccurringEvent,
+    NoOccurrenceWithin(u32),
     PastEvent(DateTime<FixedOffset>),
     EventMissed(i64),
     SleepError(sleep::Error),
+    CommandFailed(String),
+    CommandTimedOut(Duration),
+    CrontabInstallFailed(String),
+    SystemdUnitsFailed(String),
 }
 
 impl std::fmt::Display for HeliocronError {
@@ -62,6 +85,18 @@ impl std::fmt::Display for HeliocronError {
                     ConfigErrorKind::ParseAltitude => err.as_str().to_string(),
                     ConfigErrorKind::ParseOffset => err.as_str().to_string(),
                     ConfigErrorKind::InvalidEvent => err.as_str().to_string(),
+                    ConfigErrorKind::InvalidHeliotab(msg) => format!("Invalid heliotab - {msg}"),
+                    ConfigErrorKind::InvalidLocation(msg) => format!("Invalid location - {msg}"),
+                    ConfigErrorKind::InvalidCustomEvent(msg) =>
+                        format!("Invalid custom event - {msg}"),
+                    ConfigErrorKind::InvalidConfigFile(msg) =>
+                        format!("Invalid configuration file - {msg}"),
+                    ConfigErrorKind::InvalidClamp(msg) => format!("Invalid clamp - {msg}"),
+                    ConfigErrorKind::InvalidOutputDir(msg) =>
+                        format!("Invalid output directory - {msg}"),
+                    ConfigErrorKind::InvalidDateRange(msg) => format!("Invalid date range - {msg}"),
+                    ConfigErrorKind::InvalidTemplate(msg) => format!("Invalid template - {msg}"),
+                    ConfigErrorKind::InvalidArguments(msg) => format!("Invalid arguments - {msg}"),
                 }
             ),
             Self::Runtime(ref err) => write!(
@@ -70,17 +105,45 @@ impl std::fmt::Display for HeliocronError {
                 match err {
                     RuntimeErrorKind::NonOccurringEvent =>
                         "The chosen event does not occur on this day.".to_string(),
+                    RuntimeErrorKind::NoOccurrenceWithin(days) => format!(
+                        "The chosen event does not occur again within the next {days} days."
+                    ),
                     RuntimeErrorKind::PastEvent(when) => {
                         format!("The chosen event occurred in the past: {when}. Cannot wait a negative amount of time.")
                     }
                     RuntimeErrorKind::EventMissed(by) => format!("Event missed by {by}s"),
                     RuntimeErrorKind::SleepError(e) => e.to_string(),
+                    RuntimeErrorKind::CommandFailed(msg) => format!("The command failed - {msg}"),
+                    RuntimeErrorKind::CommandTimedOut(after) => format!(
+                        "The command timed out after {}s and was terminated",
+                        after.num_seconds()
+                    ),
+                    RuntimeErrorKind::CrontabInstallFailed(msg) =>
+                        format!("Couldn't install the crontab - {msg}"),
+                    RuntimeErrorKind::SystemdUnitsFailed(msg) =>
+                        format!("Couldn't write the systemd units - {msg}"),
                 }
             ),
         }
     }
 }
 
+impl HeliocronError {
+    /// The exit status with which the program should terminate because of this error, so that scripts can tell
+    /// the different failure modes apart.
+    pub fn exit_code(&self) -> i32 {
+        match *self {
+            Self::Runtime(RuntimeErrorKind::PastEvent(_) | RuntimeErrorKind::EventMissed(_)) => 3,
+            Self::Runtime(
+                RuntimeErrorKind::NonOccurringEvent | RuntimeErrorKind::NoOccurrenceWithin(_),
+            ) => 4,
+            Self::Runtime(RuntimeErrorKind::CommandFailed(_)) => 5,
+            Self::Runtime(RuntimeErrorKind::CommandTimedOut(_)) => 6,
+            _ => 1,
+        }
+    }
+}
+
 impl Error for HeliocronError {}
 
 impl From<chrono::ParseError> for HeliocronError {
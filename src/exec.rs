@@ -0,0 +1,116 @@
+use std::process::ExitStatus;
+
+use chrono::Duration;
+use tokio::{
+    process::{Child, Command},
+    signal::unix::{signal, Signal, SignalKind},
+    task::JoinHandle,
+};
+
+use super::errors::{HeliocronError, RuntimeErrorKind};
+
+type Result<T> = std::result::Result<T, HeliocronError>;
+
+/// The signals which are passed on to the child process, rather than terminating heliocron.
+const FORWARDED_SIGNALS: [libc::c_int; 6] = [
+    libc::SIGHUP,
+    libc::SIGINT,
+    libc::SIGQUIT,
+    libc::SIGTERM,
+    libc::SIGUSR1,
+    libc::SIGUSR2,
+];
+
+/// How long a command is given to exit after being sent SIGTERM, before it is killed outright.
+const TERMINATION_GRACE_PERIOD: std::time::Duration = std::time::Duration::from_secs(5);
+
+fn command_failed(msg: String) -> HeliocronError {
+    HeliocronError::Runtime(RuntimeErrorKind::CommandFailed(msg))
+}
+
+/// Run a command to completion, forwarding any signals received in the meantime. If a timeout is given and the
+/// command is still running once it has elapsed, the command is terminated.
+pub async fn run(command: &[String], timeout: Option<Duration>) -> Result<ExitStatus> {
+    let (program, args) = command
+        .split_first()
+        .ok_or_else(|| command_failed("no command was given".to_string()))?;
+
+    // The handlers must be registered before spawning the command, so that no signals slip through in between.
+    let mut signals = Vec::with_capacity(FORWARDED_SIGNALS.len());
+    for signum in FORWARDED_SIGNALS {
+        let stream = signal(SignalKind::from_raw(signum))
+            .map_err(|e| command_failed(format!("couldn't forward signals: {e}")))?;
+        signals.push((signum, stream));
+    }
+
+    let mut child = Command::new(program)
+        .args(args)
+        .spawn()
+        .map_err(|e| command_failed(format!("couldn't run '{program}': {e}")))?;
+
+    // The forwarding stops as soon as this is dropped, i.e. once the command has finished.
+    let _forwarders = Forwarders(match child.id() {
+        Some(pid) => signals
+            .into_iter()
+            .map(|(signum, stream)| forward(pid, signum, stream))
+            .collect(),
+        None => Vec::new(),
+    });
+
+    let status = match timeout {
+        // Safe to unwrap because the timeout is validated to be positive when parsed.
+        Some(timeout) => {
+            match tokio::time::timeout(timeout.to_std().unwrap(), child.wait()).await {
+                Ok(status) => status,
+                Err(_) => {
+                    terminate(&mut child).await;
+                    return Err(HeliocronError::Runtime(RuntimeErrorKind::CommandTimedOut(
+                        timeout,
+                    )));
+                }
+            }
+        }
+        None => child.wait().await,
+    };
+
+    status.map_err(|e| command_failed(format!("couldn't wait for '{program}': {e}")))
+}
+
+/// The tasks forwarding signals to a running command, which are aborted when dropped.
+struct Forwarders(Vec<JoinHandle<()>>);
+
+impl Drop for Forwarders {
+    fn drop(&mut self) {
+        for forwarder in &self.0 {
+            forwarder.abort();
+        }
+    }
+}
+
+/// Pass on every occurrence of the given signal to the process with the given PID, until the task is aborted.
+fn forward(pid: u32, signum: libc::c_int, mut stream: Signal) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        while stream.recv().await.is_some() {
+            unsafe {
+                libc::kill(pid as libc::pid_t, signum);
+            }
+        }
+    })
+}
+
+/// Ask the command to exit with SIGTERM and, failing that, kill it.
+async fn terminate(child: &mut Child) {
+    if let Some(pid) = child.id() {
+        unsafe {
+            libc::kill(pid as libc::pid_t, libc::SIGTERM);
+        }
+        if tokio::time::timeout(TERMINATION_GRACE_PERIOD, child.wait())
+            .await
+            .is_ok()
+        {
+            return;
+        }
+    }
+    // The command may have exited of its own accord in the meantime, in which case there is nothing to kill.
+    let _ = child.kill().await;
+}
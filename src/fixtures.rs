@@ -0,0 +1,35 @@
+//! Fixtures shared by the unit tests of the modules which generate schedules and calendars.
+use chrono::{Duration, NaiveDate};
+
+use crate::calc;
+use crate::domain::{self, Coordinates, Latitude, Longitude};
+
+/// The calculations for midday on 2022-06-11 at the given latitude, near Greenwich, in the +01:00 time zone.
+pub fn solar_calculations(latitude: f64) -> calc::SolarCalculations {
+    let time_zone = domain::TimeZone::parse("+01:00").unwrap();
+    let date = time_zone.from_local_datetime(&NaiveDate::from_ymd(2022, 6, 11).and_hms(12, 0, 0));
+    let coordinates = Coordinates::new(
+        Latitude::new(latitude).unwrap(),
+        Longitude::new(-0.0005).unwrap(),
+    );
+    calc::SolarCalculations::new(date, coordinates).with_time_zone(time_zone)
+}
+
+/// A command scheduled at the event (+/- offset), without any jitter or clamping.
+pub fn scheduled_command(
+    name: &str,
+    event: domain::EventName,
+    offset: Duration,
+    command: &[&str],
+) -> domain::ScheduledCommand {
+    domain::ScheduledCommand {
+        name: name.to_string(),
+        event: domain::Event::from_event_name(event),
+        adjustment: domain::EventAdjustment {
+            offset,
+            jitter: None,
+            clamp: domain::Clamp::default(),
+        },
+        command: command.iter().map(|arg| arg.to_string()).collect(),
+    }
+}
@@ -0,0 +1,344 @@
+//! Parsing and scheduling of heliotab files, as used by the `daemon` subcommand.
+//!
+//! A heliotab is similar to a crontab, except that jobs are scheduled relative to solar events. Each non-empty line
+//! which is not a comment consists of four whitespace-separated fields:
+//!
+//! ```text
+//! # event[:altitude]  offset   days      command
+//! sunset              -00:30   *         /usr/local/bin/lights on
+//! custom_am:-3.5      00:00    mon-fri   echo "good morning" >> /tmp/greetings
+//! ```
+//!
+//! The days field is either `*`, meaning every day, or a comma-separated list of weekdays and weekday ranges, such
+//! as `mon,wed,fri` or `sat-sun`. Everything after the days field is the command, which is run with `sh -c`.
+use std::{fs, path::Path};
+
+use chrono::{DateTime, Datelike, Duration, FixedOffset, Weekday};
+
+use super::{
+    calc, cli,
+    domain::{self, Altitude, EventName, RawEventName},
+    errors::{ConfigErrorKind, HeliocronError},
+};
+
+type Result<T, E = HeliocronError> = std::result::Result<T, E>;
+
+/// The set of weekdays on which a heliotab entry should run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Days([bool; 7]);
+
+impl Days {
+    pub fn every_day() -> Self {
+        Self([true; 7])
+    }
+
+    pub fn contains(&self, weekday: Weekday) -> bool {
+        self.0[weekday.num_days_from_monday() as usize]
+    }
+
+    pub fn parse(days: &str) -> Result<Self, String> {
+        if days == "*" {
+            return Ok(Self::every_day());
+        }
+
+        let parse_weekday = |day: &str| {
+            day.parse::<Weekday>()
+                .map_err(|_| format!("Expected a day of the week, such as 'mon'. Found '{day}'"))
+        };
+
+        let mut selected = [false; 7];
+        for part in days.split(',') {
+            match part.split_once('-') {
+                // Ranges are allowed to wrap around the end of the week, e.g. "fri-mon".
+                Some((start, end)) => {
+                    let mut day = parse_weekday(start)?;
+                    let end = parse_weekday(end)?;
+                    selected[day.num_days_from_monday() as usize] = true;
+                    while day != end {
+                        day = day.succ();
+                        selected[day.num_days_from_monday() as usize] = true;
+                    }
+                }
+                None => selected[parse_weekday(part)?.num_days_from_monday() as usize] = true,
+            }
+        }
+        Ok(Self(selected))
+    }
+}
+
+/// Split off the first whitespace-delimited field, returning it along with the remainder of the text.
+fn split_field(text: &str) -> (&str, &str) {
+    let text = text.trim_start();
+    match text.find(char::is_whitespace) {
+        Some(i) => text.split_at(i),
+        None => (text, ""),
+    }
+}
+
+/// A single job from a heliotab file.
+#[derive(Clone)]
+pub struct Entry {
+    /// The line number of the entry, used to identify it in log messages.
+    pub line: usize,
+    /// The event exactly as it was written in the heliotab.
+    pub event_spec: String,
+    pub event_name: EventName,
+    pub offset: Duration,
+    pub days: Days,
+    pub command: String,
+}
+
+impl Entry {
+    fn parse(line: usize, text: &str) -> Result<Self, String> {
+        let mut rest = text;
+        let mut next_field = |name: &str| {
+            let (field, remainder) = split_field(rest);
+            rest = remainder;
+            match field.is_empty() {
+                true => Err(format!("Missing {name}")),
+                false => Ok(field),
+            }
+        };
+
+        let event_spec = next_field("event")?;
+        let offset = next_field("offset")?;
+        let days = next_field("days")?;
+        let command = rest.trim();
+        if command.is_empty() {
+            return Err("Missing command".to_string());
+        }
+
+        let (event, altitude) = match event_spec.split_once(':') {
+            Some((event, altitude)) => (event, Some(Altitude::parse(altitude)?)),
+            None => (event_spec, None),
+        };
+        let event = <RawEventName as clap::ValueEnum>::from_str(event, false)
+            .map_err(|_| format!("Unknown event '{event}'"))?;
+
+        Ok(Self {
+            line,
+            event_spec: event_spec.to_string(),
+            event_name: EventName::new(event, altitude)?,
+            offset: cli::parse_offset(offset)?,
+            days: Days::parse(&days.to_lowercase())?,
+            command: command.to_string(),
+        })
+    }
+
+    /// Returns the time at which the entry is due when calculated for the given date, if at all.
+    fn due_at(
+        &self,
+        solar_calculations: &calc::SolarCalculations,
+    ) -> Option<DateTime<FixedOffset>> {
+        if !self.days.contains(solar_calculations.date.weekday()) {
+            return None;
+        }
+
+        let event = domain::Event::from_event_name(self.event_name.clone());
+        solar_calculations
+            .event_time(event)
+            .0
+            .map(|event_time| event_time + self.offset)
+    }
+}
+
+/// The parsed contents of a heliotab file.
+#[derive(Clone)]
+pub struct Heliotab {
+    pub entries: Vec<Entry>,
+}
+
+impl Heliotab {
+    pub fn parse(heliotab: &str) -> Result<Self> {
+        let entries = heliotab
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+            .map(|(i, line)| {
+                Entry::parse(i, line).map_err(|e| {
+                    HeliocronError::Config(ConfigErrorKind::InvalidHeliotab(format!(
+                        "line {i}: {e}"
+                    )))
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self { entries })
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let heliotab = fs::read_to_string(path).map_err(|e| {
+            HeliocronError::Config(ConfigErrorKind::InvalidHeliotab(format!(
+                "couldn't read '{}': {e}",
+                path.display()
+            )))
+        })?;
+        Self::parse(&heliotab)
+    }
+
+    /// Find the earliest time strictly after `after` at which any of the entries are due, along with all of the
+    /// entries which are due at that time.
+    ///
+    /// Events calculated for one date can fall on the previous or following day once the offset is applied, so the
+    /// search starts from the day before `after`. Returns `None` if nothing is due within the following week, for
+    /// example if the only events never occur at this time of year.
+    pub fn next_due(
+        &self,
+        solar_calculations: &calc::SolarCalculations,
+        after: DateTime<FixedOffset>,
+    ) -> Option<(DateTime<FixedOffset>, Vec<&Entry>)> {
+        let first_date = after.date().naive_local() - Duration::days(1);
+        let calcs: Vec<_> = (0..9)
+            .map(|days| solar_calculations.for_date(first_date + Duration::days(days)))
+            .collect();
+
+        let mut next: Option<(DateTime<FixedOffset>, Vec<&Entry>)> = None;
+        for entry in &self.entries {
+            let due_at = calcs
+                .iter()
+                .filter_map(|calcs| entry.due_at(calcs))
+                .find(|due_at| *due_at > after);
+
+            match (due_at, &mut next) {
+                (Some(due_at), Some((next_at, entries))) if due_at == *next_at => {
+                    entries.push(entry)
+                }
+                (Some(due_at), Some((next_at, _))) if due_at > *next_at => {}
+                (Some(due_at), _) => next = Some((due_at, vec![entry])),
+                (None, _) => {}
+            }
+        }
+        next
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{Coordinates, Latitude, Longitude};
+
+    fn solar_calculations() -> calc::SolarCalculations {
+        let date = DateTime::parse_from_rfc3339("2022-06-11T12:00:00+00:00").unwrap();
+        let coordinates = Coordinates {
+            latitude: Latitude::new(51.4769).unwrap(),
+            longitude: Longitude::new(-0.0005).unwrap(),
+        };
+        calc::SolarCalculations::new(date, coordinates)
+    }
+
+    #[test]
+    fn test_parse_days() {
+        assert_eq!(Days::parse("*").unwrap(), Days::every_day());
+        assert_eq!(
+            Days::parse("mon-fri").unwrap(),
+            Days([true, true, true, true, true, false, false])
+        );
+        assert_eq!(
+            Days::parse("sat,mon").unwrap(),
+            Days([true, false, false, false, false, true, false])
+        );
+        assert_eq!(
+            Days::parse("fri-mon").unwrap(),
+            Days([true, false, false, false, true, true, true])
+        );
+        assert!(Days::parse("someday").is_err());
+        assert!(Days::parse("mon-").is_err());
+    }
+
+    #[test]
+    fn test_parse_heliotab() {
+        let heliotab = Heliotab::parse(
+            "# a comment\n\
+            \n\
+            sunset -00:30 * /usr/local/bin/lights on\n\
+            custom_am:-3.5   00:00:30   Mon-Fri   echo \"hello,  world\"\n",
+        )
+        .unwrap();
+
+        assert_eq!(heliotab.entries.len(), 2);
+
+        let entry = &heliotab.entries[0];
+        assert_eq!(entry.line, 3);
+        assert_eq!(entry.event_spec, "sunset");
+        assert_eq!(entry.offset, Duration::minutes(-30));
+        assert_eq!(entry.days, Days::every_day());
+        assert_eq!(entry.command, "/usr/local/bin/lights on");
+
+        let entry = &heliotab.entries[1];
+        assert_eq!(entry.line, 4);
+        assert!(matches!(entry.event_name, EventName::CustomAM(ref alt) if **alt == -3.5));
+        assert_eq!(entry.offset, Duration::seconds(30));
+        assert!(!entry.days.contains(Weekday::Sat));
+        assert_eq!(entry.command, "echo \"hello,  world\"");
+    }
+
+    #[test]
+    fn test_parse_heliotab_errors() {
+        let params = [
+            ("sunset", "line 1: Missing offset"),
+            ("sunset 00:00 *", "line 1: Missing command"),
+            ("sunsets 00:00 * cmd", "line 1: Unknown event 'sunsets'"),
+            (
+                "custom_pm 00:00 * cmd",
+                "line 1: Custom events require an altitude",
+            ),
+            (
+                "sunset 0:0 * cmd",
+                "line 1: Expected an offset in the format '[-]HH:MM' or '[-]HH:MM:SS'",
+            ),
+            (
+                "\nsunset 00:00 weekdays cmd",
+                "line 2: Expected a day of the week, such as 'mon'. Found 'weekdays'",
+            ),
+        ];
+
+        for (heliotab, expected) in params.iter() {
+            match Heliotab::parse(heliotab) {
+                Err(HeliocronError::Config(ConfigErrorKind::InvalidHeliotab(msg))) => {
+                    assert_eq!(msg, *expected)
+                }
+                _ => panic!("expected an error parsing {heliotab:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_next_due() {
+        let heliotab = Heliotab::parse(
+            "sunrise 00:00 * echo sunrise\n\
+            sunset 00:00 * echo sunset\n\
+            sunset -01:00 sun echo sunday\n\
+            solar_noon 01:00 sat echo saturday\n",
+        )
+        .unwrap();
+        let calcs = solar_calculations();
+
+        // 2022-06-11 is a Saturday.
+        let after = DateTime::parse_from_rfc3339("2022-06-11T12:00:00+00:00").unwrap();
+        let (due_at, entries) = heliotab.next_due(&calcs, after).unwrap();
+        assert_eq!(due_at.to_string(), "2022-06-11 12:59:40 +00:00");
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].command, "echo saturday");
+
+        let (due_at, entries) = heliotab.next_due(&calcs, due_at).unwrap();
+        assert_eq!(due_at.to_string(), "2022-06-11 20:16:13 +00:00");
+        assert_eq!(entries[0].command, "echo sunset");
+
+        let (due_at, entries) = heliotab.next_due(&calcs, due_at).unwrap();
+        assert_eq!(due_at.to_string(), "2022-06-12 03:42:51 +00:00");
+        assert_eq!(entries[0].command, "echo sunrise");
+
+        let (due_at, entries) = heliotab.next_due(&calcs, due_at).unwrap();
+        assert_eq!(due_at.to_string(), "2022-06-12 19:16:54 +00:00");
+        assert_eq!(entries[0].command, "echo sunday");
+    }
+
+    #[test]
+    fn test_next_due_never() {
+        // At this latitude, astronomical dusk doesn't occur in June.
+        let heliotab = Heliotab::parse("astronomical_dusk 00:00 * echo dusk").unwrap();
+        let after = DateTime::parse_from_rfc3339("2022-06-11T12:00:00+00:00").unwrap();
+        assert!(heliotab.next_due(&solar_calculations(), after).is_none());
+    }
+}
@@ -0,0 +1,251 @@
+//! Generation of iCalendar (RFC 5545) files, as used by the `ics` subcommand.
+//!
+//! Each occurrence of an event becomes a `VEVENT` at the instant of the event, with a UID made up of the date, the name
+//! of the event and the coordinates, so that a calendar which is regenerated replaces the entries it already contains
+//! rather than duplicating them. The times are given in UTC, which calendar applications convert to local time.
+use chrono::{DateTime, Duration, Utc};
+use clap::ValueEnum;
+
+use super::{domain, report};
+
+/// Generate a calendar with the chosen events from each of the reports, which should be for consecutive days.
+///
+/// With `polar`, days on which the Sun neither rises nor sets get an all-day entry for the polar day or night.
+pub fn calendar(
+    reports: &[report::Report],
+    events: &[domain::RawEventName],
+    polar: bool,
+    now: DateTime<Utc>,
+) -> String {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        format!(
+            "PRODID:-//heliocron//heliocron {}//EN",
+            env!("CARGO_PKG_VERSION")
+        ),
+        "CALSCALE:GREGORIAN".to_string(),
+    ];
+
+    for report in reports {
+        let date = report.date.date().naive_local();
+        let coordinates = format!(
+            "{}-{}",
+            report.coordinates.latitude, report.coordinates.longitude
+        );
+
+        for event_name in events {
+            let datetime = match report
+                .event_time(event_name)
+                .and_then(|event_time| event_time.0)
+            {
+                Some(datetime) => datetime,
+                None => continue,
+            };
+            let name = event_name.to_possible_value().unwrap().get_name();
+            let summary = title(name);
+
+            lines.extend([
+                "BEGIN:VEVENT".to_string(),
+                format!(
+                    "UID:{}-{name}-{coordinates}@heliocron",
+                    date.format("%Y%m%d")
+                ),
+                format!("DTSTAMP:{}", utc_timestamp(now)),
+                format!("DTSTART:{}", utc_timestamp(datetime.with_timezone(&Utc))),
+                format!("SUMMARY:{}", escape_text(&summary)),
+                format!(
+                    "DESCRIPTION:{}",
+                    escape_text(&format!(
+                        "{summary} at {} ({}, {})",
+                        datetime.format("%H:%M:%S %:z"),
+                        report.coordinates.latitude,
+                        report.coordinates.longitude
+                    ))
+                ),
+                "TRANSP:TRANSPARENT".to_string(),
+                "END:VEVENT".to_string(),
+            ]);
+        }
+
+        if polar && !report.sunrise.is_some() && !report.sunset.is_some() {
+            // If even the lowest point of the Sun is above the horizon, it never sets.
+            let summary = match report.min_solar_elevation > report.sunrise_elevation {
+                true => "Polar day",
+                false => "Polar night",
+            };
+
+            lines.extend([
+                "BEGIN:VEVENT".to_string(),
+                format!(
+                    "UID:{}-polar-{coordinates}@heliocron",
+                    date.format("%Y%m%d")
+                ),
+                format!("DTSTAMP:{}", utc_timestamp(now)),
+                format!("DTSTART;VALUE=DATE:{}", date.format("%Y%m%d")),
+                format!(
+                    "DTEND;VALUE=DATE:{}",
+                    (date + Duration::days(1)).format("%Y%m%d")
+                ),
+                format!("SUMMARY:{summary}"),
+                "TRANSP:TRANSPARENT".to_string(),
+                "END:VEVENT".to_string(),
+            ]);
+        }
+    }
+    lines.push("END:VCALENDAR".to_string());
+
+    lines.iter().map(|line| fold(line) + "\r\n").collect()
+}
+
+/// The summary of an event, e.g. "Civil dusk" for `civil_dusk`.
+fn title(name: &str) -> String {
+    let name = name.replace('_', " ");
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => name,
+    }
+}
+
+fn utc_timestamp(datetime: DateTime<Utc>) -> String {
+    datetime.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// Escape the characters which have a special meaning in a TEXT value.
+fn escape_text(text: &str) -> String {
+    text.replace('\\', r"\\")
+        .replace(';', r"\;")
+        .replace(',', r"\,")
+        .replace('\n', r"\n")
+}
+
+/// Split a content line into lines of at most 75 octets, as required by RFC 5545. Each continuation line starts with a
+/// space.
+fn fold(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len());
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            length = 1;
+        }
+        folded.push(c);
+        length += c.len_utf8();
+    }
+    folded
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, TimeZone};
+
+    use super::*;
+    use crate::fixtures::solar_calculations;
+
+    fn reports(latitude: f64, days: i64) -> Vec<report::Report> {
+        let solar_calculations = solar_calculations(latitude);
+        (0..days)
+            .map(|days| NaiveDate::from_ymd(2022, 6, 11) + Duration::days(days))
+            .map(|date| report::Report::new(solar_calculations.for_date(date)))
+            .collect()
+    }
+
+    #[test]
+    fn test_calendar() {
+        let now = Utc.ymd(2022, 6, 11).and_hms(11, 0, 0);
+        let calendar = calendar(
+            &reports(51.4769, 2),
+            &[domain::RawEventName::Sunrise, domain::RawEventName::Sunset],
+            true,
+            now,
+        );
+
+        assert!(calendar.starts_with("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n"));
+        assert!(calendar.ends_with("\r\nEND:VCALENDAR\r\n"));
+        assert_eq!(calendar.matches("BEGIN:VEVENT").count(), 4);
+        assert!(calendar.contains(
+            "BEGIN:VEVENT\r\n\
+            UID:20220611-sunset-51.4769--0.0005@heliocron\r\n\
+            DTSTAMP:20220611T110000Z\r\n\
+            DTSTART:20220611T201612Z\r\n\
+            SUMMARY:Sunset\r\n\
+            DESCRIPTION:Sunset at 21:16:12 +01:00 (51.4769\\, -0.0005)\r\n\
+            TRANSP:TRANSPARENT\r\n\
+            END:VEVENT\r\n"
+        ));
+        assert!(!calendar.contains("Polar"));
+
+        // the calendar is the same when it is regenerated
+        assert_eq!(
+            calendar,
+            super::calendar(
+                &reports(51.4769, 2),
+                &[domain::RawEventName::Sunrise, domain::RawEventName::Sunset],
+                true,
+                now,
+            )
+        );
+    }
+
+    #[test]
+    fn test_calendar_polar() {
+        let now = Utc.ymd(2022, 6, 11).and_hms(11, 0, 0);
+
+        // at 70 degrees north, the Sun doesn't set in June...
+        let calendar = calendar(
+            &reports(70.0, 1),
+            &[domain::RawEventName::Sunset],
+            true,
+            now,
+        );
+        assert!(calendar.contains(
+            "DTSTART;VALUE=DATE:20220611\r\nDTEND;VALUE=DATE:20220612\r\nSUMMARY:Polar day\r\n"
+        ));
+        assert!(!calendar.contains("SUMMARY:Sunset"));
+
+        // ...and doesn't rise at 70 degrees south
+        let calendar = super::calendar(
+            &reports(-70.0, 1),
+            &[domain::RawEventName::Sunrise],
+            true,
+            now,
+        );
+        assert!(calendar.contains("SUMMARY:Polar night\r\n"));
+
+        // from high enough up, the Sun doesn't set even though it dips below the geometric horizon
+        let report = report::Report::new(solar_calculations(65.5).with_observer_elevation(1000.0));
+        assert!(report.min_solar_elevation < -0.833);
+        let calendar = super::calendar(&[report], &[], true, now);
+        assert!(calendar.contains("SUMMARY:Polar day\r\n"));
+
+        // the all-day entries are optional
+        let calendar = super::calendar(&reports(70.0, 1), &[], false, now);
+        assert!(!calendar.contains("BEGIN:VEVENT"));
+    }
+
+    #[test]
+    fn test_title() {
+        assert_eq!(title("sunrise"), "Sunrise");
+        assert_eq!(
+            title("morning_golden_hour_start"),
+            "Morning golden hour start"
+        );
+    }
+
+    #[test]
+    fn test_fold() {
+        assert_eq!(fold("SUMMARY:Sunset"), "SUMMARY:Sunset");
+
+        let line = format!("DESCRIPTION:{}", "x".repeat(100));
+        let folded = fold(&line);
+        assert_eq!(folded.replace("\r\n ", ""), line);
+        assert!(folded.split("\r\n").all(|line| line.len() <= 75));
+
+        // multi-byte characters aren't split
+        let folded = fold(&"°".repeat(50));
+        assert!(folded
+            .split("\r\n")
+            .all(|line| line.len() <= 75 && line.trim_start().chars().all(|c| c == '°')));
+    }
+}
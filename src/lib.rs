@@ -1,9 +1,21 @@
 pub mod calc;
 pub mod cli;
+pub mod clock;
+pub mod crontab;
 pub mod domain;
 pub mod errors;
+mod exec;
+#[cfg(test)]
+mod fixtures;
+pub mod heliotab;
+pub mod ics;
+pub mod moon;
+pub mod output;
 pub mod report;
-mod sleep;
+pub mod sleep;
 pub mod subcommands;
+pub mod systemd;
+pub mod template;
 pub mod traits;
+pub mod upcoming;
 pub mod utils;
@@ -0,0 +1,365 @@
+use std::f64::consts::TAU;
+
+use chrono::{DateTime, Duration, FixedOffset};
+
+use crate::domain;
+use crate::traits::DateTimeExt;
+
+/// Arcseconds per radian.
+const ARCSECONDS: f64 = 206264.8062;
+
+/// The altitude of the centre of the Moon at moonrise and moonset is approximately 0.7275 times its horizontal
+/// parallax less 0.5667 degrees of atmospheric refraction.
+fn standard_altitude(horizontal_parallax: f64) -> f64 {
+    0.7275 * horizontal_parallax - 0.5667
+}
+
+/// The apparent position of the Moon, and of the Sun for comparison, at a given instant.
+struct Position {
+    /// Geocentric altitude in degrees.
+    altitude: f64,
+    /// Azimuth in degrees, clockwise from north.
+    azimuth: f64,
+    /// Local hour angle in degrees, between -180.0 and 180.0.
+    hour_angle: f64,
+    /// Horizontal parallax in degrees.
+    horizontal_parallax: f64,
+    /// Elongation of the Moon from the Sun, measured eastwards along the ecliptic in degrees between 0.0 and 360.0.
+    elongation: f64,
+    /// The angular separation of the Moon and the Sun as seen from the Earth in degrees.
+    separation: f64,
+}
+
+impl Position {
+    /// Calculate the position of the Moon using the low precision lunar theory described by Montenbruck and Pfleger
+    /// in "Astronomy on the Personal Computer", which is accurate to a few arcminutes.
+    fn new(datetime: DateTime<FixedOffset>, coordinates: &domain::Coordinates) -> Self {
+        let julian_date = datetime.naive_utc().to_julian_date();
+        let julian_century = (julian_date - 2451545.0) / 36525.0;
+
+        // Mean elements of the lunar orbit
+        let mean_longitude = (0.606433 + 1336.855225 * julian_century).fract();
+        let moon_anomaly = TAU * (0.374897 + 1325.552410 * julian_century).fract();
+        let sun_anomaly = TAU * (0.993133 + 99.997361 * julian_century).fract();
+        let elongation = TAU * (0.827361 + 1236.853086 * julian_century).fract();
+        let latitude_argument = TAU * (0.259086 + 1342.227825 * julian_century).fract();
+
+        // Periodic perturbations of the longitude, in arcseconds
+        let longitude_perturbation = 22640.0 * moon_anomaly.sin()
+            - 4586.0 * (moon_anomaly - 2.0 * elongation).sin()
+            + 2370.0 * (2.0 * elongation).sin()
+            + 769.0 * (2.0 * moon_anomaly).sin()
+            - 668.0 * sun_anomaly.sin()
+            - 412.0 * (2.0 * latitude_argument).sin()
+            - 212.0 * (2.0 * moon_anomaly - 2.0 * elongation).sin()
+            - 206.0 * (moon_anomaly + sun_anomaly - 2.0 * elongation).sin()
+            + 192.0 * (moon_anomaly + 2.0 * elongation).sin()
+            - 165.0 * (sun_anomaly - 2.0 * elongation).sin()
+            - 125.0 * elongation.sin()
+            - 110.0 * (moon_anomaly + sun_anomaly).sin()
+            + 148.0 * (moon_anomaly - sun_anomaly).sin()
+            - 55.0 * (2.0 * latitude_argument - 2.0 * elongation).sin();
+
+        // Periodic perturbations of the latitude
+        let s = latitude_argument
+            + (longitude_perturbation
+                + 412.0 * (2.0 * latitude_argument).sin()
+                + 541.0 * sun_anomaly.sin())
+                / ARCSECONDS;
+        let h = latitude_argument - 2.0 * elongation;
+        let latitude_perturbation = -526.0 * h.sin() + 44.0 * (moon_anomaly + h).sin()
+            - 31.0 * (h - moon_anomaly).sin()
+            - 23.0 * (sun_anomaly + h).sin()
+            + 11.0 * (h - sun_anomaly).sin()
+            - 25.0 * (latitude_argument - 2.0 * moon_anomaly).sin()
+            + 21.0 * (latitude_argument - moon_anomaly).sin();
+
+        // Ecliptic coordinates of the Moon, in radians
+        let ecliptic_longitude = TAU * (mean_longitude + longitude_perturbation / 1296.0e3).fract();
+        let ecliptic_latitude = (18520.0 * s.sin() + latitude_perturbation) / ARCSECONDS;
+
+        // Distance to the Moon in kilometres, using the largest periodic terms
+        let distance = 385000.56
+            - 20905.36 * moon_anomaly.cos()
+            - 3699.11 * (2.0 * elongation - moon_anomaly).cos()
+            - 2955.97 * (2.0 * elongation).cos()
+            - 569.93 * (2.0 * moon_anomaly).cos();
+        let horizontal_parallax = (6378.14 / distance).asin().to_degrees();
+
+        // Ecliptic longitude of the Sun, in radians
+        let sun_mean_longitude = 280.46646 + 36000.76983 * julian_century;
+        let sun_mean_anomaly = (357.52911 + 35999.05029 * julian_century).to_radians();
+        let sun_longitude = (sun_mean_longitude
+            + 1.914602 * sun_mean_anomaly.sin()
+            + 0.019993 * (2.0 * sun_mean_anomaly).sin())
+        .to_radians();
+
+        let elongation = (ecliptic_longitude - sun_longitude)
+            .to_degrees()
+            .rem_euclid(360.0);
+        let separation = (ecliptic_latitude.cos() * (ecliptic_longitude - sun_longitude).cos())
+            .acos()
+            .to_degrees();
+
+        // Convert to equatorial coordinates
+        let obliquity = (23.43929111 - 0.0130042 * julian_century).to_radians();
+        let right_ascension = (ecliptic_longitude.sin() * obliquity.cos()
+            - ecliptic_latitude.tan() * obliquity.sin())
+        .atan2(ecliptic_longitude.cos());
+        let declination = (ecliptic_latitude.sin() * obliquity.cos()
+            + ecliptic_latitude.cos() * obliquity.sin() * ecliptic_longitude.sin())
+        .asin();
+
+        // Convert to horizontal coordinates
+        let sidereal_time = 280.46061837
+            + 360.98564736629 * (julian_date - 2451545.0)
+            + 0.000387933 * julian_century.powi(2);
+        let hour_angle = (sidereal_time + *coordinates.longitude - right_ascension.to_degrees()
+            + 180.0)
+            .rem_euclid(360.0)
+            - 180.0;
+
+        let latitude = coordinates.latitude.to_radians();
+        let altitude = (latitude.sin() * declination.sin()
+            + latitude.cos() * declination.cos() * hour_angle.to_radians().cos())
+        .asin()
+        .to_degrees();
+        let azimuth = (hour_angle
+            .to_radians()
+            .sin()
+            .atan2(
+                hour_angle.to_radians().cos() * latitude.sin() - declination.tan() * latitude.cos(),
+            )
+            .to_degrees()
+            + 180.0)
+            .rem_euclid(360.0);
+
+        Self {
+            altitude,
+            azimuth,
+            hour_angle,
+            horizontal_parallax,
+            elongation,
+            separation,
+        }
+    }
+
+    /// The altitude of the Moon relative to the altitude at which it rises and sets.
+    fn altitude_above_horizon(&self) -> f64 {
+        self.altitude - standard_altitude(self.horizontal_parallax)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LunarCalculations {
+    pub date: DateTime<FixedOffset>,
+    pub coordinates: domain::Coordinates,
+    pub time_zone: domain::TimeZone,
+
+    lunar_elevation_angle: f64,
+    lunar_azimuth_angle: f64,
+    elongation: f64,
+    separation: f64,
+}
+
+impl LunarCalculations {
+    pub fn new(date: DateTime<FixedOffset>, coordinates: domain::Coordinates) -> Self {
+        let position = Position::new(date, &coordinates);
+
+        // The elevation as seen by an observer on the surface of the Earth, rather than at its centre.
+        let lunar_elevation_angle =
+            position.altitude - position.horizontal_parallax * position.altitude.to_radians().cos();
+
+        Self {
+            date,
+            coordinates,
+            time_zone: domain::TimeZone::Fixed(*date.offset()),
+            lunar_elevation_angle,
+            lunar_azimuth_angle: position.azimuth,
+            elongation: position.elongation,
+            separation: position.separation,
+        }
+    }
+
+    /// Set the time zone in which event times are displayed. By default, this is the fixed offset of `date`.
+    pub fn with_time_zone(mut self, time_zone: domain::TimeZone) -> Self {
+        self.time_zone = time_zone;
+        self
+    }
+
+    pub fn lunar_elevation(&self) -> f64 {
+        self.lunar_elevation_angle
+    }
+
+    pub fn azimuth_angle(&self) -> f64 {
+        self.lunar_azimuth_angle
+    }
+
+    /// Returns the fraction of the lunar disk which is illuminated, between 0.0 and 1.0.
+    pub fn illuminated_fraction(&self) -> f64 {
+        // The phase angle is approximately supplementary to the angular separation of the Sun and Moon, because the
+        // Sun is so much further away than the Moon.
+        (1.0 - self.separation.to_radians().cos()) / 2.0
+    }
+
+    pub fn phase(&self) -> domain::LunarPhase {
+        domain::LunarPhase::from_elongation(self.elongation)
+    }
+
+    pub fn moonrise(&self) -> domain::EventTime {
+        self.find_crossing(|before, after| before < 0.0 && after >= 0.0)
+    }
+
+    pub fn moonset(&self) -> domain::EventTime {
+        self.find_crossing(|before, after| before >= 0.0 && after < 0.0)
+    }
+
+    /// Returns the time at which the Moon crosses the local meridian, i.e. when it is highest in the sky.
+    pub fn transit(&self) -> domain::EventTime {
+        let hour_angle = |datetime| Position::new(datetime, &self.coordinates).hour_angle;
+        // The hour angle wraps from 180 to -180 degrees when the Moon is directly below the observer, which we
+        // distinguish from the transit by the size of the jump.
+        self.search_day(hour_angle, |before, after| {
+            before < 0.0 && after >= 0.0 && after - before < 180.0
+        })
+    }
+
+    pub fn event_time(&self, event: domain::LunarEvent) -> domain::EventTime {
+        match event {
+            domain::LunarEvent::Moonrise => self.moonrise(),
+            domain::LunarEvent::Moonset => self.moonset(),
+        }
+    }
+
+    fn find_crossing(&self, is_crossing: impl Fn(f64, f64) -> bool) -> domain::EventTime {
+        let altitude =
+            |datetime| Position::new(datetime, &self.coordinates).altitude_above_horizon();
+        self.search_day(altitude, is_crossing)
+    }
+
+    /// Step through the local calendar day of `date` looking for the first interval in which `is_crossing` holds
+    /// for the values of `f` at either end, and then narrow that interval down to the nearest second.
+    ///
+    /// Unlike the Sun, the Moon does not necessarily rise or set on any given day.
+    fn search_day(
+        &self,
+        f: impl Fn(DateTime<FixedOffset>) -> f64,
+        is_crossing: impl Fn(f64, f64) -> bool,
+    ) -> domain::EventTime {
+        let step = Duration::minutes(10);
+        let local_date = self
+            .time_zone
+            .from_utc_datetime(&self.date.naive_utc())
+            .date()
+            .naive_local();
+        let start = self
+            .time_zone
+            .from_local_datetime(&local_date.and_hms(0, 0, 0));
+        let end = self
+            .time_zone
+            .from_local_datetime(&local_date.succ().and_hms(0, 0, 0));
+
+        let mut before = start;
+        let mut before_value = f(before);
+        while before < end {
+            let after = std::cmp::min(before + step, end);
+            let after_value = f(after);
+
+            if is_crossing(before_value, after_value) {
+                let (mut low, mut high) = (before, after);
+                while high - low > Duration::seconds(1) {
+                    let middle = low + (high - low) / 2;
+                    if is_crossing(before_value, f(middle)) {
+                        high = middle;
+                    } else {
+                        low = middle;
+                    }
+                }
+                // Truncate to whole seconds, as with solar events.
+                let time = high - Duration::nanoseconds(high.timestamp_subsec_nanos() as i64);
+                return domain::EventTime::new(Some(
+                    self.time_zone.from_utc_datetime(&time.naive_utc()),
+                ));
+            }
+
+            before = after;
+            before_value = after_value;
+        }
+
+        domain::EventTime::new(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::DateTime;
+
+    use super::*;
+    use crate::domain::{Coordinates, Latitude, Longitude};
+
+    fn london() -> Coordinates {
+        Coordinates::new(
+            Latitude::new(51.4769).unwrap(),
+            Longitude::new(-0.0005).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_phase() {
+        let params = [
+            // new moon on 2022-06-29 at 02:52 UTC
+            (
+                "2022-06-29T03:00:00+00:00",
+                domain::LunarPhase::NewMoon,
+                0.0,
+            ),
+            // first quarter on 2022-07-07 at 02:14 UTC
+            (
+                "2022-07-07T02:00:00+00:00",
+                domain::LunarPhase::FirstQuarter,
+                0.5,
+            ),
+            // full moon on 2022-07-13 at 18:37 UTC
+            (
+                "2022-07-13T18:00:00+00:00",
+                domain::LunarPhase::FullMoon,
+                1.0,
+            ),
+            // last quarter on 2022-07-20 at 14:18 UTC
+            (
+                "2022-07-20T14:00:00+00:00",
+                domain::LunarPhase::LastQuarter,
+                0.5,
+            ),
+        ];
+
+        for (date, phase, illumination) in params.iter() {
+            let date = DateTime::parse_from_rfc3339(date).unwrap();
+            let calcs = LunarCalculations::new(date, london());
+
+            assert_eq!(calcs.phase(), *phase);
+            assert!((calcs.illuminated_fraction() - illumination).abs() < 0.02);
+        }
+    }
+
+    #[test]
+    fn test_rise_and_set() {
+        let date = DateTime::parse_from_rfc3339("2022-07-13T12:00:00+01:00").unwrap();
+        let calcs = LunarCalculations::new(date, london());
+
+        // a full moon rises around sunset and sets around sunrise
+        assert_eq!("21:46:54", calcs.moonrise().time().unwrap().to_string());
+        assert_eq!("03:47:00", calcs.moonset().time().unwrap().to_string());
+        assert_eq!("00:17:23", calcs.transit().time().unwrap().to_string());
+    }
+
+    #[test]
+    fn test_no_moonrise() {
+        // the Moon rises shortly before midnight the day before and shortly after midnight the day after
+        let date = DateTime::parse_from_rfc3339("2022-07-20T12:00:00+01:00").unwrap();
+        let calcs = LunarCalculations::new(date, london());
+
+        assert_eq!(None, calcs.moonrise().0);
+        assert!(calcs.moonset().is_some());
+    }
+}
@@ -0,0 +1,354 @@
+//! Rendering of reports in the output format chosen with `--format`.
+//!
+//! Any report which implements both `Display` and `Serialize` can be rendered in every format. The text format is the
+//! report's own `Display`, whilst the others are derived from its serialization, so a new format only needs to be
+//! added here, rather than to each report and subcommand. The tabular formats, CSV and Markdown, flatten nested fields
+//! into columns with dotted names, such as `dawn.civil`, and give each element of a list of reports its own row.
+use std::fmt;
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use super::{
+    domain::{Output, OutputFormat},
+    template::Variables,
+};
+
+/// Render the report as chosen with `--format` or `--template`. The result ends with a newline.
+pub fn render_output<T: Serialize + fmt::Display + Variables>(
+    report: &T,
+    output: &Output,
+) -> String {
+    match output {
+        Output::Format(format) => render(report, *format),
+        Output::Template(template) => with_newline(template.render(report)),
+    }
+}
+
+/// Render one of a stream of reports as chosen with `--format` or `--template`, as with `render_streamed`. A template
+/// gives one line per report.
+pub fn render_output_streamed<T: Serialize + fmt::Display + Variables>(
+    report: &T,
+    output: &Output,
+    first: bool,
+) -> String {
+    match output {
+        Output::Format(format) => render_streamed(report, *format, first),
+        Output::Template(template) => template.render(report) + "\n",
+    }
+}
+
+/// Render the report in the given format. The result ends with a newline.
+pub fn render<T: Serialize + fmt::Display>(report: &T, format: OutputFormat) -> String {
+    match format {
+        OutputFormat::Text => with_newline(report.to_string()),
+        // Reports are made up solely of strings, numbers and maps with string keys, so serializing them can't fail.
+        OutputFormat::Json => with_newline(serde_json::to_string(report).unwrap()),
+        format => render_value(serde_json::to_value(report).unwrap(), format),
+    }
+}
+
+/// Render one of a stream of reports, such as those produced by `poll --watch`, so that the reports rendered one after
+/// another form a list in the given format. Only the first report includes the headings of the tabular formats.
+pub fn render_streamed<T: Serialize + fmt::Display>(
+    report: &T,
+    format: OutputFormat,
+    first: bool,
+) -> String {
+    let list = || serde_json::to_value([report]).unwrap();
+    let without_headings = |rendered: String, headings: usize| match first {
+        true => rendered,
+        false => rendered
+            .lines()
+            .skip(headings)
+            .map(|line| line.to_string() + "\n")
+            .collect(),
+    };
+
+    match format {
+        OutputFormat::Text => render(report, format),
+        OutputFormat::Json | OutputFormat::Ndjson => render_value(list(), OutputFormat::Ndjson),
+        OutputFormat::Csv => without_headings(render_value(list(), format), 1),
+        OutputFormat::Markdown => without_headings(render_value(list(), format), 2),
+        OutputFormat::Toml if !first => "\n".to_string() + &render_value(list(), format),
+        OutputFormat::Yaml | OutputFormat::Toml => render_value(list(), format),
+    }
+}
+
+fn render_value(value: Value, format: OutputFormat) -> String {
+    with_newline(match format {
+        OutputFormat::Text | OutputFormat::Json => serde_json::to_string(&value).unwrap(),
+        OutputFormat::Ndjson => match value {
+            Value::Array(items) => items
+                .iter()
+                .map(|item| serde_json::to_string(item).unwrap())
+                .collect::<Vec<_>>()
+                .join("\n"),
+            value => value.to_string(),
+        },
+        OutputFormat::Yaml => serde_yaml::to_string(&value).unwrap(),
+        OutputFormat::Toml => toml(value),
+        OutputFormat::Csv => csv(&rows(value)),
+        OutputFormat::Markdown => markdown(value),
+    })
+}
+
+fn with_newline(output: String) -> String {
+    match output.ends_with('\n') {
+        true => output,
+        false => output + "\n",
+    }
+}
+
+/// TOML has no null value, so fields which are null are left out. A list of reports is given as an array of tables
+/// named `rows`, as TOML documents must be tables.
+fn toml(value: Value) -> String {
+    let value = match without_nulls(value) {
+        Value::Object(map) => Value::Object(map),
+        value => Value::Object(Map::from_iter([("rows".to_string(), value)])),
+    };
+    // Converting to a TOML value first puts the tables after the other values, as TOML requires.
+    toml::to_string(&toml::Value::try_from(value).unwrap()).unwrap()
+}
+
+fn without_nulls(value: Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .filter(|(_, value)| !value.is_null())
+                .map(|(key, value)| (key, without_nulls(value)))
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.into_iter().map(without_nulls).collect()),
+        value => value,
+    }
+}
+
+/// The rows of a tabular format, as pairs of column names and values. A single report is a single row.
+fn rows(value: Value) -> Vec<Vec<(String, String)>> {
+    let items = match value {
+        Value::Array(items) => items,
+        value => vec![value],
+    };
+
+    items
+        .into_iter()
+        .map(|item| {
+            let mut row = Vec::new();
+            flatten(None, item, &mut row);
+            row
+        })
+        .collect()
+}
+
+/// Flatten nested objects into cells with dotted names. Nulls become empty cells.
+fn flatten(name: Option<String>, value: Value, row: &mut Vec<(String, String)>) {
+    let name_of = |key: &str| match &name {
+        Some(name) => format!("{name}.{key}"),
+        None => key.to_string(),
+    };
+
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                flatten(Some(name_of(&key)), value, row);
+            }
+        }
+        value => {
+            let cell = match value {
+                Value::Null => String::new(),
+                Value::String(string) => string,
+                value => value.to_string(),
+            };
+            row.push((name.unwrap_or_default(), cell));
+        }
+    }
+}
+
+/// Comma-separated values with a heading row, quoted where necessary.
+fn csv(rows: &[Vec<(String, String)>]) -> String {
+    let field = |field: &str| match field.contains([',', '"', '\n']) {
+        true => format!("\"{}\"", field.replace('"', "\"\"")),
+        false => field.to_string(),
+    };
+    let line = |cells: Vec<&str>| cells.into_iter().map(field).collect::<Vec<_>>().join(",");
+
+    let mut lines = Vec::with_capacity(rows.len() + 1);
+    if let Some(first) = rows.first() {
+        lines.push(line(first.iter().map(|(name, _)| name.as_str()).collect()));
+    }
+    for row in rows {
+        lines.push(line(row.iter().map(|(_, cell)| cell.as_str()).collect()));
+    }
+    lines.join("\n")
+}
+
+/// A Markdown table. A single report is shown with one field per row, as it would otherwise be far too wide, whilst a
+/// list of reports has one report per row.
+fn markdown(value: Value) -> String {
+    let single = !value.is_array();
+    let rows = rows(value);
+
+    let (headings, rows): (Vec<String>, Vec<Vec<String>>) = match single {
+        true => (
+            vec!["Field".to_string(), "Value".to_string()],
+            rows.into_iter()
+                .flatten()
+                .map(|(name, cell)| vec![name, cell])
+                .collect(),
+        ),
+        false => (
+            rows.first()
+                .map(|row| row.iter().map(|(name, _)| name.clone()).collect())
+                .unwrap_or_default(),
+            rows.into_iter()
+                .map(|row| row.into_iter().map(|(_, cell)| cell).collect())
+                .collect(),
+        ),
+    };
+
+    let line = |cells: &[String]| {
+        let cells: Vec<_> = cells.iter().map(|cell| cell.replace('|', r"\|")).collect();
+        format!("| {} |", cells.join(" | "))
+    };
+    let mut lines = vec![
+        line(&headings),
+        line(&vec!["---".to_string(); headings.len()]),
+    ];
+    lines.extend(rows.iter().map(|row| line(row)));
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize)]
+    struct Sample {
+        name: &'static str,
+        sunrise: Option<&'static str>,
+        location: Location,
+    }
+
+    #[derive(Serialize)]
+    struct Location {
+        latitude: f64,
+        longitude: f64,
+    }
+
+    impl fmt::Display for Sample {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{} at {}", self.name, self.location.latitude)
+        }
+    }
+
+    #[derive(Serialize)]
+    #[serde(transparent)]
+    struct Samples(Vec<Sample>);
+
+    impl fmt::Display for Samples {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            for sample in &self.0 {
+                writeln!(f, "{sample}")?;
+            }
+            Ok(())
+        }
+    }
+
+    fn sample(name: &'static str, sunrise: Option<&'static str>) -> Sample {
+        Sample {
+            name,
+            sunrise,
+            location: Location {
+                latitude: 51.4769,
+                longitude: -0.0005,
+            },
+        }
+    }
+
+    #[test]
+    fn test_render_single() {
+        let report = sample("Greenwich, London", None);
+
+        assert_eq!(
+            render(&report, OutputFormat::Text),
+            "Greenwich, London at 51.4769\n"
+        );
+        assert_eq!(
+            render(&report, OutputFormat::Csv),
+            "name,sunrise,location.latitude,location.longitude\n\
+            \"Greenwich, London\",,51.4769,-0.0005\n"
+        );
+        assert_eq!(
+            render(&report, OutputFormat::Markdown),
+            "| Field | Value |\n\
+            | --- | --- |\n\
+            | name | Greenwich, London |\n\
+            | sunrise |  |\n\
+            | location.latitude | 51.4769 |\n\
+            | location.longitude | -0.0005 |\n"
+        );
+        assert_eq!(
+            render(&report, OutputFormat::Toml),
+            "name = \"Greenwich, London\"\n\n\
+            [location]\n\
+            latitude = 51.4769\n\
+            longitude = -0.0005\n"
+        );
+        assert_eq!(
+            render(&report, OutputFormat::Yaml),
+            "name: Greenwich, London\n\
+            sunrise: null\n\
+            location:\n  latitude: 51.4769\n  longitude: -0.0005\n"
+        );
+    }
+
+    #[test]
+    fn test_render_list() {
+        let reports = Samples(vec![sample("a", Some("04:43")), sample("b", None)]);
+
+        assert_eq!(
+            render(&reports, OutputFormat::Ndjson),
+            "{\"name\":\"a\",\"sunrise\":\"04:43\",\"location\":{\"latitude\":51.4769,\"longitude\":-0.0005}}\n\
+            {\"name\":\"b\",\"sunrise\":null,\"location\":{\"latitude\":51.4769,\"longitude\":-0.0005}}\n"
+        );
+        assert_eq!(
+            render(&reports, OutputFormat::Csv),
+            "name,sunrise,location.latitude,location.longitude\n\
+            a,04:43,51.4769,-0.0005\n\
+            b,,51.4769,-0.0005\n"
+        );
+        assert_eq!(
+            render(&reports, OutputFormat::Markdown),
+            "| name | sunrise | location.latitude | location.longitude |\n\
+            | --- | --- | --- | --- |\n\
+            | a | 04:43 | 51.4769 | -0.0005 |\n\
+            | b |  | 51.4769 | -0.0005 |\n"
+        );
+        assert!(render(&reports, OutputFormat::Toml).starts_with("[[rows]]\nname = \"a\"\n"));
+    }
+
+    #[test]
+    fn test_render_streamed() {
+        let streamed = |format| {
+            render_streamed(&sample("a", Some("04:43")), format, true)
+                + &render_streamed(&sample("b", None), format, false)
+        };
+        let reports = Samples(vec![sample("a", Some("04:43")), sample("b", None)]);
+
+        // the streamed reports are equivalent to the list of reports
+        for format in [
+            OutputFormat::Ndjson,
+            OutputFormat::Csv,
+            OutputFormat::Markdown,
+            OutputFormat::Yaml,
+            OutputFormat::Toml,
+        ] {
+            assert_eq!(streamed(format), render(&reports, format));
+        }
+        assert_eq!(
+            streamed(OutputFormat::Json),
+            render(&reports, OutputFormat::Ndjson)
+        );
+    }
+}
@@ -1,12 +1,15 @@
-use std::{collections::HashMap, fmt};
+use std::{collections::BTreeMap, fmt};
 
 use chrono::{DateTime, Duration, FixedOffset};
-use serde::ser::{Serialize, SerializeStruct};
+use clap::ValueEnum;
+use serde::ser::{Serialize, SerializeMap, SerializeStruct};
 
 use super::{
     calc,
     domain::EventTime,
     domain::{self, Coordinates},
+    template::{self, Kind, Value},
+    upcoming,
 };
 
 #[derive(Debug)]
@@ -15,6 +18,10 @@ pub struct Report {
     pub coordinates: Coordinates,
 
     pub solar_noon: EventTime,
+    pub solar_midnight: EventTime,
+    pub min_solar_elevation: f64,
+    /// The elevation of the centre of the Sun at sunrise and sunset, which is lower for an observer above sea level.
+    pub sunrise_elevation: f64,
     pub day_length: Duration,
 
     pub sunrise: EventTime,
@@ -28,6 +35,25 @@ pub struct Report {
 
     pub astronomical_dawn: EventTime,
     pub astronomical_dusk: EventTime,
+
+    pub morning_blue_hour_start: EventTime,
+    pub morning_blue_hour_end: EventTime,
+    pub morning_golden_hour_start: EventTime,
+    pub morning_golden_hour_end: EventTime,
+
+    pub evening_golden_hour_start: EventTime,
+    pub evening_golden_hour_end: EventTime,
+    pub evening_blue_hour_start: EventTime,
+    pub evening_blue_hour_end: EventTime,
+
+    pub moonrise: EventTime,
+    pub moonset: EventTime,
+    pub lunar_transit: EventTime,
+    pub lunar_phase: domain::LunarPhase,
+    pub lunar_illumination: f64,
+
+    /// The times of the events defined in the configuration file, in order of name.
+    pub custom_events: Vec<(String, CustomEventTime)>,
 }
 
 impl fmt::Display for Report {
@@ -42,30 +68,155 @@ impl Serialize for Report {
     where
         S: serde::Serializer,
     {
-        let mut state = serializer.serialize_struct("Report", 12)?;
+        let mut state = serializer.serialize_struct("Report", 17)?;
         state.serialize_field("date", &self.date.to_rfc3339())?;
         state.serialize_field("location", &self.coordinates)?;
         state.serialize_field("day_length", &self.day_length.num_seconds())?;
         state.serialize_field("solar_noon", &self.solar_noon)?;
+        state.serialize_field("solar_midnight", &self.solar_midnight)?;
+        state.serialize_field("min_solar_elevation", &self.min_solar_elevation)?;
         state.serialize_field("sunrise", &self.sunrise)?;
         state.serialize_field("sunset", &self.sunset)?;
 
-        let mut dawn = HashMap::with_capacity(3);
+        let mut dawn = BTreeMap::new();
         dawn.insert("civil", &self.civil_dawn);
         dawn.insert("nautical", &self.nautical_dawn);
         dawn.insert("astronomical", &self.astronomical_dawn);
         state.serialize_field("dawn", &dawn)?;
 
-        let mut dusk = HashMap::with_capacity(3);
+        let mut dusk = BTreeMap::new();
         dusk.insert("civil", &self.civil_dusk);
         dusk.insert("nautical", &self.nautical_dusk);
         dusk.insert("astronomical", &self.astronomical_dusk);
         state.serialize_field("dusk", &dusk)?;
 
+        let mut golden_hour = BTreeMap::new();
+        golden_hour.insert(
+            "morning",
+            Window::new(
+                &self.morning_golden_hour_start,
+                &self.morning_golden_hour_end,
+            ),
+        );
+        golden_hour.insert(
+            "evening",
+            Window::new(
+                &self.evening_golden_hour_start,
+                &self.evening_golden_hour_end,
+            ),
+        );
+        state.serialize_field("golden_hour", &golden_hour)?;
+
+        let mut blue_hour = BTreeMap::new();
+        blue_hour.insert(
+            "morning",
+            Window::new(&self.morning_blue_hour_start, &self.morning_blue_hour_end),
+        );
+        blue_hour.insert(
+            "evening",
+            Window::new(&self.evening_blue_hour_start, &self.evening_blue_hour_end),
+        );
+        state.serialize_field("blue_hour", &blue_hour)?;
+
+        state.serialize_field(
+            "moon",
+            &MoonFields {
+                moonrise: Some(&self.moonrise),
+                moonset: Some(&self.moonset),
+                transit: Some(&self.lunar_transit),
+                phase: self.lunar_phase,
+                illumination: self.lunar_illumination,
+                elevation: None,
+                azimuth_angle: None,
+            },
+        )?;
+
+        if !self.custom_events.is_empty() {
+            let custom_events: BTreeMap<_, _> = self
+                .custom_events
+                .iter()
+                .map(|(name, event_time)| (name, event_time))
+                .collect();
+            state.serialize_field("custom_events", &custom_events)?;
+        }
+
         state.end()
     }
 }
 
+/// The time of a custom event, both before and after it has been clamped.
+#[derive(Debug, serde::Serialize)]
+pub struct CustomEventTime {
+    /// The time of the event, with the offset applied.
+    pub raw: EventTime,
+    pub clamped: EventTime,
+}
+
+impl CustomEventTime {
+    pub fn new(solar_calculations: &calc::SolarCalculations, event: &domain::CustomEvent) -> Self {
+        let raw = solar_calculations
+            .event_time(domain::Event::Fixed(event.event.clone()))
+            .0
+            .map(|datetime| datetime + event.offset);
+        let clamped = raw.map(|datetime| event.clamp.apply(datetime, solar_calculations.time_zone));
+
+        Self {
+            raw: EventTime::new(raw),
+            clamped: EventTime::new(clamped),
+        }
+    }
+}
+
+impl fmt::Display for CustomEventTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.raw.0, self.clamped.0) {
+            (Some(raw), Some(clamped)) if raw != clamped => {
+                write!(f, "{clamped} (clamped from {raw})")
+            }
+            _ => write!(f, "{}", self.clamped),
+        }
+    }
+}
+
+/// A period of time bounded by two events, such as golden hour.
+#[derive(serde::Serialize)]
+struct Window<'a> {
+    start: &'a EventTime,
+    end: &'a EventTime,
+}
+
+impl<'a> Window<'a> {
+    fn new(start: &'a EventTime, end: &'a EventTime) -> Self {
+        Self { start, end }
+    }
+}
+
+/// The lunar fields shared by `Report` and `PollReport` when serialized, grouped under a "moon" key.
+#[derive(serde::Serialize)]
+struct MoonFields<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    moonrise: Option<&'a EventTime>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    moonset: Option<&'a EventTime>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    transit: Option<&'a EventTime>,
+    phase: domain::LunarPhase,
+    #[serde(serialize_with = "serialize_illumination")]
+    illumination: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    elevation: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    azimuth_angle: Option<f64>,
+}
+
+/// The illuminated fraction is only given to three decimal places, i.e. to the nearest tenth of a percent.
+fn serialize_illumination<S>(illumination: &f64, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serializer.serialize_f64((illumination * 1000.0).round() / 1000.0)
+}
+
 impl Report {
     pub fn new(solar_calculations: calc::SolarCalculations) -> Report {
         // we can unwrap all of these safely because they have been manually validated against the Events::new constructor
@@ -91,11 +242,44 @@ impl Report {
         ));
         let solar_noon = solar_calculations
             .event_time(domain::Event::from_event_name(domain::EventName::SolarNoon));
+        let solar_midnight = solar_calculations.event_time(domain::Event::from_event_name(
+            domain::EventName::SolarMidnight,
+        ));
+
+        let morning_blue_hour_start = solar_calculations.event_time(
+            domain::Event::from_event_name(domain::EventName::MorningBlueHourStart),
+        );
+        let morning_blue_hour_end = solar_calculations.event_time(domain::Event::from_event_name(
+            domain::EventName::MorningBlueHourEnd,
+        ));
+        let morning_golden_hour_start = solar_calculations.event_time(
+            domain::Event::from_event_name(domain::EventName::MorningGoldenHourStart),
+        );
+        let morning_golden_hour_end = solar_calculations.event_time(
+            domain::Event::from_event_name(domain::EventName::MorningGoldenHourEnd),
+        );
+        let evening_golden_hour_start = solar_calculations.event_time(
+            domain::Event::from_event_name(domain::EventName::EveningGoldenHourStart),
+        );
+        let evening_golden_hour_end = solar_calculations.event_time(
+            domain::Event::from_event_name(domain::EventName::EveningGoldenHourEnd),
+        );
+        let evening_blue_hour_start = solar_calculations.event_time(
+            domain::Event::from_event_name(domain::EventName::EveningBlueHourStart),
+        );
+        let evening_blue_hour_end = solar_calculations.event_time(domain::Event::from_event_name(
+            domain::EventName::EveningBlueHourEnd,
+        ));
+
+        let lunar_calculations = solar_calculations.lunar_calculations();
 
         Report {
             date: solar_calculations.date,
             coordinates: solar_calculations.coordinates.clone(),
             solar_noon,
+            solar_midnight,
+            min_solar_elevation: solar_calculations.min_solar_elevation(),
+            sunrise_elevation: solar_calculations.sunrise_elevation(),
             day_length: solar_calculations.day_length(),
             sunrise,
             sunset,
@@ -105,11 +289,58 @@ impl Report {
             nautical_dusk,
             astronomical_dawn,
             astronomical_dusk,
+            morning_blue_hour_start,
+            morning_blue_hour_end,
+            morning_golden_hour_start,
+            morning_golden_hour_end,
+            evening_golden_hour_start,
+            evening_golden_hour_end,
+            evening_blue_hour_start,
+            evening_blue_hour_end,
+            moonrise: lunar_calculations.moonrise(),
+            moonset: lunar_calculations.moonset(),
+            lunar_transit: lunar_calculations.transit(),
+            lunar_phase: lunar_calculations.phase(),
+            lunar_illumination: lunar_calculations.illuminated_fraction(),
+            custom_events: Vec::new(),
         }
     }
 
+    /// Include the times of the events defined in the configuration file.
+    pub fn with_custom_events(mut self, custom_events: Vec<(String, CustomEventTime)>) -> Self {
+        self.custom_events = custom_events;
+        self
+    }
+
+    /// Returns the time of the given built-in event, or `None` for the custom events, whose altitude isn't known here.
+    pub fn event_time(&self, event_name: &domain::RawEventName) -> Option<&EventTime> {
+        Some(match event_name {
+            domain::RawEventName::Sunrise => &self.sunrise,
+            domain::RawEventName::Sunset => &self.sunset,
+            domain::RawEventName::CivilDawn => &self.civil_dawn,
+            domain::RawEventName::CivilDusk => &self.civil_dusk,
+            domain::RawEventName::NauticalDawn => &self.nautical_dawn,
+            domain::RawEventName::NauticalDusk => &self.nautical_dusk,
+            domain::RawEventName::AstronomicalDawn => &self.astronomical_dawn,
+            domain::RawEventName::AstronomicalDusk => &self.astronomical_dusk,
+            domain::RawEventName::SolarNoon => &self.solar_noon,
+            domain::RawEventName::SolarMidnight => &self.solar_midnight,
+            domain::RawEventName::MorningBlueHourStart => &self.morning_blue_hour_start,
+            domain::RawEventName::MorningBlueHourEnd => &self.morning_blue_hour_end,
+            domain::RawEventName::MorningGoldenHourStart => &self.morning_golden_hour_start,
+            domain::RawEventName::MorningGoldenHourEnd => &self.morning_golden_hour_end,
+            domain::RawEventName::EveningGoldenHourStart => &self.evening_golden_hour_start,
+            domain::RawEventName::EveningGoldenHourEnd => &self.evening_golden_hour_end,
+            domain::RawEventName::EveningBlueHourStart => &self.evening_blue_hour_start,
+            domain::RawEventName::EveningBlueHourEnd => &self.evening_blue_hour_end,
+            domain::RawEventName::Moonrise => &self.moonrise,
+            domain::RawEventName::Moonset => &self.moonset,
+            domain::RawEventName::CustomAM | domain::RawEventName::CustomPM => return None,
+        })
+    }
+
     fn format_report(&self) -> String {
-        format!(
+        let mut report = format!(
             "LOCATION\n\
         --------\n\
         Latitude: {}\n\
@@ -118,6 +349,7 @@ impl Report {
         ----\n\
         {}\n\n\
         Solar noon is at:         {}\n\
+        Solar midnight is at:     {} ({:.2}° elevation)\n\
         The day length is:        {}\n\n\
         Sunrise is at:            {}\n\
         Sunset is at:             {}\n\n\
@@ -126,13 +358,25 @@ impl Report {
         Nautical dawn is at:      {}\n\
         Nautical dusk is at:      {}\n\n\
         Astronomical dawn is at:  {}\n\
-        Astronomical dusk is at:  {}
+        Astronomical dusk is at:  {}\n\n\
+        Morning blue hour:        {} to {}\n\
+        Morning golden hour:      {} to {}\n\
+        Evening golden hour:      {} to {}\n\
+        Evening blue hour:        {} to {}\n\n\
+        MOON\n\
+        ----\n\
+        Moonrise is at:           {}\n\
+        Moonset is at:            {}\n\
+        Lunar transit is at:      {}\n\
+        The lunar phase is:       {} ({:.1}% illuminated)
         ",
             self.coordinates.latitude,
             self.coordinates.longitude,
             self.date,
             self.solar_noon,
-            Report::day_length_hms(self.day_length),
+            self.solar_midnight,
+            self.min_solar_elevation,
+            template::duration_text(self.day_length),
             self.sunrise,
             self.sunset,
             self.civil_dawn,
@@ -140,41 +384,272 @@ impl Report {
             self.nautical_dawn,
             self.nautical_dusk,
             self.astronomical_dawn,
-            self.astronomical_dusk
-        )
+            self.astronomical_dusk,
+            self.morning_blue_hour_start,
+            self.morning_blue_hour_end,
+            self.morning_golden_hour_start,
+            self.morning_golden_hour_end,
+            self.evening_golden_hour_start,
+            self.evening_golden_hour_end,
+            self.evening_blue_hour_start,
+            self.evening_blue_hour_end,
+            self.moonrise,
+            self.moonset,
+            self.lunar_transit,
+            self.lunar_phase,
+            self.lunar_illumination * 100.0,
+        );
+
+        if !self.custom_events.is_empty() {
+            report.truncate(report.trim_end().len());
+            report.push_str("\n\nCUSTOM EVENTS\n-------------\n");
+            for (name, event_time) in &self.custom_events {
+                report.push_str(&format!("{:<26}{event_time}\n", format!("{name}:")));
+            }
+        }
+        report
+    }
+}
+
+impl template::Variables for Report {
+    fn value(&self, name: &str) -> Option<Value> {
+        let time = |event_time: &EventTime| Value::Time(event_time.0);
+
+        Some(match name {
+            "date" => Value::Time(Some(self.date)),
+            "location.latitude" => Value::Number(*self.coordinates.latitude),
+            "location.longitude" => Value::Number(*self.coordinates.longitude),
+            "day_length" => Value::Duration(Some(self.day_length)),
+            "solar_noon" => time(&self.solar_noon),
+            "solar_midnight" => time(&self.solar_midnight),
+            "min_solar_elevation" => Value::Number(self.min_solar_elevation),
+            "sunrise" => time(&self.sunrise),
+            "sunset" => time(&self.sunset),
+            "dawn.civil" => time(&self.civil_dawn),
+            "dawn.nautical" => time(&self.nautical_dawn),
+            "dawn.astronomical" => time(&self.astronomical_dawn),
+            "dusk.civil" => time(&self.civil_dusk),
+            "dusk.nautical" => time(&self.nautical_dusk),
+            "dusk.astronomical" => time(&self.astronomical_dusk),
+            "golden_hour.morning.start" => time(&self.morning_golden_hour_start),
+            "golden_hour.morning.end" => time(&self.morning_golden_hour_end),
+            "golden_hour.evening.start" => time(&self.evening_golden_hour_start),
+            "golden_hour.evening.end" => time(&self.evening_golden_hour_end),
+            "blue_hour.morning.start" => time(&self.morning_blue_hour_start),
+            "blue_hour.morning.end" => time(&self.morning_blue_hour_end),
+            "blue_hour.evening.start" => time(&self.evening_blue_hour_start),
+            "blue_hour.evening.end" => time(&self.evening_blue_hour_end),
+            "moon.moonrise" => time(&self.moonrise),
+            "moon.moonset" => time(&self.moonset),
+            "moon.transit" => time(&self.lunar_transit),
+            "moon.phase" => Value::text(&self.lunar_phase),
+            "moon.illumination" => Value::Number(self.lunar_illumination),
+            name => {
+                let (event, field) = name.strip_prefix("custom_events.")?.rsplit_once('.')?;
+                let (_, event_time) = self.custom_events.iter().find(|(name, _)| name == event)?;
+                match field {
+                    "raw" => time(&event_time.raw),
+                    "clamped" => time(&event_time.clamped),
+                    _ => return None,
+                }
+            }
+        })
     }
+}
 
-    fn day_length_hms(day_length: Duration) -> String {
-        let day_length = day_length.num_seconds();
-        let hours = (day_length / 60) / 60;
-        let minutes = (day_length / 60) % 60;
-        let seconds = day_length % 60;
+impl Report {
+    /// The report for the date of the calculations, including the custom events defined in the configuration file.
+    pub fn with_custom_event_definitions(
+        solar_calculations: calc::SolarCalculations,
+        custom_events: &BTreeMap<String, domain::CustomEvent>,
+    ) -> Report {
+        let custom_events = custom_events
+            .iter()
+            .map(|(name, event)| {
+                let event_time = CustomEventTime::new(&solar_calculations, event);
+                (name.clone(), event_time)
+            })
+            .collect();
+        Report::new(solar_calculations).with_custom_events(custom_events)
+    }
 
-        format!("{hours}h {minutes}m {seconds}s")
+    /// Returns the kind of the template variable with the given name, as given by `Variables::value`, where the
+    /// custom events are those defined in the configuration file.
+    pub fn variable_kind(
+        name: &str,
+        custom_events: &BTreeMap<String, domain::CustomEvent>,
+    ) -> Option<Kind> {
+        Some(match name {
+            "day_length" => Kind::Duration,
+            "location.latitude"
+            | "location.longitude"
+            | "min_solar_elevation"
+            | "moon.illumination" => Kind::Number,
+            "moon.phase" => Kind::Text,
+            "date"
+            | "solar_noon"
+            | "solar_midnight"
+            | "sunrise"
+            | "sunset"
+            | "dawn.civil"
+            | "dawn.nautical"
+            | "dawn.astronomical"
+            | "dusk.civil"
+            | "dusk.nautical"
+            | "dusk.astronomical"
+            | "golden_hour.morning.start"
+            | "golden_hour.morning.end"
+            | "golden_hour.evening.start"
+            | "golden_hour.evening.end"
+            | "blue_hour.morning.start"
+            | "blue_hour.morning.end"
+            | "blue_hour.evening.start"
+            | "blue_hour.evening.end"
+            | "moon.moonrise"
+            | "moon.moonset"
+            | "moon.transit" => Kind::Time,
+            name => {
+                // Only the name of a custom event needs to be checked, as its fields are the same for every event.
+                let (event, field) = name.strip_prefix("custom_events.")?.rsplit_once('.')?;
+                match custom_events.contains_key(event) && ["raw", "clamped"].contains(&field) {
+                    true => Kind::Time,
+                    false => return None,
+                }
+            }
+        })
     }
 }
 
+/// How far ahead to look for the next change of day part. Near the poles, where the Sun may stay in the same part of
+/// the day for weeks on end, there is then no change to report.
+const DAY_PART_LOOKAHEAD_HOURS: i64 = 24;
+
 pub struct PollReport {
     pub date: DateTime<FixedOffset>,
     pub coordinates: Coordinates,
 
     pub solar_elevation: f64,
     pub azimuth_angle: f64,
+    pub direction: domain::Direction,
+    pub day_length: Duration,
+
+    pub last_event: Option<(String, DateTime<FixedOffset>)>,
+    pub next_event: Option<upcoming::UpcomingEvent>,
+    pub next_day_part: Option<DayPartChange>,
+
+    pub lunar_elevation: f64,
+    pub lunar_azimuth_angle: f64,
+    pub lunar_phase: domain::LunarPhase,
+    pub lunar_illumination: f64,
+}
+
+/// The next change of day part, to the nearest second.
+pub struct DayPartChange {
+    pub day_part: domain::DayPart,
+    pub datetime: DateTime<FixedOffset>,
 }
 
 impl PollReport {
     pub fn new(solar_calculations: &calc::SolarCalculations) -> Self {
+        let lunar_calculations = solar_calculations.lunar_calculations();
+        let solar_elevation = solar_calculations.solar_elevation();
+        let (last_event, next_event) = upcoming::solar_events_around(solar_calculations);
+
+        // The Sun is rising until solar noon and setting from then until solar midnight.
+        let direction = match solar_calculations
+            .solar_elevation_at(solar_calculations.date + Duration::minutes(1))
+            >= solar_elevation
+        {
+            true => domain::Direction::Ascending,
+            false => domain::Direction::Descending,
+        };
+
         Self {
             date: solar_calculations.date,
             coordinates: solar_calculations.coordinates.clone(),
-            solar_elevation: solar_calculations.solar_elevation(),
+            solar_elevation,
             azimuth_angle: solar_calculations.azimuth_angle(),
+            direction,
+            // The same day length as `report` gives for today, rather than one which drifts through the day.
+            day_length: solar_calculations
+                .for_date(solar_calculations.date.date().naive_local())
+                .day_length(),
+            last_event,
+            next_event,
+            next_day_part: PollReport::next_day_part(solar_calculations),
+            lunar_elevation: lunar_calculations.lunar_elevation(),
+            lunar_azimuth_angle: lunar_calculations.azimuth_angle(),
+            lunar_phase: lunar_calculations.phase(),
+            lunar_illumination: lunar_calculations.illuminated_fraction(),
         }
     }
+
+    /// Find when the Sun next leaves the current day part, by falling below its lower bound or rising above its upper
+    /// bound, whichever comes first.
+    fn next_day_part(solar_calculations: &calc::SolarCalculations) -> Option<DayPartChange> {
+        let day_part = domain::DayPart::from_elevation_angle(solar_calculations.solar_elevation());
+        let (lower, upper) = day_part.elevation_bounds();
+
+        [
+            (lower, domain::Direction::Descending),
+            (upper, domain::Direction::Ascending),
+        ]
+        .into_iter()
+        .filter_map(|(elevation, direction)| {
+            let threshold = domain::ElevationThreshold {
+                elevation: elevation?,
+                direction,
+                hysteresis: 0.0,
+            };
+            solar_calculations.next_elevation_crossing_within(
+                &threshold,
+                solar_calculations.date,
+                false,
+                Duration::hours(DAY_PART_LOOKAHEAD_HOURS),
+            )
+        })
+        .min()
+        .map(|datetime| DayPartChange {
+            day_part: domain::DayPart::from_elevation_angle(
+                solar_calculations.solar_elevation_at(datetime),
+            ),
+            datetime,
+        })
+    }
+
+    /// The time remaining until the next change of day part, if there is one within the lookahead.
+    fn day_part_countdown(&self) -> Option<Duration> {
+        self.next_day_part
+            .as_ref()
+            .map(|change| change.datetime - self.date)
+    }
 }
 
 impl std::fmt::Display for PollReport {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let last_event = match &self.last_event {
+            Some((name, datetime)) => format!("{name} at {}", datetime.format("%F %T %:z")),
+            None => "None within the past day".to_string(),
+        };
+        let next_event = match &self.next_event {
+            Some(event) => format!(
+                "{} at {}, in {}",
+                event.name,
+                event.datetime.format("%F %T %:z"),
+                template::duration_hms(event.countdown)
+            ),
+            None => "None within the next day".to_string(),
+        };
+        let next_day_part = match &self.next_day_part {
+            Some(change) => format!(
+                "{} at {}, in {}",
+                change.day_part,
+                change.datetime.format("%F %T %:z"),
+                template::duration_hms(change.datetime - self.date)
+            ),
+            None => "None within the next day".to_string(),
+        };
+
         let report = format!(
             "LOCATION\n\
     --------\n\
@@ -183,28 +658,108 @@ impl std::fmt::Display for PollReport {
     DATE\n\
     ----\n\
     {}\n\
-    {}\n\n\
+    {}{}\n\n\
     Solar elevation: {:.3}°\n\
     Azimuth angle:   {:.3}°\n\
+    Direction:       {}\n\
+    Day length:      {}\n\n\
+    Last event:      {}\n\
+    Next event:      {}\n\
+    Next day part:   {}\n\n\
+    MOON\n\
+    ----\n\
+    Lunar elevation: {:.3}°\n\
+    Azimuth angle:   {:.3}°\n\
+    Phase:           {} ({:.1}% illuminated)\n\
     ",
             self.coordinates.latitude,
             self.coordinates.longitude,
             self.date.format("%F %T %:z"),
             domain::DayPart::from_elevation_angle(self.solar_elevation),
+            domain::DayPartOverlay::from_elevation_angle(self.solar_elevation)
+                .map(|overlay| format!(" ({overlay})"))
+                .unwrap_or_default(),
             self.solar_elevation,
             self.azimuth_angle,
+            self.direction,
+            template::duration_text(self.day_length),
+            last_event,
+            next_event,
+            next_day_part,
+            self.lunar_elevation,
+            self.lunar_azimuth_angle,
+            self.lunar_phase,
+            self.lunar_illumination * 100.0,
         );
 
         write!(f, "{report}")
     }
 }
 
+/// The fields of a `PollReport` which describe the events either side of it when serialized. The fields are null if
+/// there is no such event, so that the tabular formats always have the same columns.
+#[derive(serde::Serialize)]
+struct LastEventFields<'a> {
+    event: Option<&'a str>,
+    time: Option<String>,
+    elapsed: Option<i64>,
+}
+
+#[derive(serde::Serialize)]
+struct NextEventFields<'a> {
+    event: Option<&'a str>,
+    time: Option<String>,
+    countdown: Option<i64>,
+}
+
+#[derive(serde::Serialize)]
+struct NextDayPartFields {
+    day_part: Option<domain::DayPart>,
+    time: Option<String>,
+    countdown: Option<i64>,
+}
+
 impl Serialize for PollReport {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: serde::Serializer,
     {
-        let mut state = serializer.serialize_struct("PollReport", 4)?;
+        let last_event = match &self.last_event {
+            Some((name, datetime)) => LastEventFields {
+                event: Some(name),
+                time: Some(datetime.to_rfc3339()),
+                elapsed: Some((self.date - *datetime).num_seconds()),
+            },
+            None => LastEventFields {
+                event: None,
+                time: None,
+                elapsed: None,
+            },
+        };
+        let next_event = match &self.next_event {
+            Some(event) => NextEventFields {
+                event: Some(&event.name),
+                time: Some(event.datetime.to_rfc3339()),
+                countdown: Some(event.countdown.num_seconds()),
+            },
+            None => NextEventFields {
+                event: None,
+                time: None,
+                countdown: None,
+            },
+        };
+        let next_day_part = NextDayPartFields {
+            day_part: self.next_day_part.as_ref().map(|change| change.day_part),
+            time: self
+                .next_day_part
+                .as_ref()
+                .map(|change| change.datetime.to_rfc3339()),
+            countdown: self
+                .day_part_countdown()
+                .map(|countdown| countdown.num_seconds()),
+        };
+
+        let mut state = serializer.serialize_struct("PollReport", 12)?;
         state.serialize_field("date", &self.date.to_rfc3339())?;
         state.serialize_field("location", &self.coordinates)?;
 
@@ -212,9 +767,191 @@ impl Serialize for PollReport {
             "day_part",
             &domain::DayPart::from_elevation_angle(self.solar_elevation),
         )?;
+        state.serialize_field(
+            "overlay",
+            &domain::DayPartOverlay::from_elevation_angle(self.solar_elevation),
+        )?;
         state.serialize_field("solar_elevation", &self.solar_elevation)?;
         state.serialize_field("azimuth_angle", &self.azimuth_angle)?;
+        state.serialize_field("direction", &self.direction)?;
+        state.serialize_field("day_length", &self.day_length.num_seconds())?;
+        state.serialize_field("last_event", &last_event)?;
+        state.serialize_field("next_event", &next_event)?;
+        state.serialize_field("next_day_part", &next_day_part)?;
+        state.serialize_field(
+            "moon",
+            &MoonFields {
+                moonrise: None,
+                moonset: None,
+                transit: None,
+                phase: self.lunar_phase,
+                illumination: self.lunar_illumination,
+                elevation: Some(self.lunar_elevation),
+                azimuth_angle: Some(self.lunar_azimuth_angle),
+            },
+        )?;
+
+        state.end()
+    }
+}
+
+impl template::Variables for PollReport {
+    fn value(&self, name: &str) -> Option<Value> {
+        let last_event = self.last_event.as_ref();
+        let next_event = self.next_event.as_ref();
+        let next_day_part = self.next_day_part.as_ref();
+
+        Some(match name {
+            "date" => Value::Time(Some(self.date)),
+            "location.latitude" => Value::Number(*self.coordinates.latitude),
+            "location.longitude" => Value::Number(*self.coordinates.longitude),
+            "day_part" => Value::text(&domain::DayPart::from_elevation_angle(self.solar_elevation)),
+            "overlay" => Value::text(&domain::DayPartOverlay::from_elevation_angle(
+                self.solar_elevation,
+            )),
+            "solar_elevation" => Value::Number(self.solar_elevation),
+            "azimuth_angle" => Value::Number(self.azimuth_angle),
+            "direction" => Value::text(&self.direction),
+            "day_length" => Value::Duration(Some(self.day_length)),
+            "last_event.event" => Value::Text(last_event.map(|(name, _)| name.clone())),
+            "last_event.time" => Value::Time(last_event.map(|(_, datetime)| *datetime)),
+            "last_event.elapsed" => {
+                Value::Duration(last_event.map(|(_, datetime)| self.date - *datetime))
+            }
+            "next_event.event" => Value::Text(next_event.map(|event| event.name.clone())),
+            "next_event.time" => Value::Time(next_event.map(|event| event.datetime)),
+            "next_event.countdown" => Value::Duration(next_event.map(|event| event.countdown)),
+            "next_day_part.day_part" => match next_day_part {
+                Some(change) => Value::text(&change.day_part),
+                None => Value::Text(None),
+            },
+            "next_day_part.time" => Value::Time(next_day_part.map(|change| change.datetime)),
+            "next_day_part.countdown" => Value::Duration(self.day_part_countdown()),
+            "moon.phase" => Value::text(&self.lunar_phase),
+            "moon.illumination" => Value::Number(self.lunar_illumination),
+            "moon.elevation" => Value::Number(self.lunar_elevation),
+            "moon.azimuth_angle" => Value::Number(self.lunar_azimuth_angle),
+            _ => return None,
+        })
+    }
+}
+
+impl PollReport {
+    /// Returns the kind of the template variable with the given name, as given by `Variables::value`.
+    pub fn variable_kind(name: &str) -> Option<Kind> {
+        Some(match name {
+            "date" | "last_event.time" | "next_event.time" | "next_day_part.time" => Kind::Time,
+            "day_length"
+            | "last_event.elapsed"
+            | "next_event.countdown"
+            | "next_day_part.countdown" => Kind::Duration,
+            "day_part"
+            | "overlay"
+            | "direction"
+            | "last_event.event"
+            | "next_event.event"
+            | "next_day_part.day_part"
+            | "moon.phase" => Kind::Text,
+            "location.latitude" | "location.longitude" | "solar_elevation" | "azimuth_angle"
+            | "moon.illumination" | "moon.elevation" | "moon.azimuth_angle" => Kind::Number,
+            _ => return None,
+        })
+    }
+}
+
+/// A report covering a range of dates, with a row of the times of every event for each day.
+pub struct RangeReport {
+    pub days: Vec<Report>,
+}
+
+impl RangeReport {
+    pub fn new(days: Vec<Report>) -> Self {
+        Self { days }
+    }
+
+    fn headings(&self) -> Vec<String> {
+        let mut headings = vec!["date".to_string(), "day_length".to_string()];
+        if let Some(day) = self.days.first() {
+            headings.extend(RangeReport::event_columns(day).map(|(name, _)| name.to_string()));
+        }
+        headings
+    }
+
+    /// The name and time of every built-in event, followed by the clamped times of the custom events.
+    fn event_columns(day: &Report) -> impl Iterator<Item = (&str, &EventTime)> {
+        domain::RawEventName::value_variants()
+            .iter()
+            .filter_map(|event_name| {
+                let name = event_name.to_possible_value()?.get_name();
+                Some((name, day.event_time(event_name)?))
+            })
+            .chain(
+                day.custom_events
+                    .iter()
+                    .map(|(name, event_time)| (name.as_str(), &event_time.clamped)),
+            )
+    }
+}
+
+/// An aligned table, with the times of day in the chosen time zone.
+impl fmt::Display for RangeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut rows = vec![self.headings()];
+        for day in &self.days {
+            let mut row = vec![
+                day.date.format("%Y-%m-%d").to_string(),
+                template::duration_hms(day.day_length),
+            ];
+            row.extend(RangeReport::event_columns(day).map(|(_, event_time)| {
+                event_time.0.map_or("Never".to_string(), |datetime| {
+                    datetime.format("%H:%M:%S").to_string()
+                })
+            }));
+            rows.push(row);
+        }
+
+        let mut widths = vec![0; rows[0].len()];
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+
+        for row in &rows {
+            let line: Vec<_> = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{cell:<width$}"))
+                .collect();
+            writeln!(f, "{}", line.join("  ").trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+impl Serialize for RangeReport {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_seq(self.days.iter().map(DayRow))
+    }
+}
+
+/// A single day of a `RangeReport`, serialized as a flat object.
+struct DayRow<'a>(&'a Report);
 
+impl<'a> Serialize for DayRow<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut state = serializer.serialize_map(None)?;
+        state.serialize_entry("date", &self.0.date.format("%Y-%m-%d").to_string())?;
+        state.serialize_entry("day_length", &self.0.day_length.num_seconds())?;
+        for (name, event_time) in RangeReport::event_columns(self.0) {
+            state.serialize_entry(name, event_time)?;
+        }
         state.end()
     }
 }
@@ -260,8 +997,15 @@ mod tests {
         assert!(report_str.contains("Longitude"));
         assert!(report_str.contains("Sunrise is at"));
         assert!(report_str.contains("Solar noon is at"));
+        assert!(report_str.contains("Solar midnight is at"));
         assert!(report_str.contains("Sunset is at"));
         assert!(report_str.contains("The day length is"));
+        assert!(report_str.contains("Morning golden hour"));
+        assert!(report_str.contains("Evening blue hour"));
+        assert!(report_str.contains("MOON"));
+        assert!(report_str.contains("Moonrise is at"));
+        assert!(report_str.contains("Moonset is at"));
+        assert!(report_str.contains("The lunar phase is"));
 
         let sunrise_str = format!("{}", report.sunrise);
         assert!(report_str.contains(&sunrise_str));
@@ -272,7 +1016,7 @@ mod tests {
         let sunset_str = format!("{}", report.sunset);
         assert!(report_str.contains(&sunset_str));
 
-        let day_length_str = Report::day_length_hms(report.day_length);
+        let day_length_str = template::duration_text(report.day_length);
         assert!(report_str.contains(&day_length_str));
     }
 
@@ -387,7 +1131,7 @@ mod tests {
         assert_eq!(None, report.nautical_dusk.0);
         assert_eq!(None, report.astronomical_dawn.0);
         assert_eq!(None, report.astronomical_dusk.0);
-        assert_eq!("24h 0m 0s", Report::day_length_hms(report.day_length));
+        assert_eq!("24h 0m 0s", template::duration_text(report.day_length));
     }
 
     #[test]
@@ -407,10 +1151,15 @@ mod tests {
             "date": "2020-03-25T12:00:00+00:00",
             "day_length": 45412,
             "solar_noon": "2020-03-25T12:18:33+00:00",
+            "solar_midnight": "2020-03-26T00:18:33+00:00",
+            "min_solar_elevation": -31.73218766310265,
             "sunrise": "2020-03-25T06:00:07+00:00",
             "sunset": "2020-03-25T18:36:59+00:00",
             "dawn": {"civil": "2020-03-25T05:22:43+00:00", "nautical": "2020-03-25T04:37:42+00:00", "astronomical": "2020-03-25T03:49:09+00:00"},
             "dusk": {"civil": "2020-03-25T19:14:23+00:00", "nautical": "2020-03-25T19:59:24+00:00", "astronomical": "2020-03-25T20:47:57+00:00"},
+            "golden_hour": {"morning": {"start": "2020-03-25T05:37:17+00:00", "end": "2020-03-25T06:49:00+00:00"}, "evening": {"start": "2020-03-25T17:48:06+00:00", "end": "2020-03-25T18:59:48+00:00"}},
+            "blue_hour": {"morning": {"start": "2020-03-25T05:22:43+00:00", "end": "2020-03-25T05:37:17+00:00"}, "evening": {"start": "2020-03-25T18:59:48+00:00", "end": "2020-03-25T19:14:23+00:00"}},
+            "moon": {"moonrise": "2020-03-25T06:54:49+00:00", "moonset": "2020-03-25T19:45:25+00:00", "transit": "2020-03-25T13:12:23+00:00", "phase": "waxing_crescent", "illumination": 0.013},
         });
 
         assert_eq!(serde_json::to_value(report).unwrap(), expected);
@@ -432,12 +1181,211 @@ mod tests {
             "date": "2022-06-11T12:00:00+01:00",
             "day_length": 59534,
             "solar_noon": "2022-06-11T13:21:31+01:00",
+            "solar_midnight": "2022-06-12T01:21:31+01:00",
+            "min_solar_elevation": -15.44326975437755,
             "sunrise": "2022-06-11T05:05:24+01:00",
             "sunset": "2022-06-11T21:37:38+01:00",
             "dawn": {"civil": "2022-06-11T04:18:29+01:00", "nautical": "2022-06-11T03:06:40+01:00", "astronomical": null},
             "dusk": {"civil": "2022-06-11T22:24:34+01:00", "nautical": "2022-06-11T23:36:23+01:00", "astronomical": null},
+            "golden_hour": {"morning": {"start": "2022-06-11T04:37:35+01:00", "end": "2022-06-11T05:59:03+01:00"}, "evening": {"start": "2022-06-11T20:44:00+01:00", "end": "2022-06-11T22:05:27+01:00"}},
+            "blue_hour": {"morning": {"start": "2022-06-11T04:18:29+01:00", "end": "2022-06-11T04:37:35+01:00"}, "evening": {"start": "2022-06-11T22:05:27+01:00", "end": "2022-06-11T22:24:34+01:00"}},
+            "moon": {"moonrise": "2022-06-11T17:58:16+01:00", "moonset": "2022-06-11T03:13:23+01:00", "transit": "2022-06-11T22:51:45+01:00", "phase": "waxing_gibbous", "illumination": 0.871},
         });
 
         assert_eq!(serde_json::to_value(report).unwrap(), expected);
     }
+
+    fn range_report() -> RangeReport {
+        let date = DateTime::parse_from_rfc3339("2022-06-11T12:00:00+01:00").unwrap();
+        let coordinates = Coordinates {
+            latitude: Latitude::new(51.4769).unwrap(),
+            longitude: Longitude::new(-0.0005).unwrap(),
+        };
+        let calcs = calc::SolarCalculations::new(date, coordinates);
+
+        RangeReport::new(
+            [date, date + Duration::days(1)]
+                .into_iter()
+                .map(|date| Report::new(calcs.for_date(date.date().naive_local())))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_range_report_table() {
+        let table = range_report().to_string();
+        let lines: Vec<_> = table.lines().collect();
+
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("date        day_length  sunrise   sunset    civil_dawn  "));
+        assert!(lines[0].ends_with("  moonrise  moonset"));
+        assert!(lines[1].starts_with("2022-06-11  16:33:05    04:43:07  21:16:12  03:56:02    "));
+        // astronomical twilight lasts all night in June at this latitude
+        assert!(lines[2].contains("  Never              Never              12:59:52  "));
+    }
+
+    #[test]
+    fn test_range_report_csv() {
+        let csv = crate::output::render(&range_report(), domain::OutputFormat::Csv);
+        let lines: Vec<_> = csv.lines().collect();
+
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("date,day_length,sunrise,sunset,civil_dawn,"));
+        assert!(lines[1]
+            .starts_with("2022-06-11,59585,2022-06-11T04:43:07+01:00,2022-06-11T21:16:12+01:00,"));
+        assert!(lines[1].contains(",2022-06-11T23:15:36+01:00,,,2022-06-11T12:59:39+01:00,"));
+        assert!(lines
+            .iter()
+            .all(|line| line.split(',').count() == lines[0].split(',').count()));
+    }
+
+    #[test]
+    fn test_range_report_json() {
+        let report = range_report();
+        let json = serde_json::to_value(&report).unwrap();
+
+        assert_eq!(json.as_array().unwrap().len(), 2);
+        assert_eq!(json[1]["date"], "2022-06-12");
+        assert_eq!(json[1]["day_length"], 59641);
+        assert_eq!(json[1]["sunset"], "2022-06-12T21:16:52+01:00");
+        assert_eq!(json[1]["astronomical_dusk"], serde_json::Value::Null);
+
+        let ndjson = crate::output::render(&report, domain::OutputFormat::Ndjson);
+        let lines: Vec<serde_json::Value> = ndjson
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines, json.as_array().unwrap().clone());
+    }
+
+    #[test]
+    fn test_poll_report() {
+        let poll_report = |latitude, date| {
+            let coordinates = Coordinates {
+                latitude: Latitude::new(latitude).unwrap(),
+                longitude: Longitude::new(-0.0005).unwrap(),
+            };
+            PollReport::new(&calc::SolarCalculations::new(
+                DateTime::parse_from_rfc3339(date).unwrap(),
+                coordinates,
+            ))
+        };
+
+        let report = poll_report(51.4769, "2022-06-11T12:00:00+01:00");
+        assert!(matches!(report.direction, domain::Direction::Ascending));
+        assert_eq!(report.day_length, Duration::seconds(59585));
+        let (name, datetime) = report.last_event.as_ref().unwrap();
+        assert_eq!(name, "morning_golden_hour_end");
+        assert_eq!(datetime.to_string(), "2022-06-11 05:36:54 +01:00");
+        let next_event = report.next_event.as_ref().unwrap();
+        assert_eq!(next_event.name, "solar_noon");
+        assert_eq!(next_event.countdown, Duration::seconds(3579));
+        let next_day_part = report.next_day_part.as_ref().unwrap();
+        assert_eq!(next_day_part.day_part, domain::DayPart::CivilTwilight);
+        assert_eq!(
+            next_day_part.datetime.to_string(),
+            "2022-06-11 21:06:42 +01:00"
+        );
+
+        // the day length is the same all day, whilst the Sun is now setting
+        let report = poll_report(51.4769, "2022-06-11T20:00:00+01:00");
+        assert!(matches!(report.direction, domain::Direction::Descending));
+        assert_eq!(report.day_length, Duration::seconds(59585));
+        assert_eq!(report.last_event.unwrap().0, "solar_noon");
+        assert_eq!(report.next_event.unwrap().name, "evening_golden_hour_start");
+
+        // the Sun only just dips into civil twilight around solar midnight, but the change is found wherever the
+        // search starts from
+        for date in ["2022-06-11T20:00:00+01:00", "2022-06-11T20:02:30+01:00"] {
+            let next_day_part = poll_report(67.206, date).next_day_part.unwrap();
+            assert_eq!(next_day_part.day_part, domain::DayPart::CivilTwilight);
+            assert_eq!(
+                next_day_part.datetime.to_string(),
+                "2022-06-12 00:57:42 +01:00"
+            );
+        }
+
+        // so close to the pole, the Sun stays up all day and there is no change of day part to come
+        let report = poll_report(89.0, "2022-06-11T12:00:00+01:00");
+        assert!(report.next_day_part.is_none());
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(
+            json["next_day_part"],
+            serde_json::json!({"day_part": null, "time": null, "countdown": null})
+        );
+    }
+
+    #[test]
+    fn test_template_variables() {
+        use template::Variables;
+
+        let date = DateTime::parse_from_rfc3339("2022-06-11T12:00:00+01:00").unwrap();
+        let coordinates = Coordinates {
+            latitude: Latitude::new(51.4769).unwrap(),
+            longitude: Longitude::new(-0.0005).unwrap(),
+        };
+        let calcs = calc::SolarCalculations::new(date, coordinates);
+        let custom_events = BTreeMap::from([(
+            "porch".to_string(),
+            domain::CustomEvent {
+                event: domain::FixedElevationEvent::new(
+                    domain::Altitude::new(3.0).unwrap(),
+                    domain::Direction::Descending,
+                ),
+                offset: Duration::zero(),
+                clamp: domain::Clamp::default(),
+            },
+        )]);
+        let report = Report::new(calcs.clone()).with_custom_events(vec![(
+            "porch".to_string(),
+            CustomEventTime::new(&calcs, &custom_events["porch"]),
+        )]);
+        let poll_report = PollReport::new(&calcs);
+
+        // every field is a variable, named as in the headings of the csv format
+        let headings = |csv: String| {
+            csv.lines()
+                .next()
+                .unwrap()
+                .split(',')
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        };
+        let csv = crate::output::render(&report, domain::OutputFormat::Csv);
+        assert!(csv.contains("custom_events.porch.clamped"));
+        for name in headings(csv) {
+            let value = report.value(&name).unwrap();
+            assert_eq!(
+                Report::variable_kind(&name, &custom_events),
+                Some(value.kind()),
+                "{name}"
+            );
+        }
+        for name in headings(crate::output::render(
+            &poll_report,
+            domain::OutputFormat::Csv,
+        )) {
+            let value = poll_report.value(&name).unwrap();
+            assert_eq!(
+                PollReport::variable_kind(&name),
+                Some(value.kind()),
+                "{name}"
+            );
+        }
+
+        assert_eq!(
+            Report::variable_kind("custom_events.porch", &custom_events),
+            None
+        );
+        assert_eq!(
+            Report::variable_kind("custom_events.door.raw", &custom_events),
+            None
+        );
+        assert_eq!(report.value("sunrise"), Some(Value::Time(report.sunrise.0)));
+        assert_eq!(
+            poll_report.value("day_part"),
+            Some(Value::Text(Some("day".to_string())))
+        );
+        assert_eq!(poll_report.value("overlay"), Some(Value::Text(None)));
+    }
 }
@@ -1,7 +1,9 @@
+//! Sleeping until a given time according to the system's wall clock.
+//!
+//! Unlike `tokio::time::sleep_until`, which measures a duration on the monotonic clock, these timers take into
+//! account any changes to the wall clock in the meantime, such as when the system is suspended or the clock is
+//! adjusted.
 use chrono::{DateTime, Utc};
-use errno::errno;
-use std::{mem::MaybeUninit, ptr};
-use tokio::signal::unix::{signal, SignalKind};
 
 #[derive(Debug)]
 pub enum Error {
@@ -33,67 +35,237 @@ impl std::fmt::Display for Error {
 
 type Result<T> = std::result::Result<T, Error>;
 
-unsafe fn arm_timer(duration: i64) -> Result<libc::timer_t> {
-    // First, initialize our timer
-    let mut timer: libc::timer_t = MaybeUninit::zeroed().assume_init();
-    // this means we are going to create a SIGALRM
-    let mut sev: libc::sigevent = MaybeUninit::zeroed().assume_init();
-    sev.sigev_notify = libc::SIGEV_SIGNAL;
-    sev.sigev_signo = SignalKind::alarm().as_raw_value();
-    if libc::timer_create(libc::CLOCK_REALTIME, &mut sev, &mut timer) != 0 {
-        return Err(Error::from(errno()));
-    }
+/// Sleep until the given time, re-arming the timer if the wall clock is changed in the meantime.
+///
+/// On Linux, each call uses its own timer file descriptor with nanosecond precision, so any number of calls may be
+/// awaited concurrently.
+#[cfg(target_os = "linux")]
+pub async fn sleep_until<Tz: chrono::TimeZone>(time: DateTime<Tz>) -> Result<()> {
+    timerfd::sleep_until(time.with_timezone(&Utc)).await
+}
+
+/// Sleep until the given time, to the nearest second.
+///
+/// On Unix platforms other than Linux, this relies on SIGALRM, so no other part of the process should use it.
+#[cfg(all(unix, not(target_os = "linux")))]
+pub async fn sleep_until<Tz: chrono::TimeZone>(time: DateTime<Tz>) -> Result<()> {
+    alarm::sleep_until(time.with_timezone(&Utc)).await
+}
 
-    // Now, get the time to sleep until
-    let mut its = libc::itimerspec {
-        it_interval: libc::timespec {
-            tv_sec: 0,
-            tv_nsec: 0,
-        },
-        it_value: MaybeUninit::zeroed().assume_init(),
+#[cfg(target_os = "linux")]
+mod timerfd {
+    use std::{
+        io,
+        os::unix::io::{AsRawFd, RawFd},
+        ptr,
     };
-    // by getting the current time
-    if libc::clock_gettime(libc::CLOCK_REALTIME, &mut its.it_value) != 0 {
-        let err = Err(Error::from(errno()));
-        disarm_timer(timer)?;
-        return err;
+
+    use chrono::{DateTime, Utc};
+    use errno::errno;
+    use tokio::io::unix::AsyncFd;
+
+    use super::{Error, Result};
+
+    /// A timer on the realtime clock, exposed as a file descriptor which becomes readable once it expires. The file
+    /// descriptor is closed when dropped.
+    struct TimerFd(RawFd);
+
+    impl TimerFd {
+        fn new() -> Result<Self> {
+            let fd = unsafe {
+                libc::timerfd_create(libc::CLOCK_REALTIME, libc::TFD_NONBLOCK | libc::TFD_CLOEXEC)
+            };
+            if fd < 0 {
+                return Err(Error::from(errno()));
+            }
+            Ok(Self(fd))
+        }
+
+        /// Arm the timer to expire at the given absolute time. If the realtime clock is set discontinuously before
+        /// then, the timer is cancelled and reading from it fails with ECANCELED.
+        fn arm(&self, deadline: libc::timespec) -> Result<()> {
+            let its = libc::itimerspec {
+                it_interval: libc::timespec {
+                    tv_sec: 0,
+                    tv_nsec: 0,
+                },
+                it_value: deadline,
+            };
+            let flags = libc::TFD_TIMER_ABSTIME | libc::TFD_TIMER_CANCEL_ON_SET;
+            if unsafe { libc::timerfd_settime(self.0, flags, &its, ptr::null_mut()) } != 0 {
+                return Err(Error::from(errno()));
+            }
+            Ok(())
+        }
+
+        /// Read the number of times the timer has expired since it was armed.
+        fn read(&self) -> io::Result<u64> {
+            let mut expirations: u64 = 0;
+            let read = unsafe {
+                libc::read(
+                    self.0,
+                    &mut expirations as *mut u64 as *mut libc::c_void,
+                    std::mem::size_of::<u64>(),
+                )
+            };
+            if read < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(expirations)
+        }
     }
 
-    // and changing the duration
-    its.it_value.tv_sec += duration as libc::time_t;
+    impl AsRawFd for TimerFd {
+        fn as_raw_fd(&self) -> RawFd {
+            self.0
+        }
+    }
 
-    // Finally, arm the timer
-    if libc::timer_settime(timer, libc::TIMER_ABSTIME, &its, ptr::null_mut()) != 0 {
-        let err = Err(Error::from(errno()));
-        disarm_timer(timer)?;
-        return err;
+    impl Drop for TimerFd {
+        fn drop(&mut self) {
+            unsafe {
+                libc::close(self.0);
+            }
+        }
     }
 
-    Ok(timer)
-}
-unsafe fn disarm_timer(timer: libc::timer_t) -> Result<()> {
-    if libc::timer_delete(timer) != 0 {
-        return Err(Error::from(errno()));
+    pub async fn sleep_until(time: DateTime<Utc>) -> Result<()> {
+        let deadline = libc::timespec {
+            tv_sec: time.timestamp() as libc::time_t,
+            // chrono represents leap seconds with more than a second's worth of nanoseconds, which the kernel rejects.
+            tv_nsec: time.timestamp_subsec_nanos().min(999_999_999) as _,
+        };
+
+        let timer = AsyncFd::new(TimerFd::new()?)?;
+        timer.get_ref().arm(deadline)?;
+
+        loop {
+            let mut guard = timer.readable().await?;
+            match guard.try_io(|timer| timer.get_ref().read()) {
+                Ok(Ok(_)) => return Ok(()),
+                // The clock was changed, e.g. by NTP or by hand. The deadline is absolute, so re-arming the timer is
+                // all that is needed to account for the change; if the deadline has now passed, it expires at once.
+                Ok(Err(e)) if e.raw_os_error() == Some(libc::ECANCELED) => {
+                    timer.get_ref().arm(deadline)?
+                }
+                Ok(Err(e)) => return Err(Error::from(e)),
+                // The readiness was spurious, so wait for the file descriptor to become readable again.
+                Err(_would_block) => continue,
+            }
+        }
     }
-    Ok(())
 }
 
-#[cfg(unix)]
-pub async fn sleep_until<Tz: chrono::TimeZone>(time: DateTime<Tz>) -> Result<()> {
-    let time = time.with_timezone(&Utc);
-    // we must schedule our signal handler before the first signal appears
-    let mut alarm = signal(SignalKind::alarm())?;
-    loop {
-        let currtime = Utc::now();
-        let seconds_to_sleep = (time - currtime).num_seconds();
-        if seconds_to_sleep < 0 {
-            break;
+#[cfg(all(unix, not(target_os = "linux")))]
+mod alarm {
+    use std::{mem::MaybeUninit, ptr};
+
+    use chrono::{DateTime, Utc};
+    use errno::errno;
+    use tokio::signal::unix::{signal, SignalKind};
+
+    use super::{Error, Result};
+
+    unsafe fn arm_timer(duration: i64) -> Result<libc::timer_t> {
+        // First, initialize our timer
+        let mut timer: libc::timer_t = MaybeUninit::zeroed().assume_init();
+        // this means we are going to create a SIGALRM
+        let mut sev: libc::sigevent = MaybeUninit::zeroed().assume_init();
+        sev.sigev_notify = libc::SIGEV_SIGNAL;
+        sev.sigev_signo = SignalKind::alarm().as_raw_value();
+        if libc::timer_create(libc::CLOCK_REALTIME, &mut sev, &mut timer) != 0 {
+            return Err(Error::from(errno()));
+        }
+
+        // Now, get the time to sleep until
+        let mut its = libc::itimerspec {
+            it_interval: libc::timespec {
+                tv_sec: 0,
+                tv_nsec: 0,
+            },
+            it_value: MaybeUninit::zeroed().assume_init(),
+        };
+        // by getting the current time
+        if libc::clock_gettime(libc::CLOCK_REALTIME, &mut its.it_value) != 0 {
+            let err = Err(Error::from(errno()));
+            disarm_timer(timer)?;
+            return err;
+        }
+
+        // and changing the duration
+        its.it_value.tv_sec += duration as libc::time_t;
+
+        // Finally, arm the timer
+        if libc::timer_settime(timer, libc::TIMER_ABSTIME, &its, ptr::null_mut()) != 0 {
+            let err = Err(Error::from(errno()));
+            disarm_timer(timer)?;
+            return err;
+        }
+
+        Ok(timer)
+    }
+    unsafe fn disarm_timer(timer: libc::timer_t) -> Result<()> {
+        if libc::timer_delete(timer) != 0 {
+            return Err(Error::from(errno()));
+        }
+        Ok(())
+    }
+
+    pub async fn sleep_until(time: DateTime<Utc>) -> Result<()> {
+        // we must schedule our signal handler before the first signal appears
+        let mut alarm = signal(SignalKind::alarm())?;
+        loop {
+            let currtime = Utc::now();
+            let seconds_to_sleep = (time - currtime).num_seconds();
+            if seconds_to_sleep < 0 {
+                break;
+            }
+            // now we set a timer for the specified date
+            let timer = unsafe { arm_timer(seconds_to_sleep)? };
+            // and wait for the signal
+            alarm.recv().await;
+            unsafe { disarm_timer(timer)? }
         }
-        // now we set a timer for the specified date
-        let timer = unsafe { arm_timer(seconds_to_sleep)? };
-        // and wait for the signal
-        alarm.recv().await;
-        unsafe { disarm_timer(timer)? }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_sleep_until() {
+        let deadline = Utc::now() + Duration::milliseconds(250);
+        sleep_until(deadline).await.unwrap();
+
+        let now = Utc::now();
+        assert!(now >= deadline);
+        assert!(now - deadline < Duration::milliseconds(500));
+    }
+
+    #[tokio::test]
+    async fn test_sleep_until_past() {
+        let start = Utc::now();
+        sleep_until(start - Duration::seconds(10)).await.unwrap();
+        assert!(Utc::now() - start < Duration::milliseconds(500));
+    }
+
+    #[tokio::test]
+    async fn test_sleep_until_concurrently() {
+        // the sleeps run side by side, rather than one after the other
+        let start = Utc::now();
+        let (first, second) = tokio::join!(
+            sleep_until(start + Duration::milliseconds(400)),
+            sleep_until(start + Duration::milliseconds(200)),
+        );
+        first.unwrap();
+        second.unwrap();
+
+        let elapsed = Utc::now() - start;
+        assert!(elapsed >= Duration::milliseconds(400));
+        assert!(elapsed < Duration::milliseconds(600));
     }
-    Ok(())
 }
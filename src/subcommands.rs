@@ -1,54 +1,164 @@
+use std::collections::BTreeMap;
+use std::fs;
 use std::io::Write;
+use std::path::Path;
 use std::result;
+use std::sync::Arc;
 
-use chrono::{Duration, Local};
+use chrono::{Duration, Utc};
 use crossterm::{cursor, terminal, ExecutableCommand, QueueableCommand};
+use serde::Serialize;
+use tokio::signal::unix::{signal, SignalKind};
 
-use super::{calc, domain, errors, report, utils};
+use super::{
+    calc, clock::Clock, crontab, domain, errors, exec, heliotab, ics, output, report, sleep,
+    systemd, template, upcoming, utils,
+};
 
 type Result<T> = result::Result<T, errors::HeliocronError>;
 
-pub fn display_report(solar_calculations: calc::SolarCalculations, json: bool) -> Result<()> {
-    let report = report::Report::new(solar_calculations);
-    let output = if json {
-        serde_json::to_string(&report).unwrap()
-    } else {
-        report.to_string()
-    };
-    println!("{output}");
+pub fn display_report(
+    solar_calculations: calc::SolarCalculations,
+    output: &domain::Output,
+    custom_events: &BTreeMap<String, domain::CustomEvent>,
+) -> Result<()> {
+    let report = report::Report::with_custom_event_definitions(solar_calculations, custom_events);
+    print!("{}", output::render_output(&report, output));
+    Ok(())
+}
+
+pub fn display_range_report(
+    solar_calculations: calc::SolarCalculations,
+    range: domain::DateRange,
+    output: &domain::Output,
+    custom_events: &BTreeMap<String, domain::CustomEvent>,
+) -> Result<()> {
+    let report = report::RangeReport::new(
+        range
+            .dates()
+            .map(|date| {
+                report::Report::with_custom_event_definitions(
+                    solar_calculations.for_date(date),
+                    custom_events,
+                )
+            })
+            .collect(),
+    );
+    match output {
+        domain::Output::Format(format) => print!("{}", output::render(&report, *format)),
+        // the template is applied to each day in turn
+        domain::Output::Template(_) => {
+            for day in &report.days {
+                print!("{}", output::render_output(day, output));
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn get(
+    solar_calculations: calc::SolarCalculations,
+    query: domain::Query,
+    seconds: bool,
+) -> Result<()> {
+    match query {
+        domain::Query::EventTime { event, adjustment } => {
+            // the occurrence on the date of the calculations
+            let datetime = solar_calculations.occurrences(&event, &adjustment, 1)[0]
+                .1
+                .ok_or(errors::HeliocronError::Runtime(
+                    errors::RuntimeErrorKind::NonOccurringEvent,
+                ))?;
+            match seconds {
+                true => println!("{}", datetime.timestamp()),
+                false => println!("{}", datetime.to_rfc3339()),
+            }
+        }
+        domain::Query::DayLength => {
+            let day_length = solar_calculations.day_length();
+            match seconds {
+                true => println!("{}", day_length.num_seconds()),
+                false => println!("{}", template::duration_hms(day_length)),
+            }
+        }
+    }
     Ok(())
 }
 
 pub async fn wait(
     event: domain::Event,
-    offset: Duration,
+    adjustment: domain::EventAdjustment,
     solar_calculations: calc::SolarCalculations,
-    run_missed_task: bool,
+    missed_event: domain::MissedEventHandling,
+    next: Option<u32>,
+    clock: &dyn Clock,
 ) -> Result<()> {
-    let event_time = solar_calculations.event_time(event);
+    let domain::EventAdjustment {
+        offset,
+        jitter,
+        clamp,
+    } = adjustment;
+
+    // The shift is chosen once, up front, so that it is the same whichever day the event is found on.
+    let shift = jitter.map(|jitter| jitter.shift());
+    if let Some(shift) = shift {
+        println!(
+            "Shifting the event by {}{} at random.",
+            if shift < Duration::zero() { "" } else { "+" },
+            template::duration_hms(shift)
+        );
+    }
+    let offset = offset + shift.unwrap_or_else(Duration::zero);
+
+    let event_time = match next {
+        Some(horizon) => {
+            next_event_time(event, offset, clamp, &solar_calculations, horizon, clock)?
+        }
+        None => solar_calculations.event_time(event),
+    };
 
     match event_time.0 {
         Some(datetime) => {
-            let wait_until = datetime + offset;
-            utils::wait(wait_until).await?;
+            let wait_until = clamp.apply(datetime + offset, solar_calculations.time_zone);
+            if wait_until != datetime + offset {
+                println!(
+                    "The event (+/- offset) at {} has been clamped to {wait_until}.",
+                    datetime + offset
+                );
+            }
+            utils::wait(wait_until, clock).await?;
 
             // If the device running heliocron is asleep for whetever reason, it is possible that this future
             // will return after `wait_until`. As such, we need to handle whether to run or skip the task
-            // if the event was missed. We allow a default tolerance of 30s, which should be more than enough to
-            // catch any scheduling delays that could cause a second or two's delay. At some point, this arbitrary
-            // number could be made configurable, if desired.
-            if run_missed_task {
+            // if the event was missed, according to the chosen policy and grace period.
+            let now = clock.now().with_timezone(wait_until.offset());
+            let late_by = now - wait_until;
+            let run = missed_event.should_run(late_by);
+
+            if let Some(path) = &missed_event.audit_log {
+                let record = WakeupRecord {
+                    scheduled_for: wait_until.to_rfc3339(),
+                    woke_at: now.to_rfc3339(),
+                    late_by: late_by.num_seconds(),
+                    grace: missed_event.grace.num_seconds(),
+                    policy: missed_event.policy,
+                    jitter: shift.map(|shift| shift.num_seconds()),
+                    run,
+                };
+                if let Err(e) = record.append_to(path) {
+                    eprintln!(
+                        "Warning - couldn't write to the audit log '{}': {e}",
+                        path.display()
+                    );
+                }
+            }
+
+            if run {
                 Ok(())
             } else {
-                let now = chrono::Utc::now().with_timezone(wait_until.offset());
-                let missed_by = (now - wait_until).num_seconds();
-                if missed_by > 30 {
-                    Err(errors::HeliocronError::Runtime(
-                        errors::RuntimeErrorKind::EventMissed(missed_by),
-                    ))
-                } else {
-                    Ok(())
-                }
+                Err(errors::HeliocronError::Runtime(
+                    errors::RuntimeErrorKind::EventMissed(late_by.num_seconds()),
+                ))
             }
         }
         None => Err(errors::HeliocronError::Runtime(
@@ -57,18 +167,121 @@ pub async fn wait(
     }
 }
 
-pub fn poll(solar_calculations: calc::SolarCalculations, watch: bool, json: bool) -> Result<()> {
-    let mut report = report::PollReport::new(&solar_calculations);
-    let output = if json {
-        serde_json::to_string(&report).unwrap()
+/// A machine-readable record of how late a wait ended, and whether the task was run as a result.
+#[derive(Serialize)]
+struct WakeupRecord {
+    scheduled_for: String,
+    woke_at: String,
+    /// How many whole seconds after `scheduled_for` the wait ended.
+    late_by: i64,
+    grace: i64,
+    policy: domain::MissedEventPolicy,
+    /// The random shift applied to `scheduled_for` by --jitter, in seconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    jitter: Option<i64>,
+    run: bool,
+}
+
+impl WakeupRecord {
+    /// Append the record to the file as a single line of JSON, creating the file if necessary.
+    fn append_to(&self, path: &Path) -> std::io::Result<()> {
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        writeln!(file, "{}", serde_json::to_string(self).unwrap())
+    }
+}
+
+pub async fn wait_for_elevation(
+    threshold: domain::ElevationThreshold,
+    mut immediate: bool,
+    solar_calculations: calc::SolarCalculations,
+    clock: &dyn Clock,
+) -> Result<()> {
+    let now = || clock.now_in(solar_calculations.time_zone);
+
+    loop {
+        let crossing = solar_calculations
+            .next_elevation_crossing(&threshold, now(), immediate)
+            .ok_or(errors::HeliocronError::Runtime(
+                errors::RuntimeErrorKind::NoOccurrenceWithin(calc::ELEVATION_CROSSING_HORIZON),
+            ))?;
+
+        if crossing > now() {
+            utils::wait(crossing, clock).await?;
+        }
+
+        // Rather than trusting the prediction, check that the Sun really has crossed the threshold, in case the
+        // system clock was changed whilst we were asleep. If not, wait for the next crossing.
+        if threshold.is_met(solar_calculations.solar_elevation_at(now())) {
+            return Ok(());
+        }
+        immediate = false;
+    }
+}
+
+/// Search day by day, starting from the date of the calculations, for the first occurrence of the event for which
+/// the event time plus the offset, once clamped, is still in the future.
+fn next_event_time(
+    event: domain::Event,
+    offset: Duration,
+    clamp: domain::Clamp,
+    solar_calculations: &calc::SolarCalculations,
+    horizon: u32,
+    clock: &dyn Clock,
+) -> Result<domain::EventTime> {
+    let now = clock.now();
+    let start_date = solar_calculations.date.date().naive_local();
+
+    (0..=horizon)
+        .map(|days| start_date + Duration::days(days.into()))
+        .find_map(|date| {
+            let event_time = solar_calculations.for_date(date).event_time(event.clone());
+            match event_time.0 {
+                Some(datetime)
+                    if clamp.apply(datetime + offset, solar_calculations.time_zone) > now =>
+                {
+                    Some((date, event_time))
+                }
+                _ => None,
+            }
+        })
+        .map(|(date, event_time)| {
+            println!("The next occurrence of the event is on {date}.");
+            event_time
+        })
+        .ok_or(errors::HeliocronError::Runtime(
+            errors::RuntimeErrorKind::NoOccurrenceWithin(horizon),
+        ))
+}
+
+pub async fn exec(command: &[String], timeout: Option<Duration>) -> Result<()> {
+    println!("Running '{}'.", command.join(" "));
+
+    let status = exec::run(command, timeout).await?;
+    if status.success() {
+        Ok(())
     } else {
-        report.to_string()
-    };
+        Err(errors::HeliocronError::Runtime(
+            errors::RuntimeErrorKind::CommandFailed(status.to_string()),
+        ))
+    }
+}
+
+pub async fn poll(
+    solar_calculations: calc::SolarCalculations,
+    watch: bool,
+    output: &domain::Output,
+    clock: &dyn Clock,
+) -> Result<()> {
+    let mut report = report::PollReport::new(&solar_calculations);
+    let text = matches!(output, domain::Output::Format(domain::OutputFormat::Text));
 
     if !watch {
-        println!("{output}");
+        print!("{}", output::render_output(&report, output));
     } else {
-        if !json {
+        if text {
             println!("Displaying solar calculations in real time. Press ctrl+C to cancel.\n");
         }
 
@@ -77,9 +290,11 @@ pub fn poll(solar_calculations: calc::SolarCalculations, watch: bool, json: bool
         stdout.queue(cursor::SavePosition).unwrap();
         stdout.execute(cursor::Hide).unwrap();
 
+        let mut first = true;
         loop {
-            if json {
-                println!("{}", serde_json::to_string(&report).unwrap());
+            if !text {
+                print!("{}", output::render_output_streamed(&report, output, first));
+                first = false;
             } else {
                 stdout.queue(cursor::RestorePosition).unwrap();
                 stdout
@@ -89,10 +304,11 @@ pub fn poll(solar_calculations: calc::SolarCalculations, watch: bool, json: bool
                 stdout.flush().unwrap();
             }
 
-            std::thread::sleep(std::time::Duration::from_secs(1));
+            clock
+                .sleep_until(clock.now() + Duration::seconds(1))
+                .await?;
 
-            let now = Local::now();
-            let now = now.with_timezone(now.offset());
+            let now = clock.now_in(solar_calculations.time_zone);
 
             let calcs = solar_calculations.refresh(now);
 
@@ -102,3 +318,429 @@ pub fn poll(solar_calculations: calc::SolarCalculations, watch: bool, json: bool
 
     Ok(())
 }
+
+pub fn next(
+    solar_calculations: calc::SolarCalculations,
+    limit: domain::UpcomingLimit,
+    output: &domain::Output,
+    custom_events: &BTreeMap<String, domain::CustomEvent>,
+) -> Result<()> {
+    let upcoming = upcoming::UpcomingEvents::new(&solar_calculations, custom_events, limit);
+    match output {
+        domain::Output::Format(format) => print!("{}", output::render(&upcoming, *format)),
+        // the template is applied to each event in turn
+        domain::Output::Template(template) => {
+            for event in &upcoming.events {
+                println!("{}", template.render(event));
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn crontab(
+    scheduled: domain::ScheduledCommand,
+    days: u32,
+    install: Option<domain::CrontabTarget>,
+    solar_calculations: calc::SolarCalculations,
+    clock: &dyn Clock,
+) -> Result<()> {
+    let now = clock.now_in(solar_calculations.time_zone);
+    let block = crontab::block(
+        &scheduled,
+        &solar_calculations,
+        days,
+        clock.local_time_zone(),
+        now,
+    );
+    let name = &scheduled.name;
+
+    match install {
+        None => print!("{block}"),
+        Some(target) => {
+            let install_failed = |msg: String| {
+                errors::HeliocronError::Runtime(errors::RuntimeErrorKind::CrontabInstallFailed(msg))
+            };
+            match target {
+                domain::CrontabTarget::File(path) => {
+                    let existing = match fs::read_to_string(&path) {
+                        Ok(existing) => existing,
+                        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+                        Err(e) => {
+                            return Err(install_failed(format!(
+                                "couldn't read '{}': {e}",
+                                path.display()
+                            )))
+                        }
+                    };
+                    fs::write(&path, crontab::replace_block(&existing, name, &block)).map_err(
+                        |e| install_failed(format!("couldn't write '{}': {e}", path.display())),
+                    )?;
+                }
+                domain::CrontabTarget::User => {
+                    let existing = read_user_crontab().map_err(install_failed)?;
+                    write_user_crontab(&crontab::replace_block(&existing, name, &block))
+                        .map_err(install_failed)?;
+                }
+            }
+            println!("Installed {days} days of entries in the '{name}' block.");
+        }
+    }
+    Ok(())
+}
+
+pub fn systemd(
+    scheduled: domain::ScheduledCommand,
+    days: u32,
+    output_dir: &Path,
+    system: bool,
+    regenerate: &[String],
+    solar_calculations: calc::SolarCalculations,
+    clock: &dyn Clock,
+) -> Result<()> {
+    let now = clock.now_in(solar_calculations.time_zone);
+    let units = systemd::units(
+        &scheduled,
+        &solar_calculations,
+        days,
+        now,
+        regenerate,
+        system,
+    );
+
+    let units_failed = |msg: String| {
+        errors::HeliocronError::Runtime(errors::RuntimeErrorKind::SystemdUnitsFailed(msg))
+    };
+    fs::create_dir_all(output_dir)
+        .map_err(|e| units_failed(format!("couldn't create '{}': {e}", output_dir.display())))?;
+    for unit in &units {
+        let path = output_dir.join(&unit.file_name);
+        fs::write(&path, &unit.contents)
+            .map_err(|e| units_failed(format!("couldn't write '{}': {e}", path.display())))?;
+        println!("Wrote {}", path.display());
+    }
+
+    let systemctl = match system {
+        true => "systemctl",
+        false => "systemctl --user",
+    };
+    let prefix = systemd::unit_prefix(&scheduled.name);
+    println!(
+        "\nTo start running the command, reload systemd and enable the timers:\n  \
+        {systemctl} daemon-reload\n  \
+        {systemctl} enable --now {prefix}.timer {prefix}-refresh.timer"
+    );
+    Ok(())
+}
+
+pub fn ics(
+    range: domain::DateRange,
+    events: &[domain::RawEventName],
+    polar: bool,
+    solar_calculations: calc::SolarCalculations,
+    clock: &dyn Clock,
+) -> Result<()> {
+    let reports: Vec<_> = range
+        .dates()
+        .map(|date| report::Report::new(solar_calculations.for_date(date)))
+        .collect();
+
+    print!("{}", ics::calendar(&reports, events, polar, clock.now()));
+    Ok(())
+}
+
+/// Read the crontab of the current user, which is empty if they don't have one yet.
+fn read_user_crontab() -> result::Result<String, String> {
+    let output = std::process::Command::new("crontab")
+        .arg("-l")
+        .output()
+        .map_err(|e| format!("couldn't run 'crontab -l': {e}"))?;
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    match output.status.success() {
+        true => Ok(String::from_utf8_lossy(&output.stdout).into_owned()),
+        false if stderr.contains("no crontab") => Ok(String::new()),
+        false => Err(format!("'crontab -l' failed: {}", stderr.trim())),
+    }
+}
+
+/// Replace the crontab of the current user.
+fn write_user_crontab(crontab: &str) -> result::Result<(), String> {
+    let mut child = std::process::Command::new("crontab")
+        .arg("-")
+        .stdin(std::process::Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("couldn't run 'crontab -': {e}"))?;
+
+    // Safe to unwrap because stdin was piped above.
+    let written = child.stdin.take().unwrap().write_all(crontab.as_bytes());
+    let status = child
+        .wait()
+        .map_err(|e| format!("couldn't wait for 'crontab -': {e}"))?;
+    written.map_err(|e| format!("couldn't write to 'crontab -': {e}"))?;
+
+    match status.success() {
+        true => Ok(()),
+        false => Err(format!("'crontab -' failed with {status}")),
+    }
+}
+
+/// List the named locations from the configuration file, marking the default location.
+pub fn locations(
+    locations: &BTreeMap<String, domain::Location>,
+    default: Option<&str>,
+) -> Result<()> {
+    if locations.is_empty() {
+        println!("No locations are defined in ~/.config/heliocron.toml.");
+        return Ok(());
+    }
+
+    for (name, location) in locations {
+        let marker = if default == Some(name.as_str()) {
+            " (default)"
+        } else {
+            ""
+        };
+        let time_zone = location
+            .time_zone
+            .map_or_else(|| "-".to_string(), |tz| tz.to_string());
+        println!(
+            "{name}{marker}: latitude {}, longitude {}, time zone {time_zone}, elevation {}m",
+            location.coordinates.latitude, location.coordinates.longitude, location.elevation
+        );
+    }
+    Ok(())
+}
+
+/// Returns whether the condition holds at the date and time of the calculations.
+pub fn check(condition: domain::Condition, solar_calculations: calc::SolarCalculations) -> bool {
+    let elevation = solar_calculations.solar_elevation();
+    let day_part = domain::DayPart::from_elevation_angle(elevation);
+    let overlay = domain::DayPartOverlay::from_elevation_angle(elevation);
+
+    match condition {
+        domain::Condition::Day => matches!(day_part, domain::DayPart::Day),
+        domain::Condition::Night => matches!(day_part, domain::DayPart::Night),
+        domain::Condition::Twilight => matches!(
+            day_part,
+            domain::DayPart::CivilTwilight
+                | domain::DayPart::NauticalTwilight
+                | domain::DayPart::AstronomicalTwilight
+        ),
+        domain::Condition::CivilTwilight => matches!(day_part, domain::DayPart::CivilTwilight),
+        domain::Condition::NauticalTwilight => {
+            matches!(day_part, domain::DayPart::NauticalTwilight)
+        }
+        domain::Condition::AstronomicalTwilight => {
+            matches!(day_part, domain::DayPart::AstronomicalTwilight)
+        }
+        domain::Condition::GoldenHour => overlay == Some(domain::DayPartOverlay::GoldenHour),
+        domain::Condition::BlueHour => overlay == Some(domain::DayPartOverlay::BlueHour),
+        domain::Condition::Between {
+            from,
+            from_offset,
+            to,
+            to_offset,
+        } => solar_calculations.is_between(
+            (&from, from_offset),
+            (&to, to_offset),
+            solar_calculations.date,
+        ),
+    }
+}
+
+pub async fn daemon(
+    heliotab_path: &Path,
+    grace: Duration,
+    solar_calculations: calc::SolarCalculations,
+    clock: Arc<dyn Clock>,
+) -> Result<()> {
+    let time_zone = solar_calculations.time_zone;
+    let now = || clock.now_in(time_zone);
+
+    let mut heliotab = heliotab::Heliotab::from_file(heliotab_path)?;
+    println!(
+        "[{}] Loaded {} entries from {}",
+        timestamp(clock.as_ref(), time_zone),
+        heliotab.entries.len(),
+        heliotab_path.display()
+    );
+
+    let mut hangup = signal(SignalKind::hangup()).map_err(sleep::Error::from)?;
+    let mut after = now();
+
+    loop {
+        let next_due = heliotab
+            .next_due(&solar_calculations, after)
+            .map(|(due_at, entries)| (due_at, entries.into_iter().cloned().collect::<Vec<_>>()));
+
+        // If nothing is due in the coming week, check again tomorrow, when there will be a new day to consider.
+        let wake_at = match next_due {
+            Some((due_at, _)) => due_at,
+            None => after + Duration::days(1),
+        };
+
+        tokio::select! {
+            result = clock.sleep_until(wake_at.with_timezone(&Utc)) => result?,
+            _ = hangup.recv() => {
+                match heliotab::Heliotab::from_file(heliotab_path) {
+                    Ok(reloaded) => {
+                        heliotab = reloaded;
+                        println!(
+                            "[{}] Reloaded {} entries from {}",
+                            timestamp(clock.as_ref(), time_zone),
+                            heliotab.entries.len(),
+                            heliotab_path.display()
+                        );
+                    }
+                    Err(e) => eprintln!(
+                        "[{}] Failed to reload heliotab, keeping the previous entries: {e}",
+                        timestamp(clock.as_ref(), time_zone)
+                    ),
+                }
+                after = now();
+                continue;
+            }
+        }
+
+        if let Some((due_at, entries)) = next_due {
+            // As with `wait`, the event is considered to have been missed if we wake up more than the grace period
+            // after it, e.g. because the device was suspended.
+            let missed_by = (now() - due_at).num_seconds();
+            for entry in entries {
+                if missed_by > grace.num_seconds() {
+                    eprintln!(
+                        "[{}] line {} ({}): skipped '{}', event missed by {missed_by}s",
+                        timestamp(clock.as_ref(), time_zone),
+                        entry.line,
+                        entry.event_spec,
+                        entry.command
+                    );
+                } else {
+                    tokio::spawn(run_heliotab_entry(entry, time_zone, clock.clone()));
+                }
+            }
+        }
+        after = wake_at;
+    }
+}
+
+async fn run_heliotab_entry(
+    entry: heliotab::Entry,
+    time_zone: domain::TimeZone,
+    clock: Arc<dyn Clock>,
+) {
+    println!(
+        "[{}] line {} ({}): running '{}'",
+        timestamp(clock.as_ref(), time_zone),
+        entry.line,
+        entry.event_spec,
+        entry.command
+    );
+
+    let status = tokio::process::Command::new("sh")
+        .arg("-c")
+        .arg(&entry.command)
+        .status()
+        .await;
+
+    match status {
+        Ok(status) if status.success() => println!(
+            "[{}] line {} ({}): '{}' completed successfully",
+            timestamp(clock.as_ref(), time_zone),
+            entry.line,
+            entry.event_spec,
+            entry.command
+        ),
+        Ok(status) => eprintln!(
+            "[{}] line {} ({}): '{}' failed with {status}",
+            timestamp(clock.as_ref(), time_zone),
+            entry.line,
+            entry.event_spec,
+            entry.command
+        ),
+        Err(e) => eprintln!(
+            "[{}] line {} ({}): '{}' couldn't be run: {e}",
+            timestamp(clock.as_ref(), time_zone),
+            entry.line,
+            entry.event_spec,
+            entry.command
+        ),
+    }
+}
+
+/// The current time in the given time zone, formatted for log messages.
+fn timestamp(clock: &dyn Clock, time_zone: domain::TimeZone) -> String {
+    clock
+        .now_in(time_zone)
+        .format("%Y-%m-%d %H:%M:%S %:z")
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::DateTime;
+
+    use super::*;
+    use crate::clock::{SimulatedClock, Sleep};
+    use crate::domain::{Coordinates, Latitude, Longitude};
+
+    /// A simulated clock which always wakes up an hour late, as though the system had been suspended whilst sleeping.
+    struct SuspendedClock(SimulatedClock);
+
+    impl Clock for SuspendedClock {
+        fn now(&self) -> DateTime<Utc> {
+            self.0.now()
+        }
+
+        fn sleep_until(&self, time: DateTime<Utc>) -> Sleep<'_> {
+            self.0.sleep_until(time + Duration::hours(1))
+        }
+
+        fn local_time_zone(&self) -> domain::TimeZone {
+            self.0.local_time_zone()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_daemon_grace() {
+        let start = DateTime::parse_from_rfc3339("2022-06-11T12:00:00+01:00").unwrap();
+        let coordinates = Coordinates::new(
+            Latitude::new(51.4769).unwrap(),
+            Longitude::new(-0.0005).unwrap(),
+        );
+        let solar_calculations = calc::SolarCalculations::new(start, coordinates);
+
+        // the entry leaves the marker behind if it is run
+        let temp_path = |name: &str| {
+            std::env::temp_dir().join(format!("heliocron-{}-{name}", std::process::id()))
+        };
+        let marker = temp_path("grace-marker");
+        let heliotab = temp_path("grace-heliotab");
+        fs::write(
+            &heliotab,
+            format!("sunset 00:00 * touch {}\n", marker.display()),
+        )
+        .unwrap();
+
+        // sunset is missed by an hour, so the entry is only run if that is within the grace period
+        for (grace, runs) in [(Duration::minutes(30), false), (Duration::hours(2), true)] {
+            let clock =
+                SuspendedClock(SimulatedClock::new(start).with_end(start + Duration::days(1)));
+            // The daemon never returns, but comes to rest once its clock has reached the end.
+            let daemon = daemon(
+                &heliotab,
+                grace,
+                solar_calculations.clone(),
+                Arc::new(clock),
+            );
+            let rest = std::time::Duration::from_millis(500);
+            assert!(tokio::time::timeout(rest, daemon).await.is_err());
+
+            assert_eq!(marker.exists(), runs);
+            let _ = fs::remove_file(&marker);
+        }
+
+        fs::remove_file(heliotab).unwrap();
+    }
+}
@@ -0,0 +1,278 @@
+//! Generation of systemd units, as used by the `systemd` subcommand.
+//!
+//! As an alternative to cron, the times of an event can be calculated in advance and written into a timer unit, with
+//! one `OnCalendar=` line per day, which starts a service unit running the command:
+//!
+//! ```text
+//! heliocron-lights.service          runs the command
+//! heliocron-lights.timer            starts heliocron-lights.service at each occurrence of the event
+//! heliocron-lights-refresh.service  regenerates the units and reloads systemd
+//! heliocron-lights-refresh.timer    starts heliocron-lights-refresh.service once a day
+//! ```
+//!
+//! The times are given in UTC, so that they are unaffected by any daylight saving changes within the horizon.
+use chrono::{DateTime, FixedOffset, Utc};
+
+use super::{calc, domain, utils};
+
+/// The first line of every generated unit.
+const HEADER: &str =
+    "# Generated by heliocron. Any changes will be lost when the units are regenerated.";
+
+/// A unit file, ready to be written into the chosen directory.
+pub struct Unit {
+    pub file_name: String,
+    pub contents: String,
+}
+
+/// The prefix of the file names of all of the units for the schedule with the given name.
+pub fn unit_prefix(name: &str) -> String {
+    format!("heliocron-{name}")
+}
+
+/// Generate the units for the given number of days, starting from the date of the calculations.
+///
+/// The refresh service runs the `regenerate` command, which should recreate these units, before reloading systemd.
+pub fn units(
+    scheduled: &domain::ScheduledCommand,
+    solar_calculations: &calc::SolarCalculations,
+    days: u32,
+    now: DateTime<FixedOffset>,
+    regenerate: &[String],
+    system: bool,
+) -> Vec<Unit> {
+    let prefix = unit_prefix(&scheduled.name);
+    let description = format!("Description=heliocron schedule '{}'", scheduled.name);
+    let systemctl = match system {
+        true => "/bin/systemctl",
+        false => "/bin/systemctl --user",
+    };
+
+    let service = [
+        HEADER,
+        "[Unit]",
+        &description,
+        "",
+        "[Service]",
+        "Type=oneshot",
+        &format!("ExecStart={}", command_line(&scheduled.command)),
+    ];
+
+    let mut timer = vec![
+        HEADER.to_string(),
+        "[Unit]".to_string(),
+        description.clone(),
+        "".to_string(),
+        "[Timer]".to_string(),
+        "AccuracySec=1s".to_string(),
+    ];
+    timer.extend(calendar(scheduled, solar_calculations, days, now));
+    timer.extend(["".to_string(), "[Install]".to_string()]);
+    timer.push("WantedBy=timers.target".to_string());
+
+    let refresh_service = [
+        HEADER,
+        "[Unit]",
+        &format!(
+            "Description=Regenerate the units of heliocron schedule '{}'",
+            scheduled.name
+        ),
+        "",
+        "[Service]",
+        "Type=oneshot",
+        &format!("ExecStart={}", command_line(regenerate)),
+        &format!("ExecStartPost={systemctl} daemon-reload"),
+        &format!("ExecStartPost={systemctl} try-restart {prefix}.timer"),
+    ];
+
+    let refresh_timer = [
+        HEADER,
+        "[Unit]",
+        &format!(
+            "Description=Regenerate the units of heliocron schedule '{}' daily",
+            scheduled.name
+        ),
+        "",
+        "[Timer]",
+        "OnCalendar=daily",
+        "Persistent=true",
+        "",
+        "[Install]",
+        "WantedBy=timers.target",
+    ];
+
+    let unit = |suffix: &str, lines: &[&str]| Unit {
+        file_name: format!("{prefix}{suffix}"),
+        contents: lines.join("\n") + "\n",
+    };
+    vec![
+        unit(".service", &service),
+        unit(
+            ".timer",
+            &timer.iter().map(String::as_str).collect::<Vec<_>>(),
+        ),
+        unit("-refresh.service", &refresh_service),
+        unit("-refresh.timer", &refresh_timer),
+    ]
+}
+
+/// The `OnCalendar=` lines of the timer, one per day, with a comment in place of those on which the event isn't due.
+fn calendar(
+    scheduled: &domain::ScheduledCommand,
+    solar_calculations: &calc::SolarCalculations,
+    days: u32,
+    now: DateTime<FixedOffset>,
+) -> Vec<String> {
+    solar_calculations
+        .scheduled_occurrences(
+            &scheduled.event,
+            &scheduled.adjustment,
+            days,
+            now,
+            |datetime| datetime,
+        )
+        .into_iter()
+        .map(|(date, occurrence)| match occurrence {
+            calc::Occurrence::Due(datetime) => format!(
+                "OnCalendar={}",
+                datetime.with_timezone(&Utc).format("%Y-%m-%d %H:%M:%S UTC")
+            ),
+            occurrence => occurrence.comment(date),
+        })
+        .collect()
+}
+
+/// Returns whether the name can be used in the file names of the units.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_.:-".contains(c))
+}
+
+/// Join the command and its arguments into a single command line for systemd, quoting where necessary. Percent and
+/// dollar signs are escaped because systemd would otherwise expand them as specifiers and environment variables.
+fn command_line(command: &[String]) -> String {
+    utils::join_command(command, "_@%$+=:,./-", |arg| {
+        format!("\"{}\"", arg.replace('\\', r"\\").replace('"', "\\\""))
+    })
+    .replace('%', "%%")
+    .replace('$', "$$")
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+    use crate::fixtures::{scheduled_command, solar_calculations};
+
+    #[test]
+    fn test_calendar() {
+        let now = DateTime::parse_from_rfc3339("2022-06-11T12:00:00+01:00").unwrap();
+
+        // sunset is at 21:16:12 and 21:16:52 on these dates, which is an hour earlier in UTC
+        let scheduled = scheduled_command(
+            "lights",
+            domain::EventName::Sunset,
+            Duration::minutes(-30),
+            &["lights", "on"],
+        );
+        assert_eq!(
+            calendar(&scheduled, &solar_calculations(51.4769), 2, now),
+            [
+                "OnCalendar=2022-06-11 19:46:12 UTC",
+                "OnCalendar=2022-06-12 19:46:52 UTC"
+            ]
+        );
+
+        let scheduled = scheduled_command(
+            "lights",
+            domain::EventName::Sunrise,
+            Duration::zero(),
+            &["lights", "on"],
+        );
+        assert_eq!(
+            calendar(&scheduled, &solar_calculations(51.4769), 1, now),
+            ["# 2022-06-11: the event has already passed, at 2022-06-11 04:43:07 +01:00"]
+        );
+
+        // at 70 degrees north, the Sun doesn't set in June
+        let scheduled = scheduled_command(
+            "lights",
+            domain::EventName::Sunset,
+            Duration::zero(),
+            &["lights", "on"],
+        );
+        assert_eq!(
+            calendar(&scheduled, &solar_calculations(70.0), 1, now),
+            ["# 2022-06-11: the event does not occur on this day"]
+        );
+    }
+
+    #[test]
+    fn test_units() {
+        let now = DateTime::parse_from_rfc3339("2022-06-11T12:00:00+01:00").unwrap();
+        let scheduled = scheduled_command(
+            "lights",
+            domain::EventName::Sunset,
+            Duration::zero(),
+            &["lights", "on"],
+        );
+        let regenerate = ["/usr/bin/heliocron".to_string(), "systemd".to_string()];
+
+        let units = units(
+            &scheduled,
+            &solar_calculations(51.4769),
+            1,
+            now,
+            &regenerate,
+            false,
+        );
+        let file_names: Vec<_> = units.iter().map(|unit| unit.file_name.as_str()).collect();
+        assert_eq!(
+            file_names,
+            [
+                "heliocron-lights.service",
+                "heliocron-lights.timer",
+                "heliocron-lights-refresh.service",
+                "heliocron-lights-refresh.timer"
+            ]
+        );
+
+        assert!(units[0].contents.contains("\nExecStart=lights on\n"));
+        assert!(units[1]
+            .contents
+            .contains("\nOnCalendar=2022-06-11 20:16:12 UTC\n"));
+        assert!(units[2]
+            .contents
+            .contains("\nExecStart=/usr/bin/heliocron systemd\n"));
+        assert!(units[2].contents.contains(
+            "\nExecStartPost=/bin/systemctl --user try-restart heliocron-lights.timer\n"
+        ));
+        assert!(units[3].contents.contains("\nOnCalendar=daily\n"));
+    }
+
+    #[test]
+    fn test_command_line() {
+        let command = |args: &[&str]| {
+            command_line(&args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>())
+        };
+
+        assert_eq!(command(&["/usr/bin/lights", "on"]), "/usr/bin/lights on");
+        assert_eq!(command(&["echo", "it's dark"]), "echo \"it's dark\"");
+        assert_eq!(command(&["echo", r#"say "hi""#]), r#"echo "say \"hi\"""#);
+        assert_eq!(command(&["date", "+%H:%M"]), "date +%%H:%%M");
+        assert_eq!(command(&["echo", "$HOME"]), "echo $$HOME");
+        assert_eq!(command(&["echo", ""]), "echo \"\"");
+    }
+
+    #[test]
+    fn test_is_valid_name() {
+        assert!(is_valid_name("lights"));
+        assert!(is_valid_name("porch_lights-2"));
+        assert!(!is_valid_name(""));
+        assert!(!is_valid_name("porch lights"));
+        assert!(!is_valid_name("../lights"));
+    }
+}
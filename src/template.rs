@@ -0,0 +1,373 @@
+//! Templates, in which the values of a report are substituted into text chosen with `--template`.
+//!
+//! A variable is the dotted name of a field of the report, as in the headings of `--format csv`, in braces. It may be
+//! followed by a colon and a format, which depends on the kind of the variable:
+//!
+//! ```text
+//! {sunrise:%H:%M}        times take strftime-style specifiers, e.g. '%s' for a Unix timestamp
+//! {day_length:%Hh%Mm}    durations take '%H', '%M' and '%S', or '%-H' etc without padding, and '%s' for the seconds
+//! {solar_elevation:.1}   numbers take a precision
+//! ```
+//!
+//! Literal braces are written as `{{` and `}}`. The template is checked against the variables of the report when it is
+//! parsed, so that a mistake is reported before anything is output.
+use chrono::{
+    format::{Item, StrftimeItems},
+    DateTime, Duration, FixedOffset,
+};
+use serde::Serialize;
+
+/// The format of a duration without a format of its own, which matches the text report, e.g. "16h 32m 14s".
+const DEFAULT_DURATION_FORMAT: &str = "%-Hh %-Mm %-Ss";
+
+/// The kinds of variable, each of which takes a different kind of format.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Time,
+    Duration,
+    Number,
+    Text,
+}
+
+/// The value of a variable. Times, durations and text which are `None`, such as the time of an event which doesn't
+/// occur, are rendered as the template's text for missing values.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Time(Option<DateTime<FixedOffset>>),
+    Duration(Option<Duration>),
+    Number(f64),
+    Text(Option<String>),
+}
+
+impl Value {
+    /// The text of a value which is serialized as a string, such as the `snake_case` name of an enum variant.
+    pub fn text<T: Serialize>(value: &T) -> Self {
+        Self::Text(
+            serde_json::to_value(value)
+                .ok()
+                .and_then(|value| value.as_str().map(str::to_string)),
+        )
+    }
+
+    pub fn kind(&self) -> Kind {
+        match self {
+            Self::Time(_) => Kind::Time,
+            Self::Duration(_) => Kind::Duration,
+            Self::Number(_) => Kind::Number,
+            Self::Text(_) => Kind::Text,
+        }
+    }
+}
+
+/// A report whose fields can be substituted into a template.
+pub trait Variables {
+    /// Returns the value of the variable with the given name, or `None` if there is no such variable.
+    fn value(&self, name: &str) -> Option<Value>;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    parts: Vec<Part>,
+    never: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Literal(String),
+    Variable { name: String, format: Format },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Format {
+    Default,
+    Time(String),
+    Duration(Vec<DurationItem>),
+    Precision(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DurationItem {
+    Literal(char),
+    Hours { padded: bool },
+    Minutes { padded: bool },
+    Seconds { padded: bool },
+    TotalSeconds,
+}
+
+impl Template {
+    /// Parse the template, where `kind_of` gives the kind of each variable of the report, or `None` if the report has
+    /// no such variable.
+    pub fn parse(template: &str, kind_of: impl Fn(&str) -> Option<Kind>) -> Result<Self, String> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars().enumerate().peekable();
+
+        while let Some((position, c)) = chars.next() {
+            match c {
+                '{' if chars.next_if(|&(_, c)| c == '{').is_some() => literal.push('{'),
+                '}' if chars.next_if(|&(_, c)| c == '}').is_some() => literal.push('}'),
+                '}' => {
+                    return Err(format!(
+                        "Unmatched '}}' at character {}. Use '}}}}' for a literal brace.",
+                        position + 1
+                    ))
+                }
+                '{' => {
+                    let mut variable = String::new();
+                    loop {
+                        match chars.next() {
+                            Some((_, '}')) => break,
+                            Some((_, c)) => variable.push(c),
+                            None => {
+                                return Err(format!(
+                                "Unclosed '{{' at character {}. Use '{{{{' for a literal brace.",
+                                position + 1
+                            ))
+                            }
+                        }
+                    }
+
+                    let (name, format) = match variable.split_once(':') {
+                        Some((name, format)) => (name, Some(format)),
+                        None => (variable.as_str(), None),
+                    };
+                    let kind =
+                        kind_of(name).ok_or_else(|| format!("Unknown variable '{name}'."))?;
+
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(Part::Variable {
+                        name: name.to_string(),
+                        format: Format::parse(name, kind, format)?,
+                    });
+                }
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+
+        Ok(Self {
+            parts,
+            never: "Never".to_string(),
+        })
+    }
+
+    /// Set the text which replaces variables without a value, such as the time of an event which doesn't occur.
+    pub fn with_never(mut self, never: String) -> Self {
+        self.never = never;
+        self
+    }
+
+    /// Substitute the values of the report into the template.
+    pub fn render(&self, variables: &impl Variables) -> String {
+        self.parts
+            .iter()
+            .map(|part| match part {
+                Part::Literal(literal) => literal.clone(),
+                Part::Variable { name, format } => match variables.value(name) {
+                    Some(value) => format.apply(value).unwrap_or_else(|| self.never.clone()),
+                    None => self.never.clone(),
+                },
+            })
+            .collect()
+    }
+}
+
+impl Format {
+    fn parse(name: &str, kind: Kind, format: Option<&str>) -> Result<Self, String> {
+        let format = match format {
+            Some("") => return Err(format!("Missing format after ':' in '{name}'.")),
+            Some(format) => format,
+            None => return Ok(Self::Default),
+        };
+
+        match kind {
+            Kind::Time => match StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+                true => Err(format!("Invalid time format '{format}' for '{name}'.")),
+                false => Ok(Self::Time(format.to_string())),
+            },
+            Kind::Duration => duration_items(format)
+                .map(Self::Duration)
+                .ok_or_else(|| {
+                    format!(
+                        "Invalid duration format '{format}' for '{name}'. Expected '%H', '%M', '%S' or '%s'."
+                    )
+                }),
+            Kind::Number => format
+                .strip_prefix('.')
+                .and_then(|precision| precision.parse().ok())
+                .map(Self::Precision)
+                .ok_or_else(|| {
+                    format!("Invalid number format '{format}' for '{name}'. Expected a precision such as '.2'.")
+                }),
+            Kind::Text => Err(format!("'{name}' doesn't take a format.")),
+        }
+    }
+
+    /// Format the value, or return `None` if it is missing.
+    fn apply(&self, value: Value) -> Option<String> {
+        Some(match (value, self) {
+            (Value::Time(datetime), Self::Time(format)) => datetime?.format(format).to_string(),
+            (Value::Time(datetime), _) => datetime?.to_string(),
+            (Value::Duration(duration), Self::Duration(items)) => format_duration(duration?, items),
+            (Value::Duration(duration), _) => duration_text(duration?),
+            (Value::Number(number), Self::Precision(precision)) => format!("{number:.precision$}"),
+            (Value::Number(number), _) => number.to_string(),
+            (Value::Text(text), _) => text?,
+        })
+    }
+}
+
+/// Parse the specifiers of a duration format, or return `None` if any of them are invalid.
+fn duration_items(format: &str) -> Option<Vec<DurationItem>> {
+    let mut items = Vec::new();
+    let mut chars = format.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            items.push(DurationItem::Literal(c));
+            continue;
+        }
+
+        let (padded, specifier) = match chars.next()? {
+            '-' => (false, chars.next()?),
+            specifier => (true, specifier),
+        };
+        items.push(match specifier {
+            'H' => DurationItem::Hours { padded },
+            'M' => DurationItem::Minutes { padded },
+            'S' => DurationItem::Seconds { padded },
+            's' => DurationItem::TotalSeconds,
+            '%' if padded => DurationItem::Literal('%'),
+            _ => return None,
+        });
+    }
+    Some(items)
+}
+
+/// Format a duration as in the text report, e.g. "16h 32m 14s".
+pub fn duration_text(duration: Duration) -> String {
+    // The default format is known to be valid.
+    format_duration(duration, &duration_items(DEFAULT_DURATION_FORMAT).unwrap())
+}
+
+/// Format a duration as 'HH:MM:SS', where the hours may exceed 24, preceded by '-' if it is negative.
+pub fn duration_hms(duration: Duration) -> String {
+    match duration < Duration::zero() {
+        true => format!("-{}", duration_hms(-duration)),
+        false => format_duration(duration, &duration_items("%H:%M:%S").unwrap()),
+    }
+}
+
+fn format_duration(duration: Duration, items: &[DurationItem]) -> String {
+    let seconds = duration.num_seconds();
+    let number = |value: i64, padded: bool| match padded {
+        true => format!("{value:02}"),
+        false => value.to_string(),
+    };
+
+    items
+        .iter()
+        .map(|item| match *item {
+            DurationItem::Literal(c) => c.to_string(),
+            DurationItem::Hours { padded } => number(seconds / 3600, padded),
+            DurationItem::Minutes { padded } => number(seconds / 60 % 60, padded),
+            DurationItem::Seconds { padded } => number(seconds % 60, padded),
+            DurationItem::TotalSeconds => seconds.to_string(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Sample;
+
+    impl Variables for Sample {
+        fn value(&self, name: &str) -> Option<Value> {
+            Some(match name {
+                "sunrise" => Value::Time(Some(
+                    DateTime::parse_from_rfc3339("2022-06-11T04:43:07+01:00").unwrap(),
+                )),
+                "sunset" => Value::Time(None),
+                "day_length" => Value::Duration(Some(Duration::seconds(59585))),
+                "solar_elevation" => Value::Number(57.63277680057746),
+                "day_part" => Value::text(&crate::domain::DayPart::CivilTwilight),
+                _ => return None,
+            })
+        }
+    }
+
+    fn kind_of(name: &str) -> Option<Kind> {
+        Sample.value(name).map(|value| value.kind())
+    }
+
+    fn render(template: &str) -> String {
+        Template::parse(template, kind_of).unwrap().render(&Sample)
+    }
+
+    #[test]
+    fn test_render() {
+        let params = [
+            ("{sunrise:%H:%M}", "04:43"),
+            ("{sunrise:%s}", "1654918987"),
+            ("{sunrise}", "2022-06-11 04:43:07 +01:00"),
+            ("{day_length:%Hh%Mm}", "16h33m"),
+            ("{day_length:%s}", "59585"),
+            ("{day_length}", "16h 33m 5s"),
+            ("{day_length:%-H:%M:%S %%}", "16:33:05 %"),
+            ("{solar_elevation:.1}°", "57.6°"),
+            ("{solar_elevation}", "57.63277680057746"),
+            ("It is {day_part}", "It is civil_twilight"),
+            ("{{sunrise}} {sunset:%H:%M}", "{sunrise} Never"),
+        ];
+
+        for (template, expected) in params {
+            assert_eq!(render(template), expected, "{template}");
+        }
+    }
+
+    #[test]
+    fn test_render_never() {
+        let template = Template::parse("{sunrise:%H:%M}-{sunset:%H:%M}", kind_of)
+            .unwrap()
+            .with_never("--:--".to_string());
+        assert_eq!(template.render(&Sample), "04:43---:--");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let params = [
+            ("{sunrize}", "Unknown variable 'sunrize'."),
+            ("{sunrise", "Unclosed '{' at character 1. Use '{{' for a literal brace."),
+            ("a } b", "Unmatched '}' at character 3. Use '}}' for a literal brace."),
+            ("{sunrise:}", "Missing format after ':' in 'sunrise'."),
+            ("{sunrise:%Q}", "Invalid time format '%Q' for 'sunrise'."),
+            (
+                "{day_length:%Y}",
+                "Invalid duration format '%Y' for 'day_length'. Expected '%H', '%M', '%S' or '%s'.",
+            ),
+            (
+                "{solar_elevation:2}",
+                "Invalid number format '2' for 'solar_elevation'. Expected a precision such as '.2'.",
+            ),
+            ("{day_part:%H}", "'day_part' doesn't take a format."),
+        ];
+
+        for (template, expected) in params {
+            assert_eq!(Template::parse(template, kind_of).unwrap_err(), expected);
+        }
+    }
+
+    #[test]
+    fn test_duration_hms() {
+        assert_eq!(duration_hms(Duration::seconds(59585)), "16:33:05");
+        assert_eq!(duration_hms(Duration::hours(30)), "30:00:00");
+        assert_eq!(duration_hms(Duration::minutes(-30)), "-00:30:00");
+        assert_eq!(duration_text(Duration::seconds(59585)), "16h 33m 5s");
+    }
+}
@@ -0,0 +1,364 @@
+//! The upcoming events in chronological order, as listed by the `next` subcommand.
+//!
+//! The events calculated for a date don't necessarily fall on that date: where the time zone is far from the local
+//! solar time, sunset may be after the following midnight, or sunrise before the previous one. The events are
+//! therefore calculated for one date after another, starting from the day before now, and an event is only listed once
+//! none of the dates still to be calculated could have an event before it.
+use std::{collections::BTreeMap, fmt};
+
+use chrono::{DateTime, Duration, FixedOffset, NaiveDate};
+use clap::ValueEnum;
+use serde::ser::{Serialize, SerializeSeq, SerializeStruct};
+
+use super::{
+    calc, domain, report,
+    template::{self, Kind, Value},
+};
+
+/// An occurrence of an event, with the time remaining until it occurs.
+pub struct UpcomingEvent {
+    pub name: String,
+    pub datetime: DateTime<FixedOffset>,
+    pub countdown: Duration,
+}
+
+/// The events which occur after the date of the calculations, in chronological order.
+pub struct UpcomingEvents {
+    pub events: Vec<UpcomingEvent>,
+}
+
+impl UpcomingEvents {
+    /// Find the upcoming events, including the custom events, up to the given limit.
+    pub fn new(
+        solar_calculations: &calc::SolarCalculations,
+        custom_events: &BTreeMap<String, domain::CustomEvent>,
+        limit: domain::UpcomingLimit,
+    ) -> Self {
+        let now = solar_calculations.date;
+        let mut date = now.date().naive_local() - Duration::days(1);
+        let mut events = Vec::new();
+
+        loop {
+            let calculations = solar_calculations.for_date(date);
+            events.extend(
+                events_on(&calculations, custom_events)
+                    .into_iter()
+                    .filter(|(_, datetime)| *datetime > now),
+            );
+            events.sort_by_key(|(_, datetime)| *datetime);
+
+            // The events of a date fall no earlier than the start of the previous day, so no date still to be
+            // calculated has an event before the start of the day before this one.
+            let settled = solar_calculations
+                .time_zone
+                .from_local_datetime(&(date - Duration::days(1)).and_hms(0, 0, 0));
+            let done = match limit {
+                domain::UpcomingLimit::Count(count) => {
+                    events
+                        .iter()
+                        .filter(|(_, datetime)| *datetime < settled)
+                        .count()
+                        >= count
+                }
+                domain::UpcomingLimit::Within(period) => settled > now + period,
+            };
+            if done {
+                break;
+            }
+            date += Duration::days(1);
+        }
+
+        match limit {
+            domain::UpcomingLimit::Count(count) => events.truncate(count),
+            domain::UpcomingLimit::Within(period) => {
+                events.retain(|(_, datetime)| *datetime <= now + period)
+            }
+        }
+
+        Self {
+            events: events
+                .into_iter()
+                .map(|(name, datetime)| UpcomingEvent {
+                    name,
+                    datetime,
+                    countdown: datetime - now,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// The last solar event, i.e. any built-in event other than moonrise and moonset, to have occurred at or before the
+/// date of the calculations, and the first still to occur.
+///
+/// Only the events from the start of the previous day to the end of the next day are considered, so that just the
+/// dates either side need to be calculated.
+pub fn solar_events_around(
+    solar_calculations: &calc::SolarCalculations,
+) -> (
+    Option<(String, DateTime<FixedOffset>)>,
+    Option<UpcomingEvent>,
+) {
+    let now = solar_calculations.date;
+    let today = now.date().naive_local();
+    let start_of = |date: NaiveDate| {
+        solar_calculations
+            .time_zone
+            .from_local_datetime(&date.and_hms(0, 0, 0))
+    };
+    let (start, end) = (
+        start_of(today - Duration::days(1)),
+        start_of(today + Duration::days(2)),
+    );
+
+    // The events of a date fall no earlier than the start of the previous day and no later than the end of the next,
+    // so these dates give every event between the start and the end.
+    let mut events: Vec<_> = (-2..=2)
+        .map(|days| solar_calculations.for_date(today + Duration::days(days)))
+        .flat_map(|calculations| built_in_events_on(&calculations, false).collect::<Vec<_>>())
+        .filter(|(_, datetime)| (start..end).contains(datetime))
+        .collect();
+    events.sort_by_key(|(_, datetime)| *datetime);
+
+    let split = events.partition_point(|(_, datetime)| *datetime <= now);
+    let next = events.get(split).map(|(name, datetime)| UpcomingEvent {
+        name: name.clone(),
+        datetime: *datetime,
+        countdown: *datetime - now,
+    });
+    events.truncate(split);
+    (events.pop(), next)
+}
+
+/// The name and time of every event calculated for the date of the calculations which occurs at all. The custom
+/// events are clamped.
+fn events_on(
+    solar_calculations: &calc::SolarCalculations,
+    custom_events: &BTreeMap<String, domain::CustomEvent>,
+) -> Vec<(String, DateTime<FixedOffset>)> {
+    let custom = custom_events.iter().filter_map(|(name, event)| {
+        let datetime = report::CustomEventTime::new(solar_calculations, event)
+            .clamped
+            .0?;
+        Some((name.clone(), datetime))
+    });
+
+    built_in_events_on(solar_calculations, true)
+        .chain(custom)
+        .collect()
+}
+
+/// The name and time of every built-in event calculated for the date of the calculations which occurs at all, leaving
+/// out moonrise and moonset unless `lunar` is true.
+fn built_in_events_on(
+    solar_calculations: &calc::SolarCalculations,
+    lunar: bool,
+) -> impl Iterator<Item = (String, DateTime<FixedOffset>)> + '_ {
+    domain::RawEventName::value_variants()
+        .iter()
+        .filter(|event_name| !event_name.is_custom())
+        .filter_map(move |event_name| {
+            let name = event_name.to_possible_value()?.get_name().to_string();
+            // Safe to unwrap because only the custom events require an altitude.
+            let event = domain::EventName::new(event_name.clone(), None).unwrap();
+            let event = domain::Event::from_event_name(event);
+            if !lunar && matches!(event, domain::Event::Lunar(_)) {
+                return None;
+            }
+            let datetime = solar_calculations.event_time(event).0?;
+            Some((name, datetime))
+        })
+}
+
+/// One event per line, with the time remaining until it occurs.
+impl fmt::Display for UpcomingEvents {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self
+            .events
+            .iter()
+            .map(|event| event.name.chars().count())
+            .max()
+            .unwrap_or_default();
+
+        for event in &self.events {
+            writeln!(
+                f,
+                "{}  {:<width$}  in {}",
+                event.datetime.format("%F %T %:z"),
+                event.name,
+                template::duration_hms(event.countdown)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Serialize for UpcomingEvents {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut seq = serializer.serialize_seq(Some(self.events.len()))?;
+        for event in &self.events {
+            seq.serialize_element(event)?;
+        }
+        seq.end()
+    }
+}
+
+impl Serialize for UpcomingEvent {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut state = serializer.serialize_struct("UpcomingEvent", 3)?;
+        state.serialize_field("event", &self.name)?;
+        state.serialize_field("time", &self.datetime.to_rfc3339())?;
+        state.serialize_field("countdown", &self.countdown.num_seconds())?;
+        state.end()
+    }
+}
+
+impl template::Variables for UpcomingEvent {
+    fn value(&self, name: &str) -> Option<Value> {
+        Some(match name {
+            "event" => Value::Text(Some(self.name.clone())),
+            "time" => Value::Time(Some(self.datetime)),
+            "countdown" => Value::Duration(Some(self.countdown)),
+            _ => return None,
+        })
+    }
+}
+
+impl UpcomingEvent {
+    /// Returns the kind of the template variable with the given name, as given by `Variables::value`.
+    pub fn variable_kind(name: &str) -> Option<Kind> {
+        Some(match name {
+            "event" => Kind::Text,
+            "time" => Kind::Time,
+            "countdown" => Kind::Duration,
+            _ => return None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{Coordinates, Latitude, Longitude};
+
+    fn solar_calculations(longitude: f64, time_zone: &str, now: &str) -> calc::SolarCalculations {
+        let coordinates = Coordinates::new(
+            Latitude::new(51.4769).unwrap(),
+            Longitude::new(longitude).unwrap(),
+        );
+        calc::SolarCalculations::new(DateTime::parse_from_rfc3339(now).unwrap(), coordinates)
+            .with_time_zone(domain::TimeZone::parse(time_zone).unwrap())
+    }
+
+    fn names(upcoming: &UpcomingEvents) -> Vec<&str> {
+        upcoming
+            .events
+            .iter()
+            .map(|event| event.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_count() {
+        let calculations = solar_calculations(-0.0005, "+01:00", "2022-06-11T12:00:00+01:00");
+        let upcoming = UpcomingEvents::new(
+            &calculations,
+            &BTreeMap::new(),
+            domain::UpcomingLimit::Count(4),
+        );
+
+        assert_eq!(
+            names(&upcoming),
+            [
+                "solar_noon",
+                "moonrise",
+                "evening_golden_hour_start",
+                "sunset"
+            ]
+        );
+        assert_eq!(
+            upcoming.events[3].datetime.to_string(),
+            "2022-06-11 21:16:12 +01:00"
+        );
+        assert_eq!(upcoming.events[3].countdown, Duration::seconds(33372));
+    }
+
+    #[test]
+    fn test_within() {
+        let calculations = solar_calculations(-0.0005, "+01:00", "2022-06-11T12:00:00+01:00");
+        let upcoming = UpcomingEvents::new(
+            &calculations,
+            &BTreeMap::new(),
+            domain::UpcomingLimit::Within(Duration::hours(48)),
+        );
+
+        let datetimes: Vec<_> = upcoming.events.iter().map(|event| event.datetime).collect();
+        assert!(datetimes.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert!(datetimes.first().unwrap() > &calculations.date);
+        assert!(datetimes.last().unwrap() <= &(calculations.date + Duration::hours(48)));
+        // there is a sunset on each of the two evenings in the window
+        assert_eq!(
+            names(&upcoming)
+                .iter()
+                .filter(|name| **name == "sunset")
+                .count(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_solar_events_around() {
+        // moonrise at 17:35:17 isn't a solar event, so it is passed over
+        let calculations = solar_calculations(-0.0005, "+01:00", "2022-06-11T17:00:00+01:00");
+        let (last, next) = solar_events_around(&calculations);
+
+        let (name, datetime) = last.unwrap();
+        assert_eq!(name, "solar_noon");
+        assert_eq!(datetime.to_string(), "2022-06-11 12:59:39 +01:00");
+        let next = next.unwrap();
+        assert_eq!(next.name, "evening_golden_hour_start");
+        assert_eq!(next.countdown, next.datetime - calculations.date);
+
+        // an event which is happening now is the last event rather than the next one
+        let calculations = solar_calculations(-0.0005, "+01:00", "2022-06-11T12:59:39+01:00");
+        let (last, next) = solar_events_around(&calculations);
+        assert_eq!(last.unwrap().0, "solar_noon");
+        assert_eq!(next.unwrap().name, "evening_golden_hour_start");
+    }
+
+    #[test]
+    fn test_events_on_neighbouring_days() {
+        // in a time zone 12 hours ahead of the local solar time, the evening events calculated for each date fall on
+        // the following morning, after the events of the following date have begun
+        let calculations = solar_calculations(-0.0005, "+12:00", "2022-06-12T07:00:00+12:00");
+        let upcoming = UpcomingEvents::new(
+            &calculations,
+            &BTreeMap::new(),
+            domain::UpcomingLimit::Count(30),
+        );
+
+        let sunsets: Vec<_> = upcoming
+            .events
+            .iter()
+            .filter(|event| event.name == "sunset")
+            .map(|event| event.datetime.to_string())
+            .collect();
+        assert_eq!(
+            sunsets,
+            ["2022-06-12 08:15:52 +12:00", "2022-06-13 08:16:34 +12:00"]
+        );
+        assert_eq!(
+            names(&upcoming)[..2],
+            ["evening_golden_hour_start", "sunset"]
+        );
+
+        let datetimes: Vec<_> = upcoming.events.iter().map(|event| event.datetime).collect();
+        assert!(datetimes.windows(2).all(|pair| pair[0] <= pair[1]));
+    }
+}
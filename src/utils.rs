@@ -1,26 +1,14 @@
 use std::result;
 
-use crate::sleep;
-use chrono::{DateTime, FixedOffset, Local, TimeZone};
+use chrono::{DateTime, FixedOffset, Utc};
 
+use super::clock::Clock;
 use super::errors::{HeliocronError, RuntimeErrorKind};
 
 type Result<T> = result::Result<T, HeliocronError>;
 
-async fn sleep(time: DateTime<FixedOffset>) -> Result<()> {
-    if cfg!(feature = "integration-test") {
-        println!("Fake sleep until {time}.");
-    } else {
-        sleep::sleep_until(time).await?;
-    }
-    Ok(())
-}
-
-pub(crate) async fn wait(wait_until: DateTime<FixedOffset>) -> Result<()> {
-    let local_time = Local::now();
-    let local_time = local_time.with_timezone(&FixedOffset::from_offset(local_time.offset()));
-
-    let duration_to_wait = wait_until - local_time;
+pub(crate) async fn wait(wait_until: DateTime<FixedOffset>, clock: &dyn Clock) -> Result<()> {
+    let duration_to_wait = wait_until.with_timezone(&Utc) - clock.now();
 
     // Chrono supports negative durations, but std::time::does not. An error here, therefore, tells us that
     // the event occurred in the past.
@@ -36,21 +24,52 @@ pub(crate) async fn wait(wait_until: DateTime<FixedOffset>) -> Result<()> {
         duration_to_wait.as_secs(),
         wait_until
     );
-    sleep(wait_until).await?;
+    clock.sleep_until(wait_until.with_timezone(&Utc)).await?;
     Ok(())
 }
 
+/// Join the command and its arguments into a single line, quoting with `quote` any argument which is empty or contains
+/// characters other than ASCII alphanumerics and those in `safe`. The escapes are left to the caller, as they depend
+/// on what will run the command.
+pub(crate) fn join_command(
+    command: &[String],
+    safe: &str,
+    quote: impl Fn(&str) -> String,
+) -> String {
+    command
+        .iter()
+        .map(|arg| {
+            let is_safe = |c: char| c.is_ascii_alphanumeric() || safe.contains(c);
+            if !arg.is_empty() && arg.chars().all(is_safe) {
+                arg.to_string()
+            } else {
+                quote(arg)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
 #[cfg(test)]
 mod tests {
-    #[cfg(feature = "integration-test")]
+    use chrono::{FixedOffset, TimeZone};
+
+    use super::*;
+    use crate::clock::SimulatedClock;
+
     #[tokio::test]
     async fn test_wait() {
-        use chrono::{FixedOffset, TimeZone};
-
-        use super::*;
+        let clock = SimulatedClock::new(FixedOffset::west(0).timestamp(0, 0));
 
         // Some time improbably far in the future.
         let wait_until = FixedOffset::west(0).timestamp(9999999999, 0);
-        wait(wait_until).await.unwrap();
+        wait(wait_until, &clock).await.unwrap();
+        assert_eq!(clock.now(), wait_until);
+
+        // Having waited, the time is now in the past.
+        assert!(matches!(
+            wait(wait_until - chrono::Duration::seconds(1), &clock).await,
+            Err(HeliocronError::Runtime(RuntimeErrorKind::PastEvent(_)))
+        ));
     }
 }
@@ -0,0 +1,23 @@
+//! Helpers shared by the integration tests.
+// Each integration test is its own crate, which only uses some of these.
+#![allow(dead_code)]
+
+use std::process::Command;
+
+use assert_cmd::prelude::*;
+
+/// Create a command which runs heliocron with a simulated clock, so that waits return immediately. The simulated
+/// local time zone is +01:00.
+pub fn heliocron() -> Command {
+    let mut cmd = Command::cargo_bin("heliocron").unwrap();
+    cmd.args(["--now", "2022-06-11T12:00:00+01:00"]);
+    cmd
+}
+
+/// Write a configuration file into its own config directory, which heliocron is pointed at via `XDG_CONFIG_HOME`.
+pub fn write_config(name: &str, contents: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("heliocron-{}-{name}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("heliocron.toml"), contents).unwrap();
+    dir
+}
@@ -0,0 +1,108 @@
+use std::process::Command;
+
+use assert_cmd::prelude::*;
+
+fn check(args: &[&str]) -> assert_cmd::assert::Assert {
+    let mut cmd = Command::cargo_bin("heliocron").unwrap();
+    cmd.args([
+        "--latitude",
+        "51.4769",
+        "--longitude",
+        "-0.0005",
+        "--time-zone",
+        "+00:00",
+    ])
+    .args(args)
+    .assert()
+}
+
+#[test]
+fn test_check_day_parts() {
+    // sunset at this location is at 20:16:13 and civil dusk is at 21:03:19, whilst the Sun gets no lower than 15
+    // degrees below the horizon overnight
+    let params = [
+        ("2022-06-11T12:00:00", "day", true),
+        ("2022-06-11T12:00:00", "night", false),
+        ("2022-06-11T12:00:00", "twilight", false),
+        ("2022-06-11T20:00:00", "golden_hour", true),
+        ("2022-06-11T21:00:00", "civil_twilight", true),
+        ("2022-06-11T21:00:00", "twilight", true),
+        ("2022-06-11T21:00:00", "blue_hour", true),
+        ("2022-06-11T21:00:00", "day", false),
+        ("2022-06-12T01:00:00", "nautical_twilight", false),
+        ("2022-06-12T01:00:00", "astronomical_twilight", true),
+    ];
+
+    for (at, condition, expected) in params {
+        let assert = check(&["check", condition, "--at", at])
+            .stdout("")
+            .stderr("");
+        match expected {
+            true => assert.success(),
+            false => assert.code(1),
+        };
+    }
+}
+
+#[test]
+fn test_check_between() {
+    // the window from sunset until sunrise spans midnight
+    let night = ["is", "between", "--from", "sunset", "--to", "sunrise"];
+    check(&[&night[..], &["--at", "2022-06-12T01:00:00"]].concat())
+        .success()
+        .stdout("");
+    check(&[&night[..], &["--at", "2022-06-11T12:00:00"]].concat())
+        .code(1)
+        .stdout("");
+
+    let evening = [
+        "is",
+        "between",
+        "--from",
+        "sunset",
+        "--from-offset",
+        "-01:00",
+        "--to",
+        "sunset",
+        "--to-offset",
+        "01:00",
+    ];
+    check(&[&evening[..], &["--at", "2022-06-11T19:30"]].concat()).success();
+    check(&[&evening[..], &["--at", "2022-06-11T21:30"]].concat()).code(1);
+}
+
+#[test]
+fn test_check_at_utc_offset() {
+    // 13:00 at +01:00 is midday at +00:00
+    check(&["is", "--at", "2022-06-11T13:00:00+01:00", "day"]).success();
+    check(&["is", "--at", "2022-06-11 02:00+01:00", "night"]).code(1);
+
+    check(&["is", "day", "--at", "tomorrow"])
+        .code(2)
+        .stderr(predicates::str::contains("Invalid date and time"));
+}
+
+#[test]
+fn test_check_between_requires_altitude() {
+    check(&["is", "between", "--from", "custom_am", "--to", "sunset"])
+        .code(2)
+        .stderr(predicates::str::contains("--from-altitude"));
+}
+
+#[test]
+fn test_check_errors_are_distinct_from_false() {
+    // exit status 1 means the condition doesn't hold, so errors must use a different status
+    check(&["--date", "2022-06-11", "is", "day"])
+        .code(2)
+        .stderr(predicates::str::contains("use --at instead"));
+
+    let mut cmd = Command::cargo_bin("heliocron").unwrap();
+    cmd.env(
+        "XDG_CONFIG_HOME",
+        std::env::temp_dir().join("heliocron-no-config"),
+    )
+    .args(["--location", "nowhere", "is", "day"])
+    .assert()
+    .code(2)
+    .stderr(predicates::str::contains("Invalid location"));
+}
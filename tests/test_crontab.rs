@@ -0,0 +1,82 @@
+use assert_cmd::prelude::*;
+
+mod common;
+use common::heliocron;
+
+#[test]
+fn test_crontab_print() {
+    let mut cmd = heliocron();
+    cmd.args(["crontab", "--event", "sunset", "--offset", "-00:30"])
+        .args(["--days", "3", "--name", "lights", "--", "lights", "on"])
+        .assert()
+        .success()
+        .stdout(
+            "# BEGIN heliocron lights\n\
+            46 20 11 6 * lights on\n\
+            47 20 12 6 * lights on\n\
+            48 20 13 6 * lights on\n\
+            # END heliocron lights\n",
+        );
+}
+
+#[test]
+fn test_crontab_local_time_zone() {
+    // cron runs in the local time zone, so the entries are the same whatever the time zone of the calculations
+    let mut cmd = heliocron();
+    cmd.args(["--time-zone", "+00:00", "crontab", "--event", "sunset"])
+        .args(["--offset", "-00:30", "--days", "1", "--", "lights", "on"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("\n46 20 11 6 * lights on\n"));
+}
+
+#[test]
+fn test_crontab_event_does_not_occur() {
+    // the Sun doesn't set at 70 degrees north in June
+    let mut cmd = heliocron();
+    cmd.args(["-l", "70", "-o", "0", "crontab", "--event", "sunset"])
+        .args(["--days", "1", "--", "lights", "on"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(
+            "# 2022-06-11: the event does not occur on this day\n",
+        ));
+}
+
+#[test]
+fn test_crontab_install() {
+    let path = std::env::temp_dir().join(format!("heliocron-{}-crontab", std::process::id()));
+    std::fs::write(&path, "MAILTO=me\n@reboot backup\n").unwrap();
+
+    // installing the same block twice leaves the crontab as it was after the first time
+    for _ in 0..2 {
+        let mut cmd = heliocron();
+        cmd.args(["crontab", "--event", "sunset", "--days", "2", "--install"])
+            .arg("--crontab-file")
+            .arg(&path)
+            .args(["--", "lights", "on"])
+            .assert()
+            .success()
+            .stdout("Installed 2 days of entries in the 'default' block.\n");
+
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "MAILTO=me\n@reboot backup\n\
+            # BEGIN heliocron default\n\
+            16 21 11 6 * lights on\n\
+            17 21 12 6 * lights on\n\
+            # END heliocron default\n"
+        );
+    }
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_crontab_requires_command() {
+    let mut cmd = heliocron();
+    cmd.args(["crontab", "--event", "sunset"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("<COMMAND>"));
+}
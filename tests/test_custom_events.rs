@@ -0,0 +1,150 @@
+use std::process::Command;
+
+use assert_cmd::prelude::*;
+
+mod common;
+use common::write_config;
+
+const CONFIG: &str = r#"
+[events.porch_lights]
+altitude = 3.5
+direction = "descending"
+offset = "-00:10"
+not_before = "17:00"
+not_after = "21:00"
+
+[events.dawn_chorus]
+altitude = 9
+direction = "ascending"
+"#;
+
+#[test]
+fn test_custom_events_report() {
+    let config = write_config("report", CONFIG);
+
+    let mut cmd = Command::cargo_bin("heliocron").unwrap();
+    cmd.env("XDG_CONFIG_HOME", &config)
+        .args(["-d", "2022-06-11", "-t", "+01:00", "report"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("CUSTOM EVENTS"))
+        .stdout(predicates::str::contains(
+            "dawn_chorus:              2022-06-11 03:23:44 +01:00",
+        ))
+        .stdout(predicates::str::contains(
+            "porch_lights:             2022-06-11 21:00:00 +01:00 (clamped from 2022-06-11 21:29:31 +01:00)",
+        ));
+
+    let mut cmd = Command::cargo_bin("heliocron").unwrap();
+    cmd.env("XDG_CONFIG_HOME", &config)
+        .args(["-d", "2022-06-11", "-t", "+01:00", "report", "--json"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(
+            r#""custom_events":{"dawn_chorus":{"raw":"2022-06-11T03:23:44+01:00","clamped":"2022-06-11T03:23:44+01:00"},"porch_lights":{"raw":"2022-06-11T21:29:31+01:00","clamped":"2022-06-11T21:00:00+01:00"}}"#,
+        ));
+
+    std::fs::remove_dir_all(config).unwrap();
+}
+
+#[test]
+fn test_custom_events_wait() {
+    let config = write_config("wait", CONFIG);
+
+    // the offset given on the command line is added to the offset of the event, before the event is clamped
+    let params = [("00:00", "21:00:00"), ("-01:00", "20:29:31")];
+
+    for (offset, expected) in params {
+        let mut cmd = Command::cargo_bin("heliocron").unwrap();
+        cmd.env("XDG_CONFIG_HOME", &config)
+            .args(["--now", "2022-06-11T12:00:00+01:00", "wait"])
+            .args(["--event", "porch_lights", "--offset", offset])
+            .assert()
+            .success()
+            .stdout(predicates::str::contains(format!(
+                "until 2022-06-11 {expected} +01:00"
+            )));
+    }
+
+    // the clamp given on the command line takes precedence
+    let mut cmd = Command::cargo_bin("heliocron").unwrap();
+    cmd.env("XDG_CONFIG_HOME", &config)
+        .args(["--now", "2022-06-11T12:00:00+01:00", "wait"])
+        .args(["--event", "porch_lights", "--not-after", "21:15"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(
+            "until 2022-06-11 21:15:00 +01:00",
+        ));
+
+    let mut cmd = Command::cargo_bin("heliocron").unwrap();
+    cmd.env("XDG_CONFIG_HOME", &config)
+        .args(["--now", "2022-06-11T12:00:00+01:00", "wait"])
+        .args(["--event", "porch_lights", "--not-before", "21:15"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "Invalid clamp - The earliest time must not be later than the latest time",
+        ));
+
+    std::fs::remove_dir_all(config).unwrap();
+}
+
+#[test]
+fn test_custom_events_unknown() {
+    let config = write_config("unknown", CONFIG);
+
+    let mut cmd = Command::cargo_bin("heliocron").unwrap();
+    cmd.env("XDG_CONFIG_HOME", &config)
+        .args(["wait", "--event", "porch_light"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "Invalid custom event - 'porch_light' is neither a built-in event nor defined",
+        ))
+        .stderr(predicates::str::contains(
+            "moonset, dawn_chorus, porch_lights",
+        ));
+
+    std::fs::remove_dir_all(config).unwrap();
+}
+
+#[test]
+fn test_custom_events_invalid() {
+    let params = [
+        (
+            "[events.sunset]\naltitude = 3.5\ndirection = \"descending\"\n",
+            "Invalid custom event 'sunset' - the name is already used by a built-in event",
+        ),
+        (
+            "[events.lights]\naltitude = 93.5\ndirection = \"descending\"\n",
+            "Invalid custom event 'lights' - Expected a number between -90.0 and 90.0",
+        ),
+        (
+            "[events.lights]\naltitude = 3.5\ndirection = \"down\"\n",
+            "unknown variant `down`, expected `ascending` or `descending`",
+        ),
+        (
+            "[events.lights]\naltitude = 3.5\ndirection = \"descending\"\nnot_before = \"21:30\"\nnot_after = \"17:00\"\n",
+            "Invalid custom event 'lights' - The earliest time must not be later than the latest time",
+        ),
+        (
+            "[events.lights]\naltitude = 3.5\ndirection = \"descending\"\nnot_after = \"9pm\"\n",
+            "Invalid custom event 'lights' - Expected a time of day in the format HH:MM or HH:MM:SS. Found '9pm'",
+        ),
+    ];
+
+    for (i, (contents, expected)) in params.into_iter().enumerate() {
+        let config = write_config(&format!("invalid-{i}"), contents);
+
+        let mut cmd = Command::cargo_bin("heliocron").unwrap();
+        cmd.env("XDG_CONFIG_HOME", &config)
+            .args(["wait", "--event", "lights"])
+            .assert()
+            .failure()
+            .stderr(predicates::str::contains("Invalid configuration file"))
+            .stderr(predicates::str::contains(expected));
+
+        std::fs::remove_dir_all(config).unwrap();
+    }
+}
@@ -0,0 +1,147 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::{Child, Command},
+    thread,
+    time::Duration,
+};
+
+use assert_cmd::prelude::*;
+
+mod common;
+
+fn write_heliotab(name: &str, contents: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("heliocron-{}-{name}", std::process::id()));
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+/// A daemon running with the simulated clock, which stops at midday on the day after it starts. Both stdout and stderr
+/// go to the log. The daemon is killed when this is dropped, even if the test fails.
+struct Daemon {
+    child: Child,
+    log: PathBuf,
+}
+
+impl Daemon {
+    fn spawn(heliotab: &Path) -> Self {
+        let log = heliotab.with_extension("log");
+        let file = fs::File::create(&log).unwrap();
+        let child = common::heliocron()
+            .args([
+                "--until",
+                "2022-06-12T12:00:00+01:00",
+                "daemon",
+                "--heliotab",
+            ])
+            .arg(heliotab)
+            .stdout(file.try_clone().unwrap())
+            .stderr(file)
+            .spawn()
+            .unwrap();
+        Self { child, log }
+    }
+
+    /// Wait a few seconds at most for the log to contain `expected`, returning the whole log.
+    fn wait_for_log(&self, expected: &str) -> String {
+        for _ in 0..100 {
+            let log = fs::read_to_string(&self.log).unwrap();
+            if log.contains(expected) {
+                return log;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        panic!(
+            "'{expected}' not found in the log:\n{}",
+            fs::read_to_string(&self.log).unwrap()
+        );
+    }
+}
+
+impl Drop for Daemon {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = fs::remove_file(&self.log);
+    }
+}
+
+#[test]
+fn test_daemon_invalid_heliotab() {
+    // assert that the daemon refuses to start with an invalid heliotab, and points at the offending line
+    let heliotab = write_heliotab(
+        "invalid",
+        "# lights\nsunset -00:30 * lights on\nsunrise 00:30 someday lights off\n",
+    );
+
+    let mut cmd = Command::cargo_bin("heliocron").unwrap();
+    cmd.args(["daemon", "--heliotab"])
+        .arg(&heliotab)
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("Invalid heliotab - line 3"))
+        .stderr(predicates::str::contains("Found 'someday'"));
+
+    std::fs::remove_file(heliotab).unwrap();
+}
+
+#[test]
+fn test_daemon_missing_heliotab() {
+    let mut cmd = Command::cargo_bin("heliocron").unwrap();
+    cmd.args(["daemon", "--heliotab", "/does/not/exist"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "Invalid heliotab - couldn't read '/does/not/exist'",
+        ));
+}
+
+#[test]
+fn test_daemon_runs_due_entries() {
+    // assert that an entry is run when it falls due, and that both the run and its outcome are logged
+    let heliotab = write_heliotab("due", "sunset 00:00 * echo lights on\n");
+    let daemon = Daemon::spawn(&heliotab);
+
+    let log = daemon.wait_for_log("completed successfully");
+    assert!(log.starts_with(&format!(
+        "[2022-06-11 12:00:00 +01:00] Loaded 1 entries from {}\n",
+        heliotab.display()
+    )));
+    assert!(log.contains(
+        "[2022-06-11 21:16:12 +01:00] line 1 (sunset): running 'echo lights on'\nlights on\n\
+        [2022-06-11 21:16:12 +01:00] line 1 (sunset): 'echo lights on' completed successfully\n"
+    ));
+
+    drop(daemon);
+    fs::remove_file(heliotab).unwrap();
+}
+
+#[test]
+fn test_daemon_reloads_on_hangup() {
+    // assert that the heliotab is reloaded on SIGHUP, and that the entries added to it are then run
+    let heliotab = write_heliotab("reload", "sunset 00:00 * echo lights on\n");
+    let daemon = Daemon::spawn(&heliotab);
+    daemon.wait_for_log("'echo lights on' completed successfully");
+
+    fs::write(
+        &heliotab,
+        "sunset 00:00 * echo lights on\nsunrise 00:00 * echo lights off\n",
+    )
+    .unwrap();
+    Command::new("kill")
+        .args(["-HUP", &daemon.child.id().to_string()])
+        .assert()
+        .success();
+
+    let log = daemon.wait_for_log("'echo lights off' completed successfully");
+    assert!(log.contains(&format!(
+        "[2022-06-11 21:16:12 +01:00] Reloaded 2 entries from {}\n",
+        heliotab.display()
+    )));
+    assert!(
+        log.contains("[2022-06-12 04:42:51 +01:00] line 2 (sunrise): running 'echo lights off'\n")
+    );
+
+    drop(daemon);
+    fs::remove_file(heliotab).unwrap();
+}
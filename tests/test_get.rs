@@ -0,0 +1,77 @@
+use assert_cmd::prelude::*;
+
+mod common;
+use common::heliocron;
+
+#[test]
+fn test_get_event() {
+    let params = [
+        (vec!["sunset"], "2022-06-11T21:16:12+01:00\n"),
+        (
+            vec!["sunset", "--offset", "-00:30"],
+            "2022-06-11T20:46:12+01:00\n",
+        ),
+        (vec!["sunset", "--unix"], "1654978572\n"),
+        (
+            vec!["custom_am", "--altitude", "3"],
+            "2022-06-11T04:24:18+01:00\n",
+        ),
+    ];
+
+    for (args, expected) in params {
+        let mut cmd = heliocron();
+        cmd.arg("get")
+            .args(args)
+            .assert()
+            .success()
+            .stdout(expected);
+    }
+
+    // the chosen date is used, rather than today
+    let mut cmd = heliocron();
+    cmd.args(["--date", "2022-06-12", "get", "sunset"])
+        .assert()
+        .success()
+        .stdout("2022-06-12T21:16:52+01:00\n");
+}
+
+#[test]
+fn test_get_day_length() {
+    let mut cmd = heliocron();
+    cmd.args(["get", "day_length"])
+        .assert()
+        .success()
+        .stdout("16:33:05\n");
+
+    let mut cmd = heliocron();
+    cmd.args(["get", "day_length", "--seconds"])
+        .assert()
+        .success()
+        .stdout("59585\n");
+
+    // the day length isn't an event, so can't be offset
+    for args in [["--offset", "01:00"], ["--altitude", "5.0"]] {
+        let mut cmd = heliocron();
+        cmd.args(["get", "day_length"])
+            .args(args)
+            .assert()
+            .failure()
+            .stdout("")
+            .stderr(predicates::str::contains(
+                "--offset and --altitude only apply to events",
+            ));
+    }
+}
+
+#[test]
+fn test_get_event_does_not_occur() {
+    // the Sun doesn't set at 70 degrees north in June
+    let mut cmd = heliocron();
+    cmd.args(["-l", "70", "-o", "0", "get", "sunset"])
+        .assert()
+        .code(4)
+        .stdout("")
+        .stderr(predicates::str::contains(
+            "The chosen event does not occur on this day.",
+        ));
+}
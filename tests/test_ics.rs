@@ -0,0 +1,76 @@
+use assert_cmd::prelude::*;
+
+mod common;
+use common::heliocron;
+
+#[test]
+fn test_ics() {
+    let mut cmd = heliocron();
+    let output = cmd
+        .args(["ics", "--from", "2022-06-11", "--to", "2022-06-13"])
+        .args(["--events", "sunrise,civil_dusk"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let calendar = String::from_utf8(output.stdout).unwrap();
+    assert!(calendar.starts_with("BEGIN:VCALENDAR\r\n"));
+    assert!(calendar.ends_with("END:VCALENDAR\r\n"));
+    assert_eq!(calendar.matches("BEGIN:VEVENT\r\n").count(), 6);
+    assert!(calendar.contains(
+        "UID:20220611-sunrise-51.4769--0.0005@heliocron\r\n\
+        DTSTAMP:20220611T110000Z\r\n\
+        DTSTART:20220611T034307Z\r\n\
+        SUMMARY:Sunrise\r\n"
+    ));
+    assert!(calendar.contains("SUMMARY:Civil dusk\r\n"));
+    assert!(calendar.contains("UID:20220613-civil_dusk-51.4769--0.0005@heliocron\r\n"));
+}
+
+#[test]
+fn test_ics_polar() {
+    // the Sun doesn't set at 70 degrees north in June
+    let mut cmd = heliocron();
+    let output = cmd
+        .args(["-l", "70", "-o", "0", "ics", "--from", "2022-06-11"])
+        .args(["--to", "2022-06-11", "--polar"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let calendar = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(calendar.matches("BEGIN:VEVENT\r\n").count(), 1);
+    assert!(calendar.contains(
+        "UID:20220611-polar-70-0@heliocron\r\n\
+        DTSTAMP:20220611T110000Z\r\n\
+        DTSTART;VALUE=DATE:20220611\r\n\
+        DTEND;VALUE=DATE:20220612\r\n\
+        SUMMARY:Polar day\r\n"
+    ));
+}
+
+#[test]
+fn test_ics_invalid_range() {
+    let mut cmd = heliocron();
+    cmd.args(["ics", "--from", "2022-06-11", "--to", "2022-06-10"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "The first date must not be later than the last date.",
+        ));
+
+    let mut cmd = heliocron();
+    cmd.args(["ics", "--from", "2022-06-11", "--to", "2032-06-20"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "The range must not be longer than 3660 days.",
+        ));
+
+    let mut cmd = heliocron();
+    cmd.args(["ics", "--from", "2022-06-11", "--to", "2022-06-12"])
+        .args(["--events", "sunrise,custom_am"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("Found 'custom_am'"));
+}
@@ -0,0 +1,124 @@
+use std::process::Command;
+
+use assert_cmd::prelude::*;
+
+mod common;
+use common::write_config;
+
+const CONFIG: &str = r#"
+default = "home"
+
+[locations.home]
+latitude = 51.4769
+longitude = -0.0005
+time_zone = "Europe/London"
+
+[locations.summit]
+latitude = 56.7969
+longitude = -5.0036
+time_zone = "+00:00"
+elevation = 1345
+"#;
+
+#[test]
+fn test_locations_list() {
+    let config = write_config("list", CONFIG);
+
+    let mut cmd = Command::cargo_bin("heliocron").unwrap();
+    cmd.env("XDG_CONFIG_HOME", &config)
+        .arg("locations")
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(
+            "home (default): latitude 51.4769, longitude -0.0005, time zone Europe/London, elevation 0m",
+        ))
+        .stdout(predicates::str::contains(
+            "summit: latitude 56.7969, longitude -5.0036, time zone +00:00, elevation 1345m",
+        ));
+
+    std::fs::remove_dir_all(config).unwrap();
+}
+
+#[test]
+fn test_locations_default_and_selected() {
+    let config = write_config("select", CONFIG);
+
+    // the default location is used when no coordinates are given...
+    let mut cmd = Command::cargo_bin("heliocron").unwrap();
+    cmd.env("XDG_CONFIG_HOME", &config)
+        .args(["-d", "2022-06-11", "report"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("Latitude: 51.4769"))
+        .stdout(predicates::str::contains("2022-06-11 12:00:00 +01:00"));
+
+    // ...unless another one is chosen
+    let mut cmd = Command::cargo_bin("heliocron").unwrap();
+    cmd.env("XDG_CONFIG_HOME", &config)
+        .args(["-d", "2022-06-11", "--location", "summit", "report"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("Latitude: 56.7969"))
+        .stdout(predicates::str::contains("2022-06-11 12:00:00 +00:00"));
+
+    // coordinates on the command line take precedence over the default location
+    let mut cmd = Command::cargo_bin("heliocron").unwrap();
+    cmd.env("XDG_CONFIG_HOME", &config)
+        .args(["-d", "2022-06-11", "-l", "10", "-o", "10", "report"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("Latitude: 10"));
+
+    std::fs::remove_dir_all(config).unwrap();
+}
+
+#[test]
+fn test_locations_unknown() {
+    let config = write_config("unknown", CONFIG);
+
+    let mut cmd = Command::cargo_bin("heliocron").unwrap();
+    cmd.env("XDG_CONFIG_HOME", &config)
+        .args(["--location", "barn", "report"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "Invalid location - 'barn' is not defined in ~/.config/heliocron.toml. Expected one of: home, summit",
+        ));
+
+    std::fs::remove_dir_all(config).unwrap();
+}
+
+#[test]
+fn test_locations_invalid() {
+    let params = [
+        (
+            "[locations.home]\nlatitude = 91.0\nlongitude = 0.0\n",
+            "Invalid location 'home' - Latitude must be between -90.0 and 90.0",
+        ),
+        (
+            "[locations.home]\nlatitude = 0.0\nlongitude = 0.0\nelevation = -5\n",
+            "Invalid location 'home' - Elevation must be between 0.0 and 10000.0 metres",
+        ),
+        (
+            "[locations.home]\nlatitude = 0.0\nlongitude = 0.0\naltitude = 5\n",
+            "unknown field `altitude`",
+        ),
+        (
+            "default = \"barn\"\n[locations.home]\nlatitude = 0.0\nlongitude = 0.0\n",
+            "The default location 'barn' is not defined in [locations]",
+        ),
+    ];
+
+    for (i, (contents, expected)) in params.into_iter().enumerate() {
+        let config = write_config(&format!("invalid-{i}"), contents);
+
+        let mut cmd = Command::cargo_bin("heliocron").unwrap();
+        cmd.env("XDG_CONFIG_HOME", &config)
+            .arg("locations")
+            .assert()
+            .failure()
+            .stderr(predicates::str::contains(expected));
+
+        std::fs::remove_dir_all(config).unwrap();
+    }
+}
@@ -0,0 +1,81 @@
+use std::process::Command;
+
+use assert_cmd::prelude::*;
+
+mod common;
+
+/// Create a command which runs heliocron for Greenwich, with a simulated clock.
+fn heliocron() -> Command {
+    let mut cmd = common::heliocron();
+    cmd.args(["--latitude", "51.4769", "--longitude", "-0.0005"]);
+    cmd
+}
+
+#[test]
+fn test_next_count() {
+    let mut cmd = heliocron();
+    cmd.args(["next", "--count", "4"])
+        .assert()
+        .success()
+        .stdout(
+            "2022-06-11 12:59:39 +01:00  solar_noon                 in 00:59:39\n\
+            2022-06-11 17:35:17 +01:00  moonrise                   in 05:35:17\n\
+            2022-06-11 20:22:25 +01:00  evening_golden_hour_start  in 08:22:25\n\
+            2022-06-11 21:16:12 +01:00  sunset                     in 09:16:12\n",
+        );
+}
+
+#[test]
+fn test_next_within() {
+    // the events continue into the following day, past solar midnight
+    let mut cmd = heliocron();
+    let output = cmd
+        .args(["next", "--within", "20h", "--format", "ndjson"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let events: Vec<serde_json::Value> = String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(
+        events.first().unwrap(),
+        &serde_json::json!({"event": "solar_noon", "time": "2022-06-11T12:59:39+01:00", "countdown": 3579})
+    );
+    assert!(events
+        .iter()
+        .any(|event| event["time"] == "2022-06-12T04:42:51+01:00" && event["event"] == "sunrise"));
+    assert!(events
+        .iter()
+        .all(|event| (1..=20 * 3600).contains(&event["countdown"].as_i64().unwrap())));
+}
+
+#[test]
+fn test_next_template() {
+    let mut cmd = heliocron();
+    cmd.args([
+        "next",
+        "-n",
+        "2",
+        "--template",
+        "{event} in {countdown:%-Hh%Mm}",
+    ])
+    .assert()
+    .success()
+    .stdout("solar_noon in 0h59m\nmoonrise in 5h35m\n");
+}
+
+#[test]
+fn test_next_invalid_period() {
+    for period in ["0h", "48", "2w", "400d"] {
+        let mut cmd = heliocron();
+        cmd.args(["next", "--within", period]).assert().code(2);
+    }
+
+    let mut cmd = heliocron();
+    cmd.args(["next", "--count", "3", "--within", "1d"])
+        .assert()
+        .code(2);
+}
@@ -0,0 +1,153 @@
+//! Golden-file tests of every output format. Run with `UPDATE_GOLDEN=1` to regenerate the files in tests/golden after
+//! an intended change to the output.
+use std::{path::PathBuf, process::Command};
+
+use assert_cmd::prelude::*;
+use pretty_assertions::assert_eq;
+
+mod common;
+
+const FORMATS: [(&str, &str); 7] = [
+    ("text", "txt"),
+    ("json", "json"),
+    ("ndjson", "ndjson"),
+    ("csv", "csv"),
+    ("yaml", "yaml"),
+    ("toml", "toml"),
+    ("markdown", "md"),
+];
+
+/// Create a command which runs heliocron for a fixed location and a simulated clock.
+fn heliocron() -> Command {
+    let mut cmd = common::heliocron();
+    cmd.args(["--latitude", "51.4", "--longitude", "-5.467"]);
+    cmd
+}
+
+fn assert_golden(file_name: &str, output: &str) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+        .join(file_name);
+
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::write(&path, output).unwrap();
+    }
+    let expected = std::fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("couldn't read {}: {e}", path.display()));
+    assert_eq!(output, expected, "{file_name}");
+}
+
+fn assert_formats(name: &str, args: &[&str]) {
+    for (format, extension) in FORMATS {
+        let output = heliocron()
+            .args(args)
+            .args(["--format", format])
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{format}");
+
+        assert_golden(
+            &format!("{name}.{extension}"),
+            &String::from_utf8(output.stdout).unwrap(),
+        );
+    }
+}
+
+#[test]
+fn test_report_formats() {
+    assert_formats("report", &["report"]);
+}
+
+#[test]
+fn test_range_report_formats() {
+    assert_formats("range_report", &["report", "--days", "2"]);
+}
+
+#[test]
+fn test_poll_formats() {
+    assert_formats("poll", &["poll"]);
+}
+
+#[test]
+fn test_json_flag() {
+    // --json is the same as --format json
+    let json = heliocron().args(["report", "--json"]).output().unwrap();
+    let format = heliocron()
+        .args(["report", "--format", "json"])
+        .output()
+        .unwrap();
+    assert_eq!(json.stdout, format.stdout);
+
+    heliocron()
+        .args(["report", "--json", "--format", "csv"])
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn test_template() {
+    heliocron()
+        .args([
+            "report",
+            "--template",
+            "{sunrise:%H:%M} {sunset:%s} {day_length:%Hh%Mm} {dawn.astronomical:%H:%M}",
+        ])
+        .assert()
+        .success()
+        .stdout("05:05 1654979858 16h32m Never\n");
+
+    // the template is applied to each day of a range...
+    heliocron()
+        .args([
+            "report",
+            "--days",
+            "2",
+            "--template",
+            "{date:%F} {dusk.astronomical}",
+        ])
+        .args(["--never", "-"])
+        .assert()
+        .success()
+        .stdout("2022-06-11 -\n2022-06-12 -\n");
+
+    // ...and to the poll report
+    heliocron()
+        .args(["poll", "--template", "{day_part} at {solar_elevation:.1}°"])
+        .assert()
+        .success()
+        .stdout("day at 57.6°\n");
+}
+
+#[test]
+fn test_template_errors() {
+    let params = [
+        (
+            vec!["report", "--template", "{sunrize}"],
+            "Invalid template - Unknown variable 'sunrize'.",
+        ),
+        (
+            vec!["report", "--template", "{sunrise:%Q}"],
+            "Invalid template - Invalid time format '%Q' for 'sunrise'.",
+        ),
+        // the variables of the report aren't those of the poll report
+        (
+            vec!["poll", "--template", "{sunrise}"],
+            "Invalid template - Unknown variable 'sunrise'.",
+        ),
+    ];
+
+    for (args, expected) in params {
+        heliocron()
+            .args(args)
+            .assert()
+            .code(1)
+            .stdout("")
+            .stderr(predicates::str::contains(expected));
+    }
+
+    heliocron()
+        .args(["report", "--template", "{sunrise}", "--format", "csv"])
+        .assert()
+        .code(2);
+}
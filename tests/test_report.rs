@@ -139,10 +139,15 @@ fn test_report_json_output() {
         "date": "2022-06-11T12:00:00+01:00",
         "day_length": 59534,
         "solar_noon": "2022-06-11T13:21:31+01:00",
+        "solar_midnight": "2022-06-12T01:21:31+01:00",
+        "min_solar_elevation": -15.44326975437755,
         "sunrise": "2022-06-11T05:05:24+01:00",
         "sunset": "2022-06-11T21:37:38+01:00",
         "dawn": {"civil": "2022-06-11T04:18:29+01:00", "nautical": "2022-06-11T03:06:40+01:00", "astronomical": null},
         "dusk": {"civil": "2022-06-11T22:24:34+01:00", "nautical": "2022-06-11T23:36:23+01:00", "astronomical": null},
+        "golden_hour": {"morning": {"start": "2022-06-11T04:37:35+01:00", "end": "2022-06-11T05:59:03+01:00"}, "evening": {"start": "2022-06-11T20:44:00+01:00", "end": "2022-06-11T22:05:27+01:00"}},
+        "blue_hour": {"morning": {"start": "2022-06-11T04:18:29+01:00", "end": "2022-06-11T04:37:35+01:00"}, "evening": {"start": "2022-06-11T22:05:27+01:00", "end": "2022-06-11T22:24:34+01:00"}},
+        "moon": {"moonrise": "2022-06-11T17:58:16+01:00", "moonset": "2022-06-11T03:13:23+01:00", "transit": "2022-06-11T22:51:45+01:00", "phase": "waxing_gibbous", "illumination": 0.871},
     });
 
     assert_eq!(json, expected);
@@ -176,10 +181,15 @@ fn test_correct_output_small_offset() {
         "date": "2022-07-29T12:00:00+01:00",
         "day_length": 59066,
         "solar_noon": "2022-07-29T13:26:55+01:00",
+        "solar_midnight": "2022-07-30T01:26:55+01:00",
+        "min_solar_elevation": -14.600672511432144,
         "sunrise": "2022-07-29T05:14:42+01:00",
         "sunset": "2022-07-29T21:39:08+01:00",
         "dawn": {"civil": "2022-07-29T04:23:01+01:00", "nautical": "2022-07-29T03:00:07+01:00", "astronomical": null},
         "dusk": {"civil": "2022-07-29T22:30:48+01:00", "nautical": "2022-07-29T23:53:43+01:00", "astronomical": null},
+        "golden_hour": {"morning": {"start": "2022-07-29T04:44:09+01:00", "end": "2022-07-29T06:13:17+01:00"}, "evening": {"start": "2022-07-29T20:40:32+01:00", "end": "2022-07-29T22:09:40+01:00"}},
+        "blue_hour": {"morning": {"start": "2022-07-29T04:23:01+01:00", "end": "2022-07-29T04:44:09+01:00"}, "evening": {"start": "2022-07-29T22:09:40+01:00", "end": "2022-07-29T22:30:48+01:00"}},
+        "moon": {"moonrise": "2022-07-29T05:18:00+01:00", "moonset": "2022-07-29T22:36:47+01:00", "transit": "2022-07-29T14:09:01+01:00", "phase": "waxing_crescent", "illumination": 0.007},
     });
 
     assert_eq!(json, expected);
@@ -213,15 +223,122 @@ fn test_correct_output_large_pos_offset() {
         "date": "2022-07-29T12:00:00+11:00",
         "day_length": 36606,
         "solar_noon": "2022-07-29T11:26:01+11:00",
+        "solar_midnight": "2022-07-29T23:26:01+11:00",
+        "min_solar_elevation": -71.6433230692661,
         "sunrise": "2022-07-29T06:20:58+11:00",
         "sunset": "2022-07-29T16:31:04+11:00",
         "dawn": {"civil": "2022-07-29T05:53:13+11:00", "nautical": "2022-07-29T05:21:48+11:00", "astronomical": "2022-07-29T04:51:00+11:00"},
         "dusk": {"civil": "2022-07-29T16:58:49+11:00", "nautical": "2022-07-29T17:30:14+11:00", "astronomical": "2022-07-29T18:01:02+11:00"},
+        "golden_hour": {"morning": {"start": "2022-07-29T06:03:52+11:00", "end": "2022-07-29T06:59:05+11:00"}, "evening": {"start": "2022-07-29T15:52:57+11:00", "end": "2022-07-29T16:48:10+11:00"}},
+        "blue_hour": {"morning": {"start": "2022-07-29T05:53:13+11:00", "end": "2022-07-29T06:03:52+11:00"}, "evening": {"start": "2022-07-29T16:48:10+11:00", "end": "2022-07-29T16:58:49+11:00"}},
+        "moon": {"moonrise": "2022-07-29T06:51:29+11:00", "moonset": "2022-07-29T16:42:08+11:00", "transit": "2022-07-29T11:44:25+11:00", "phase": "new_moon", "illumination": 0.003},
     });
 
     assert_eq!(json, expected);
 }
 
+#[test]
+fn test_correct_output_named_time_zone() {
+    // the clocks go back at 01:00 UTC, before sunrise, so every event on this date is in GMT (+00:00)
+    let output = get_base_command()
+        .args([
+            "--date",
+            "2022-10-30",
+            "--time-zone",
+            "Europe/London",
+            "--latitude",
+            "51.4769",
+            "--longitude",
+            "-0.0005",
+            "report",
+            "--json",
+        ])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+
+    let json: serde_json::Value = serde_json::from_slice(&output).unwrap();
+
+    assert_eq!(json["date"], "2022-10-30T12:00:00+00:00");
+    assert_eq!(json["sunrise"], "2022-10-30T06:50:04+00:00");
+    assert_eq!(json["sunset"], "2022-10-30T16:37:08+00:00");
+}
+
+#[test]
+fn test_invalid_time_zone() {
+    get_base_command()
+        .args(["--time-zone", "Europe/Narnia", "report"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("Invalid time zone"));
+}
+
+#[test]
+fn test_report_range_csv() {
+    // the rows follow the change to daylight saving time
+    let output = get_base_command()
+        .args(["--time-zone", "Europe/London", "report"])
+        .args([
+            "--from",
+            "2022-03-26",
+            "--to",
+            "2022-03-28",
+            "--format",
+            "csv",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let csv = String::from_utf8(output.stdout).unwrap();
+    let rows: Vec<_> = csv
+        .lines()
+        .map(|line| line.split(',').take(4).collect::<Vec<_>>().join(","))
+        .collect();
+    assert_eq!(
+        rows,
+        [
+            "date,day_length,sunrise,sunset",
+            "2022-03-26,45236,2022-03-26T05:48:40+00:00,2022-03-26T18:22:36+00:00",
+            "2022-03-27,45464,2022-03-27T06:46:29+01:00,2022-03-27T19:24:13+01:00",
+            "2022-03-28,45701,2022-03-28T06:44:12+01:00,2022-03-28T19:25:53+01:00",
+        ]
+    );
+}
+
+#[test]
+fn test_report_range_days() {
+    let output = get_base_command()
+        .args(["--date", "2022-06-11", "--time-zone", "+01:00", "report"])
+        .args(["--days", "3", "--format", "ndjson"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let dates: Vec<_> = String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["date"].clone())
+        .collect();
+    assert_eq!(dates, ["2022-06-11", "2022-06-12", "2022-06-13"]);
+
+    get_base_command()
+        .args(["report", "--to", "2022-06-12", "--days", "3"])
+        .assert()
+        .code(2);
+
+    // --to is limited to the same number of days as --days
+    get_base_command()
+        .args(["--date", "2022-06-11", "report", "--to", "2032-06-20"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "The range must not be longer than 3660 days. Found 3663 days",
+        ));
+}
+
 fn assert_report(report: Assert) {
     report
         .success()
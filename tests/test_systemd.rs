@@ -0,0 +1,109 @@
+use assert_cmd::prelude::*;
+
+mod common;
+use common::{heliocron, write_config};
+
+#[test]
+fn test_systemd_units() {
+    let dir = std::env::temp_dir().join(format!("heliocron-{}-systemd", std::process::id()));
+
+    let mut cmd = heliocron();
+    cmd.args(["systemd", "--name", "lights", "--event", "sunset"])
+        .args(["--offset", "-00:30", "--days", "3", "--output-dir"])
+        .arg(&dir)
+        .args(["--", "lights", "on"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(
+            "systemctl --user enable --now heliocron-lights.timer heliocron-lights-refresh.timer",
+        ));
+
+    let read = |file_name: &str| std::fs::read_to_string(dir.join(file_name)).unwrap();
+
+    assert!(read("heliocron-lights.service").contains("\nExecStart=lights on\n"));
+    assert!(read("heliocron-lights.timer").contains(
+        "\nOnCalendar=2022-06-11 19:46:12 UTC\n\
+        OnCalendar=2022-06-12 19:46:52 UTC\n\
+        OnCalendar=2022-06-13 19:47:30 UTC\n"
+    ));
+    assert!(read("heliocron-lights-refresh.timer").contains("\nOnCalendar=daily\n"));
+
+    // the refresh service regenerates the units from the current time, rather than the simulated one
+    let refresh = read("heliocron-lights-refresh.service");
+    assert!(refresh.contains(&format!(
+        " systemd --name lights --event sunset --offset -00:30:00 --days 3 --output-dir {} -- lights on\n",
+        dir.display()
+    )));
+    assert!(!refresh.contains("--now"));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_systemd_regenerate_from_parsed_args() {
+    let dir = std::env::temp_dir().join(format!("heliocron-{}-systemd-parsed", std::process::id()));
+    let config = write_config(
+        "systemd-parsed",
+        "[locations.systemd]\nlatitude = 51.4769\nlongitude = -0.0005\n",
+    );
+
+    // a location named after the subcommand, and options after it which look like --date or --now, are passed on as
+    // they were parsed
+    let mut cmd = heliocron();
+    cmd.env("XDG_CONFIG_HOME", &config)
+        .args(["--location", "systemd", "-t", "Europe/London", "systemd"])
+        .args([
+            "--name",
+            "lights",
+            "--event",
+            "custom_pm",
+            "--altitude",
+            "8.5",
+        ])
+        .args(["--days", "1", "--output-dir"])
+        .arg(&dir)
+        .args(["--", "lights", "--date", "--now"])
+        .assert()
+        .success();
+
+    let refresh = std::fs::read_to_string(dir.join("heliocron-lights-refresh.service")).unwrap();
+    assert!(refresh.contains(&format!(
+        " --location systemd --time-zone Europe/London systemd --name lights --event custom_pm --altitude 8.5 \
+        --days 1 --output-dir {} -- lights --date --now\n",
+        dir.display()
+    )));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_systemd_event_does_not_occur() {
+    let dir = std::env::temp_dir().join(format!("heliocron-{}-systemd-70", std::process::id()));
+
+    // the Sun doesn't set at 70 degrees north in June
+    let mut cmd = heliocron();
+    cmd.args(["-l", "70", "-o", "0", "systemd", "--name", "sun"])
+        .args(["--event", "sunset", "--days", "1", "--output-dir"])
+        .arg(&dir)
+        .args(["--", "lights", "on"])
+        .assert()
+        .success();
+
+    let timer = std::fs::read_to_string(dir.join("heliocron-sun.timer")).unwrap();
+    assert!(timer.contains("\n# 2022-06-11: the event does not occur on this day\n"));
+    assert!(!timer.contains("OnCalendar="));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_systemd_invalid_name() {
+    let mut cmd = heliocron();
+    cmd.args(["systemd", "--name", "porch lights", "--event", "sunset"])
+        .args(["--", "lights", "on"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "Expected a name made up of letters, digits and any of '_.:-'",
+        ));
+}
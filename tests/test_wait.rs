@@ -1,15 +1,12 @@
-#![cfg(feature = "integration-test")]
-use std::process::Command;
-
 use assert_cmd::prelude::*;
 
-// run these tests with `cargo test --test test_wait --features integration-test` in order to
-// override the default sleep function
+mod common;
+use common::heliocron;
 
 #[test]
 fn test_wait_non_custom_events() {
     // assert that the following events are all supported (excluding custom events)
-    let non_custom_events = &[
+    let non_custom_events = [
         "sunrise",
         "sunset",
         "civil_dawn",
@@ -19,11 +16,22 @@ fn test_wait_non_custom_events() {
         "astronomical_dawn",
         "astronomical_dusk",
         "solar_noon",
+        "solar_midnight",
+        "morning_blue_hour_start",
+        "morning_blue_hour_end",
+        "morning_golden_hour_start",
+        "morning_golden_hour_end",
+        "evening_golden_hour_start",
+        "evening_golden_hour_end",
+        "evening_blue_hour_start",
+        "evening_blue_hour_end",
+        "moonrise",
+        "moonset",
     ];
 
     for event in non_custom_events.iter() {
-        let mut cmd = Command::cargo_bin("heliocron").unwrap();
-        cmd.args(&["-d", "2099-12-30", "wait", "--event", event])
+        let mut cmd = heliocron();
+        cmd.args(["-d", "2099-12-30", "wait", "--event", event])
             .assert()
             .success()
             .stdout(predicates::str::contains("going to sleep for"));
@@ -33,11 +41,11 @@ fn test_wait_non_custom_events() {
 #[test]
 fn test_wait_custom_events() {
     // assert that the following custome events are supported
-    let custom_events = &["custom_am", "custom_pm"];
+    let custom_events = ["custom_am", "custom_pm"];
 
     for event in custom_events.iter() {
-        let mut cmd = Command::cargo_bin("heliocron").unwrap();
-        cmd.args(&[
+        let mut cmd = heliocron();
+        cmd.args([
             "-d",
             "2099-12-30",
             "wait",
@@ -55,8 +63,8 @@ fn test_wait_custom_events() {
 #[test]
 fn test_wait_requires_an_event() {
     // assert that the command fails when no event is provided
-    let mut cmd = Command::cargo_bin("heliocron").unwrap();
-    cmd.args(&["wait"])
+    let mut cmd = heliocron();
+    cmd.args(["wait"])
         .assert()
         .failure()
         .stderr(predicates::str::contains("required"))
@@ -66,13 +74,13 @@ fn test_wait_requires_an_event() {
 #[test]
 fn test_wait_custom_altitude_positive_or_negative() {
     // assert that --altitude can be positve or negative
-    let mut cmd = Command::cargo_bin("heliocron").unwrap();
-    cmd.args(&["-d", "2099-12-30", "wait", "-e", "custom_am", "-a", "-6.3"])
+    let mut cmd = heliocron();
+    cmd.args(["-d", "2099-12-30", "wait", "-e", "custom_am", "-a", "-6.3"])
         .assert()
         .success();
 
-    let mut cmd = Command::cargo_bin("heliocron").unwrap();
-    cmd.args(&["-d", "2099-12-30", "wait", "-e", "custom_am", "-a", "6.3"])
+    let mut cmd = heliocron();
+    cmd.args(["-d", "2099-12-30", "wait", "-e", "custom_am", "-a", "6.3"])
         .assert()
         .success();
 }
@@ -80,32 +88,32 @@ fn test_wait_custom_altitude_positive_or_negative() {
 #[test]
 fn test_wait_custom_altitude_range() {
     // assert that --altitude must be <= 90 and >= -90
-    let mut cmd = Command::cargo_bin("heliocron").unwrap();
-    cmd.args(&["-d", "2099-12-30", "wait", "-e", "custom_am", "-a", "-90.0"])
+    let mut cmd = heliocron();
+    cmd.args(["-d", "2099-12-30", "wait", "-e", "custom_am", "-a", "-90.0"])
         .assert()
         .failure()
         .stderr(predicates::str::contains(
             "The chosen event does not occur on this day",
         ));
 
-    let mut cmd = Command::cargo_bin("heliocron").unwrap();
-    cmd.args(&["-d", "2099-12-30", "wait", "-e", "custom_am", "-a", "-90.1"])
+    let mut cmd = heliocron();
+    cmd.args(["-d", "2099-12-30", "wait", "-e", "custom_am", "-a", "-90.1"])
         .assert()
         .failure()
         .stderr(predicates::str::contains(
             "Expected a number between -90.0 and 90.0",
         ));
 
-    let mut cmd = Command::cargo_bin("heliocron").unwrap();
-    cmd.args(&["-d", "2099-12-30", "wait", "-e", "custom_am", "-a", "90.0"])
+    let mut cmd = heliocron();
+    cmd.args(["-d", "2099-12-30", "wait", "-e", "custom_am", "-a", "90.0"])
         .assert()
         .failure()
         .stderr(predicates::str::contains(
             "The chosen event does not occur on this day",
         ));
 
-    let mut cmd = Command::cargo_bin("heliocron").unwrap();
-    cmd.args(&["-d", "2099-12-30", "wait", "-e", "custom_am", "-a", "90.1"])
+    let mut cmd = heliocron();
+    cmd.args(["-d", "2099-12-30", "wait", "-e", "custom_am", "-a", "90.1"])
         .assert()
         .failure()
         .stderr(predicates::str::contains(
@@ -115,8 +123,8 @@ fn test_wait_custom_altitude_range() {
 
 #[test]
 fn test_custom_altitude_must_be_float() {
-    let mut cmd = Command::cargo_bin("heliocron").unwrap();
-    cmd.args(&[
+    let mut cmd = heliocron();
+    cmd.args([
         "-d",
         "2099-12-30",
         "wait",
@@ -135,9 +143,9 @@ fn test_custom_altitude_must_be_float() {
 #[test]
 fn test_altitude_ignored_for_non_custom_events() {
     // assert that --altitude is ignored for non-custom events
-    let mut cmd = Command::cargo_bin("heliocron").unwrap();
+    let mut cmd = heliocron();
 
-    cmd.args(&[
+    cmd.args([
         "-l",
         "51.4769",
         "-o",
@@ -160,9 +168,9 @@ fn test_altitude_ignored_for_non_custom_events() {
 #[test]
 fn test_custom_am_event_correctness() {
     // assert that --altitude is ignored for non-custom events
-    let mut cmd = Command::cargo_bin("heliocron").unwrap();
+    let mut cmd = heliocron();
 
-    cmd.args(&[
+    cmd.args([
         "-l",
         "51.4769",
         "-o",
@@ -185,9 +193,9 @@ fn test_custom_am_event_correctness() {
 #[test]
 fn test_custom_pm_event_correctness() {
     // assert that --altitude is ignored for non-custom events
-    let mut cmd = Command::cargo_bin("heliocron").unwrap();
+    let mut cmd = heliocron();
 
-    cmd.args(&[
+    cmd.args([
         "-l",
         "51.4769",
         "-o",
@@ -210,9 +218,9 @@ fn test_custom_pm_event_correctness() {
 #[test]
 fn test_wait_custom_event_requires_custom_altitude() {
     // assert that --altitude is a required argument when using custom events
-    let mut cmd = Command::cargo_bin("heliocron").unwrap();
+    let mut cmd = heliocron();
 
-    let wait = cmd.args(&["wait", "--event", "custom_pm"]).assert();
+    let wait = cmd.args(["wait", "--event", "custom_pm"]).assert();
 
     wait.failure()
         .stderr(predicates::str::contains(
@@ -224,8 +232,8 @@ fn test_wait_custom_event_requires_custom_altitude() {
 #[test]
 fn test_wait_errors_with_event_non_occurrence() {
     // assert that the correct error is displayed when a given event doees not occur
-    let mut cmd = Command::cargo_bin("heliocron").unwrap();
-    cmd.args(&[
+    let mut cmd = heliocron();
+    cmd.args([
         "-d",
         "2099-06-21",
         "-t",
@@ -244,10 +252,10 @@ fn test_wait_errors_with_event_non_occurrence() {
 #[test]
 fn test_wait_no_offset() {
     // assert that the heliocron will put the thread to sleep
-    let mut cmd = Command::cargo_bin("heliocron").unwrap();
+    let mut cmd = heliocron();
 
     let wait_long = cmd
-        .args(&[
+        .args([
             "-d",
             "2099-12-31",
             "-t",
@@ -263,9 +271,9 @@ fn test_wait_no_offset() {
         .stdout(predicates::str::contains("going to sleep for"))
         .stdout(predicates::str::contains("2099-12-31 16:00:33 +00:00"));
 
-    let mut cmd = Command::cargo_bin("heliocron").unwrap();
+    let mut cmd = heliocron();
     let wait_short = cmd
-        .args(&["-d", "2091-10-05", "-t", "+00:00", "wait", "-e", "sunrise"])
+        .args(["-d", "2091-10-05", "-t", "+00:00", "wait", "-e", "sunrise"])
         .assert();
 
     wait_short
@@ -277,9 +285,9 @@ fn test_wait_no_offset() {
 #[test]
 fn test_wait_with_offset() {
     // assert that the heliocron will put the thread to sleep
-    let mut cmd = Command::cargo_bin("heliocron").unwrap();
+    let mut cmd = heliocron();
     let wait_long = cmd
-        .args(&[
+        .args([
             "-d",
             "2099-12-31",
             "-t",
@@ -297,9 +305,9 @@ fn test_wait_with_offset() {
         .stdout(predicates::str::contains("going to sleep for"))
         .stdout(predicates::str::contains("2099-12-31 17:00:33 +00:00"));
 
-    let mut cmd = Command::cargo_bin("heliocron").unwrap();
+    let mut cmd = heliocron();
     let wait_short = cmd
-        .args(&[
+        .args([
             "-d",
             "2091-10-05",
             "-t",
@@ -320,8 +328,8 @@ fn test_wait_with_offset() {
 
 #[test]
 fn test_offset_parse_error() {
-    let mut cmd = Command::cargo_bin("heliocron").unwrap();
-    cmd.args(&[
+    let mut cmd = heliocron();
+    cmd.args([
         "-d",
         "2091-10-05",
         "-t",
@@ -341,9 +349,9 @@ fn test_offset_parse_error() {
 
 #[test]
 fn test_tag_is_allowed() {
-    let mut cmd = Command::cargo_bin("heliocron").unwrap();
+    let mut cmd = heliocron();
     let wait_short = cmd
-        .args(&[
+        .args([
             "-d",
             "2091-10-05",
             "-t",
@@ -363,3 +371,368 @@ fn test_tag_is_allowed() {
         .stdout(predicates::str::contains("going to sleep for"))
         .stdout(predicates::str::contains("2091-10-04 17:37:02 +00:00"));
 }
+
+#[test]
+fn test_wait_runs_command() {
+    let mut cmd = heliocron();
+    cmd.args([
+        "-d",
+        "2099-12-30",
+        "wait",
+        "-e",
+        "sunrise",
+        "--",
+        "sh",
+        "-c",
+        "echo hello from the command",
+    ])
+    .assert()
+    .success()
+    .stdout(predicates::str::contains("hello from the command"));
+}
+
+#[test]
+fn test_wait_exit_codes() {
+    // assert that each kind of failure is distinguishable by the exit code
+    let mut cmd = heliocron();
+    cmd.args(["-d", "2000-01-01", "wait", "-e", "sunrise", "--", "true"])
+        .assert()
+        .code(3);
+
+    let mut cmd = heliocron();
+    cmd.args([
+        "-d",
+        "2099-06-21",
+        "-t",
+        "+00:00",
+        "wait",
+        "-e",
+        "astronomical_dusk",
+        "--",
+        "true",
+    ])
+    .assert()
+    .code(4);
+
+    let mut cmd = heliocron();
+    cmd.args(["-d", "2099-12-30", "wait", "-e", "sunrise", "--", "false"])
+        .assert()
+        .code(5)
+        .stderr(predicates::str::contains("The command failed"));
+
+    let mut cmd = heliocron();
+    cmd.args([
+        "-d",
+        "2099-12-30",
+        "wait",
+        "-e",
+        "sunrise",
+        "--timeout",
+        "00:00:01",
+        "--",
+        "sleep",
+        "10",
+    ])
+    .assert()
+    .code(6)
+    .stderr(predicates::str::contains("The command timed out after 1s"));
+}
+
+#[test]
+fn test_wait_timeout_requires_command() {
+    let mut cmd = heliocron();
+    cmd.args([
+        "-d",
+        "2099-12-30",
+        "wait",
+        "-e",
+        "sunrise",
+        "--timeout",
+        "00:01",
+    ])
+    .assert()
+    .failure()
+    .stderr(predicates::str::contains("<COMMAND>"));
+}
+
+#[test]
+fn test_wait_next_skips_non_occurring_days() {
+    // assert that --next searches forward to the end of the polar night
+    let mut cmd = heliocron();
+    cmd.args([
+        "-l",
+        "78.2232",
+        "-o",
+        "15.6267",
+        "-d",
+        "2099-11-20",
+        "-t",
+        "+01:00",
+        "wait",
+        "-e",
+        "sunrise",
+        "--next",
+    ])
+    .assert()
+    .success()
+    .stdout(predicates::str::contains(
+        "The next occurrence of the event is on 2100-02-15.",
+    ))
+    .stdout(predicates::str::contains(
+        "until 2100-02-15 11:52:16 +01:00",
+    ));
+}
+
+#[test]
+fn test_wait_next_horizon() {
+    // assert that --next gives up once the horizon is reached
+    let mut cmd = heliocron();
+    cmd.args([
+        "-d",
+        "2000-01-01",
+        "wait",
+        "-e",
+        "sunset",
+        "--next",
+        "--horizon",
+        "100",
+    ])
+    .assert()
+    .code(4)
+    .stderr(predicates::str::contains(
+        "does not occur again within the next 100 days",
+    ));
+}
+
+#[test]
+fn test_wait_elevation_threshold_conflicts() {
+    // assert that only one of an event or an elevation threshold can be chosen
+    let mut cmd = heliocron();
+    cmd.args(["wait", "--event", "sunset", "--elevation-below", "-3.5"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("cannot be used with"));
+
+    let mut cmd = heliocron();
+    cmd.args(["wait", "--elevation-below", "5", "--elevation-above", "10"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("cannot be used with"));
+
+    let mut cmd = heliocron();
+    cmd.args(["wait", "--event", "sunset", "--immediate"])
+        .assert()
+        .failure();
+
+    // the offset, --next and --horizon only apply to events, so they aren't silently ignored
+    let params: [&[&str]; 3] = [&["--offset", "01:00"], &["--next"], &["--horizon", "5"]];
+    for args in params {
+        let mut cmd = heliocron();
+        cmd.args(["wait", "--elevation-below", "-3"])
+            .args(args)
+            .assert()
+            .code(2)
+            .stderr(predicates::str::contains("cannot be used with"));
+    }
+}
+
+#[test]
+fn test_wait_elevation_never_crossed() {
+    // assert that a threshold which is never crossed is reported as a non-occurring event
+    let mut cmd = heliocron();
+    cmd.args(["-l", "89.0", "-o", "0.0", "wait", "--elevation-above", "60"])
+        .assert()
+        .code(4);
+}
+
+#[test]
+fn test_wait_audit_log() {
+    let audit_log = std::env::temp_dir().join(format!("heliocron-{}-audit", std::process::id()));
+
+    for policy in ["skip", "run-if-within-grace"] {
+        let mut cmd = heliocron();
+        cmd.args([
+            "-d",
+            "2099-12-30",
+            "-t",
+            "+00:00",
+            "wait",
+            "-e",
+            "sunrise",
+            "--missed-event",
+            policy,
+            "--grace",
+            "00:01:30",
+            "--audit-log",
+        ])
+        .arg(&audit_log)
+        .assert()
+        .success();
+    }
+
+    // the simulated wait ends exactly on time, so the task is run, and one record is appended per wait
+    let records = std::fs::read_to_string(&audit_log).unwrap();
+    let records: Vec<serde_json::Value> = records
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0]["scheduled_for"], "2099-12-30T08:05:21+00:00");
+    assert_eq!(records[0]["policy"], "skip");
+    assert_eq!(records[1]["policy"], "run-if-within-grace");
+    assert_eq!(records[1]["grace"], 90);
+    assert_eq!(records[1]["run"], true);
+    assert_eq!(records[1]["woke_at"], "2099-12-30T08:05:21+00:00");
+    assert_eq!(records[1]["late_by"], 0);
+
+    std::fs::remove_file(audit_log).unwrap();
+}
+
+#[test]
+fn test_wait_missed_event_policy_conflicts() {
+    let mut cmd = heliocron();
+    cmd.args([
+        "-d",
+        "2099-12-30",
+        "wait",
+        "-e",
+        "sunrise",
+        "--run-missed-event",
+        "--missed-event",
+        "skip",
+    ])
+    .assert()
+    .failure()
+    .code(2)
+    .stderr(predicates::str::contains("cannot be used with"));
+}
+
+#[test]
+fn test_wait_elevation_threshold() {
+    // the simulated time is midday, when the Sun is well above the horizon
+    let location = ["-l", "51.4769", "-o", "-0.0005"];
+
+    let mut cmd = heliocron();
+    cmd.args(location)
+        .args(["wait", "--elevation-below", "-3.5"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(
+            "until 2022-06-11 21:40:40 +01:00",
+        ));
+
+    // the threshold is already met, so either return straight away or wait until it is next crossed
+    let mut cmd = heliocron();
+    cmd.args(location)
+        .args(["wait", "--elevation-above", "10", "--immediate"])
+        .assert()
+        .success()
+        .stdout("");
+
+    let mut cmd = heliocron();
+    cmd.args(location)
+        .args(["wait", "--elevation-above", "10"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(
+            "until 2022-06-12 06:05:07 +01:00",
+        ));
+}
+
+#[test]
+fn test_wait_clamp() {
+    // sunset is at 21:16:12 on this date, and the clamps are applied after the offset
+    let params = [
+        (
+            ["--not-before", "17:00", "--not-after", "21:30"],
+            "00:00",
+            "21:16:12",
+        ),
+        (
+            ["--not-before", "17:00", "--not-after", "21:30"],
+            "00:30",
+            "21:30:00",
+        ),
+        (
+            ["--not-before", "21:20", "--not-after", "21:30"],
+            "00:00",
+            "21:20:00",
+        ),
+    ];
+
+    for (clamp, offset, expected) in params {
+        let mut cmd = heliocron();
+        cmd.args(["wait", "--event", "sunset", "--offset", offset])
+            .args(clamp)
+            .assert()
+            .success()
+            .stdout(predicates::str::contains(format!(
+                "until 2022-06-11 {expected} +01:00"
+            )));
+    }
+
+    let mut cmd = heliocron();
+    cmd.args(["wait", "--event", "sunset", "--not-after", "21:00"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(
+            "The event (+/- offset) at 2022-06-11 21:16:12 +01:00 has been clamped to 2022-06-11 21:00:00 +01:00.",
+        ));
+}
+
+#[test]
+fn test_wait_clamp_invalid() {
+    let mut cmd = heliocron();
+    cmd.args([
+        "wait",
+        "--event",
+        "sunset",
+        "--not-before",
+        "21:30",
+        "--not-after",
+        "17:00",
+    ])
+    .assert()
+    .failure()
+    .stderr(predicates::str::contains("Invalid clamp"));
+
+    let mut cmd = heliocron();
+    cmd.args(["wait", "--elevation-below", "-3.5", "--not-after", "17:00"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("cannot be used with"));
+}
+
+#[test]
+fn test_wait_jitter() {
+    // sunset is at 21:16:12 on this date, and the same seed always gives the same shift
+    for _ in 0..2 {
+        let mut cmd = heliocron();
+        cmd.args(["wait", "--event", "sunset", "--jitter", "00:15"])
+            .args(["--seed", "2022-06-11-porch"])
+            .assert()
+            .success()
+            .stdout(predicates::str::contains(
+                "Shifting the event by +00:08:26 at random.",
+            ))
+            .stdout(predicates::str::contains(
+                "until 2022-06-11 21:24:38 +01:00",
+            ));
+    }
+
+    // the shift is applied before the clamp
+    let mut cmd = heliocron();
+    cmd.args(["wait", "--event", "sunset", "--jitter", "00:15"])
+        .args(["--seed", "2022-06-11-porch", "--not-after", "21:20"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(
+            "until 2022-06-11 21:20:00 +01:00",
+        ));
+
+    let mut cmd = heliocron();
+    cmd.args(["wait", "--event", "sunset", "--seed", "2022-06-11-porch"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("--jitter <JITTER>"));
+}